@@ -0,0 +1,200 @@
+use crate::db::DBError;
+use arkycore::types::Value;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Aggregate computed by `QueryExecutor::aggregate`/`aggregate_edges`. Null
+/// and missing properties are skipped, so only `Count` counts them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Aggregate {
+    Count,
+    Sum(String),
+    Avg(String),
+    Min(String),
+    Max(String),
+    /// Distinct values of the property, sorted.
+    Distinct(String),
+}
+impl Aggregate {
+    pub fn sum(prop: &str) -> Self {
+        Self::Sum(prop.to_string())
+    }
+    pub fn avg(prop: &str) -> Self {
+        Self::Avg(prop.to_string())
+    }
+    pub fn min(prop: &str) -> Self {
+        Self::Min(prop.to_string())
+    }
+    pub fn max(prop: &str) -> Self {
+        Self::Max(prop.to_string())
+    }
+    pub fn distinct(prop: &str) -> Self {
+        Self::Distinct(prop.to_string())
+    }
+    fn prop(&self) -> Option<&str> {
+        match self {
+            Self::Count => None,
+            Self::Sum(prop)
+            | Self::Avg(prop)
+            | Self::Min(prop)
+            | Self::Max(prop)
+            | Self::Distinct(prop) => Some(prop),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AggregateRow {
+    /// Value of the `group_by` property, `Value::Null` without grouping.
+    pub group: Value,
+    pub count: usize,
+    /// Results of the aggregates, in the order they were asked for.
+    pub values: Vec<Value>,
+}
+
+enum State {
+    Count,
+    Sum {
+        int: i128,
+        float: f64,
+        is_float: bool,
+    },
+    Avg {
+        sum: f64,
+        count: usize,
+    },
+    Min(Option<Value>),
+    Max(Option<Value>),
+    Distinct(BTreeSet<Value>),
+}
+impl State {
+    fn new(aggregate: &Aggregate) -> Self {
+        match aggregate {
+            Aggregate::Count => Self::Count,
+            Aggregate::Sum(_) => Self::Sum {
+                int: 0,
+                float: 0.0,
+                is_float: false,
+            },
+            Aggregate::Avg(_) => Self::Avg { sum: 0.0, count: 0 },
+            Aggregate::Min(_) => Self::Min(None),
+            Aggregate::Max(_) => Self::Max(None),
+            Aggregate::Distinct(_) => Self::Distinct(BTreeSet::new()),
+        }
+    }
+    fn add(&mut self, prop: &str, value: &Value) -> Result<(), DBError> {
+        let not_number = || DBError::QueryError {
+            error: format!("{} is not a number: {}", prop, value),
+        };
+        match self {
+            Self::Count => {}
+            Self::Sum {
+                int,
+                float,
+                is_float,
+            } => match value {
+                Value::Int(n) => *int += *n as i128,
+                Value::UInt(n) => *int += *n as i128,
+                Value::Float(n) => {
+                    *float += n;
+                    *is_float = true;
+                }
+                _ => return Err(not_number()),
+            },
+            Self::Avg { sum, count } => {
+                *sum += value.as_f64().ok_or_else(not_number)?;
+                *count += 1;
+            }
+            Self::Min(min) => {
+                if min.as_ref().is_none_or(|min| value < min) {
+                    *min = Some(value.clone());
+                }
+            }
+            Self::Max(max) => {
+                if max.as_ref().is_none_or(|max| value > max) {
+                    *max = Some(value.clone());
+                }
+            }
+            Self::Distinct(values) => {
+                values.insert(value.clone());
+            }
+        }
+        Ok(())
+    }
+    fn finish(self, count: usize) -> Value {
+        match self {
+            Self::Count => Value::UInt(count as u64),
+            Self::Sum {
+                int,
+                float,
+                is_float,
+            } => match (is_float, i64::try_from(int), u64::try_from(int)) {
+                (false, Ok(int), _) => Value::Int(int),
+                (false, _, Ok(int)) => Value::UInt(int),
+                _ => Value::Float(int as f64 + float),
+            },
+            Self::Avg { count: 0, .. } => Value::Null,
+            Self::Avg { sum, count } => Value::Float(sum / count as f64),
+            Self::Min(value) | Self::Max(value) => value.unwrap_or(Value::Null),
+            Self::Distinct(values) => Value::List(values.into_iter().collect()),
+        }
+    }
+}
+
+/// Folds items, as `Value` maps, into one row per distinct value of the
+/// `group` property.
+pub(crate) struct Aggregator<'a> {
+    group: Option<&'a str>,
+    aggregates: &'a [Aggregate],
+    groups: BTreeMap<Value, (usize, Vec<State>)>,
+}
+impl<'a> Aggregator<'a> {
+    pub(crate) fn new(group: Option<&'a str>, aggregates: &'a [Aggregate]) -> Self {
+        Self {
+            group,
+            aggregates,
+            groups: BTreeMap::new(),
+        }
+    }
+    pub(crate) fn add(&mut self, item: &Value) -> Result<(), DBError> {
+        let key = self
+            .group
+            .and_then(|group| item.get(group))
+            .cloned()
+            .unwrap_or(Value::Null);
+        let (count, states) = self.groups.entry(key).or_insert_with(|| {
+            let states = self.aggregates.iter().map(State::new).collect();
+            (0, states)
+        });
+
+        *count += 1;
+        for (aggregate, state) in self.aggregates.iter().zip(states) {
+            let Some(prop) = aggregate.prop() else {
+                continue;
+            };
+            match item.get(prop) {
+                None | Some(Value::Null) => {}
+                Some(value) => state.add(prop, value)?,
+            }
+        }
+        Ok(())
+    }
+    /// Rows sorted by group. Without grouping there's always one row, even
+    /// when nothing was added.
+    pub(crate) fn rows(mut self) -> Vec<AggregateRow> {
+        if self.group.is_none() && self.groups.is_empty() {
+            let states = self.aggregates.iter().map(State::new).collect();
+            self.groups.insert(Value::Null, (0, states));
+        }
+        self.groups
+            .into_iter()
+            .map(|(group, (count, states))| AggregateRow {
+                group,
+                count,
+                values: states
+                    .into_iter()
+                    .map(|state| state.finish(count))
+                    .collect(),
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,84 @@
+use crate::db::DBError;
+use arkycore::types::{NodeID, Value};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Opaque position in the results of a query, handed out with each `Page`.
+/// It's a plain string, so it can go to a client and come back with a later
+/// request to resume the same query with `QueryExecutor::after`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Cursor(String);
+impl Cursor {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+impl fmt::Display for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+impl FromStr for Cursor {
+    type Err = DBError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Position::decode(text)?;
+        Ok(Self(text.to_string()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page<T> {
+    pub nodes: Vec<T>,
+    /// Where the next page starts, `None` on the last one.
+    pub next: Option<Cursor>,
+}
+
+/// What a cursor holds: the query it belongs to and the last node seen,
+/// with its entity and its value of the sort property.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Position {
+    pub(crate) query: u64,
+    pub(crate) id: NodeID,
+    pub(crate) entity: String,
+    pub(crate) value: Option<Value>,
+}
+impl Position {
+    pub(crate) fn to_cursor(&self) -> Cursor {
+        let bytes = bincode::serialize(self).unwrap_or_default();
+        Cursor(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+    }
+    /// Position of `cursor`, which must have been made by the `query`.
+    pub(crate) fn from_cursor(cursor: &Cursor, query: u64) -> Result<Self, DBError> {
+        let position = Self::decode(&cursor.0)?;
+        if position.query != query {
+            return Err(DBError::QueryError {
+                error: "cursor was made by another query".to_string(),
+            });
+        }
+        Ok(position)
+    }
+    fn decode(text: &str) -> Result<Self, DBError> {
+        let invalid = || DBError::QueryError {
+            error: format!("invalid cursor: {}", text),
+        };
+        let bytes = (0..text.len())
+            .step_by(2)
+            .map(|i| {
+                text.get(i..i + 2)
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            })
+            .collect::<Option<Vec<u8>>>()
+            .ok_or_else(invalid)?;
+        bincode::deserialize(&bytes).map_err(|_| invalid())
+    }
+}
+
+/// FNV-1a hash of the description of a query, stable across processes so
+/// cursors outlive the server that made them.
+pub(crate) fn fingerprint(query: &str) -> u64 {
+    query.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
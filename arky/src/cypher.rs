@@ -0,0 +1,766 @@
+use crate::{
+    db::{DBError, DB},
+    edge::EdgeItem,
+    node::Node,
+    query::{entity_name, QueryBuilder, QueryExecutor, QueryOperation},
+};
+use arkycore::types::{NodeID, Value};
+use futures::future::BoxFuture;
+use futures::TryStreamExt;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::Bound;
+use std::sync::Arc;
+
+/// Parsed `MATCH ... [WHERE ...] RETURN ... [ORDER BY ...] [SKIP n] [LIMIT n]`
+/// statement.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Statement {
+    pub start: NodePattern,
+    pub hops: Vec<(RelPattern, NodePattern)>,
+    pub filter: Option<Expr>,
+    pub returns: Vec<ReturnItem>,
+    pub order: Vec<(Operand, bool)>,
+    pub skip: usize,
+    pub limit: Option<usize>,
+}
+
+/// `(var:Label {prop: literal})`, every part being optional.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NodePattern {
+    pub var: Option<String>,
+    pub label: Option<String>,
+    pub props: Vec<(String, Value)>,
+}
+
+/// `-[:label]->`, `<-[:label]-` or `-[:label]-`, the label being optional.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelPattern {
+    pub label: Option<String>,
+    pub direction: RelDirection,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelDirection {
+    Out,
+    In,
+    Both,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operand {
+    Literal(Value),
+    Var(String),
+    Prop(String, String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Compare(Operand, CompareOp, Operand),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+}
+
+/// Returned column, named by its alias or its text, like `c.name`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReturnItem {
+    pub operand: Operand,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Int(i64),
+    Float(f64),
+    Symbol(&'static str),
+}
+
+const SYMBOLS: [&str; 16] = [
+    "<>", "<=", ">=", "(", ")", "[", "]", "{", "}", ":", ",", ".", "-", "<", ">", "=",
+];
+
+fn syntax_error(position: usize, message: &str) -> DBError {
+    DBError::QueryError {
+        error: format!("syntax error at {}: {}", position, message),
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<(usize, Token)>, DBError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some(&(position, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_alphabetic() || c == '_' {
+            let mut ident = String::new();
+            while let Some(&(_, c)) = chars
+                .peek()
+                .filter(|(_, c)| c.is_alphanumeric() || *c == '_')
+            {
+                ident.push(c);
+                chars.next();
+            }
+            tokens.push((position, Token::Ident(ident)));
+        } else if c.is_ascii_digit() {
+            let mut number = String::new();
+            while let Some(&(_, c)) = chars
+                .peek()
+                .filter(|(_, c)| c.is_ascii_digit() || *c == '.')
+            {
+                number.push(c);
+                chars.next();
+            }
+            let token = match number.parse() {
+                Ok(n) => Token::Int(n),
+                Err(_) => Token::Float(
+                    number
+                        .parse()
+                        .map_err(|_| syntax_error(position, "invalid number"))?,
+                ),
+            };
+            tokens.push((position, token));
+        } else if c == '\'' || c == '"' {
+            chars.next();
+            let mut string = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, c)) => string.push(c),
+                        None => return Err(syntax_error(position, "unterminated string")),
+                    },
+                    Some((_, end)) if end == c => break,
+                    Some((_, c)) => string.push(c),
+                    None => return Err(syntax_error(position, "unterminated string")),
+                }
+            }
+            tokens.push((position, Token::Str(string)));
+        } else {
+            let symbol = SYMBOLS
+                .iter()
+                .find(|symbol| text[position..].starts_with(*symbol))
+                .ok_or_else(|| syntax_error(position, &format!("unexpected `{}`", c)))?;
+            for _ in 0..symbol.len() {
+                chars.next();
+            }
+            tokens.push((position, Token::Symbol(symbol)));
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    end: usize,
+}
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, token)| token)
+    }
+    fn error(&self, message: &str) -> DBError {
+        let position = self.tokens.get(self.pos).map_or(self.end, |(pos, _)| *pos);
+        syntax_error(position, message)
+    }
+    fn symbol(&mut self, symbol: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol);
+        self.pos += found as usize;
+        found
+    }
+    fn expect_symbol(&mut self, symbol: &str) -> Result<(), DBError> {
+        if self.symbol(symbol) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected `{}`", symbol)))
+        }
+    }
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let found =
+            matches!(self.peek(), Some(Token::Ident(ident)) if ident.eq_ignore_ascii_case(keyword));
+        self.pos += found as usize;
+        found
+    }
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), DBError> {
+        if self.keyword(keyword) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected {}", keyword)))
+        }
+    }
+    fn ident(&mut self) -> Result<String, DBError> {
+        match self.peek() {
+            Some(Token::Ident(ident)) => {
+                let ident = ident.clone();
+                self.pos += 1;
+                Ok(ident)
+            }
+            _ => Err(self.error("expected a name")),
+        }
+    }
+    fn int(&mut self) -> Result<usize, DBError> {
+        match self.peek() {
+            Some(Token::Int(n)) if *n >= 0 => {
+                let n = *n as usize;
+                self.pos += 1;
+                Ok(n)
+            }
+            _ => Err(self.error("expected a positive integer")),
+        }
+    }
+
+    fn statement(&mut self) -> Result<Statement, DBError> {
+        self.expect_keyword("MATCH")?;
+        let start = self.node()?;
+        let mut hops = Vec::new();
+        while matches!(self.peek(), Some(Token::Symbol("-" | "<"))) {
+            let rel = self.rel()?;
+            hops.push((rel, self.node()?));
+        }
+        let filter = if self.keyword("WHERE") {
+            Some(self.or()?)
+        } else {
+            None
+        };
+
+        self.expect_keyword("RETURN")?;
+        let mut returns = Vec::new();
+        loop {
+            let start = self.pos;
+            let operand = self.reference()?;
+            let name = if self.keyword("AS") {
+                self.ident()?
+            } else {
+                self.tokens[start..self.pos]
+                    .iter()
+                    .map(|(_, token)| match token {
+                        Token::Ident(ident) => ident.as_str(),
+                        _ => ".",
+                    })
+                    .collect()
+            };
+            returns.push(ReturnItem { operand, name });
+            if !self.symbol(",") {
+                break;
+            }
+        }
+
+        let mut order = Vec::new();
+        if self.keyword("ORDER") {
+            self.expect_keyword("BY")?;
+            loop {
+                let operand = self.reference()?;
+                let descending = self.keyword("DESC");
+                if !descending {
+                    self.keyword("ASC");
+                }
+                order.push((operand, descending));
+                if !self.symbol(",") {
+                    break;
+                }
+            }
+        }
+        let skip = if self.keyword("SKIP") { self.int()? } else { 0 };
+        let limit = if self.keyword("LIMIT") {
+            Some(self.int()?)
+        } else {
+            None
+        };
+        if self.peek().is_some() {
+            return Err(self.error("unexpected token"));
+        }
+
+        Ok(Statement {
+            start,
+            hops,
+            filter,
+            returns,
+            order,
+            skip,
+            limit,
+        })
+    }
+    fn node(&mut self) -> Result<NodePattern, DBError> {
+        self.expect_symbol("(")?;
+        let mut node = NodePattern::default();
+        if let Some(Token::Ident(_)) = self.peek() {
+            node.var = Some(self.ident()?);
+        }
+        if self.symbol(":") {
+            node.label = Some(self.ident()?);
+        }
+        if self.symbol("{") {
+            loop {
+                let prop = self.ident()?;
+                self.expect_symbol(":")?;
+                node.props.push((prop, self.literal()?));
+                if !self.symbol(",") {
+                    break;
+                }
+            }
+            self.expect_symbol("}")?;
+        }
+        self.expect_symbol(")")?;
+        Ok(node)
+    }
+    fn rel(&mut self) -> Result<RelPattern, DBError> {
+        let incoming = self.symbol("<");
+        self.expect_symbol("-")?;
+        let mut label = None;
+        if self.symbol("[") {
+            if self.symbol(":") {
+                label = Some(self.ident()?);
+            }
+            self.expect_symbol("]")?;
+        }
+        self.expect_symbol("-")?;
+        let outgoing = self.symbol(">");
+        let direction = match (incoming, outgoing) {
+            (false, true) => RelDirection::Out,
+            (true, false) => RelDirection::In,
+            (false, false) => RelDirection::Both,
+            (true, true) => return Err(self.error("a relationship has one direction")),
+        };
+        Ok(RelPattern { label, direction })
+    }
+    fn or(&mut self) -> Result<Expr, DBError> {
+        let mut expr = self.and()?;
+        while self.keyword("OR") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+    fn and(&mut self) -> Result<Expr, DBError> {
+        let mut expr = self.not()?;
+        while self.keyword("AND") {
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+    fn not(&mut self) -> Result<Expr, DBError> {
+        if self.keyword("NOT") {
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        if self.symbol("(") {
+            let expr = self.or()?;
+            self.expect_symbol(")")?;
+            return Ok(expr);
+        }
+
+        let left = self.operand()?;
+        let op = match self.peek() {
+            Some(Token::Symbol("=")) => CompareOp::Eq,
+            Some(Token::Symbol("<>")) => CompareOp::Ne,
+            Some(Token::Symbol("<")) => CompareOp::Lt,
+            Some(Token::Symbol("<=")) => CompareOp::Le,
+            Some(Token::Symbol(">")) => CompareOp::Gt,
+            Some(Token::Symbol(">=")) => CompareOp::Ge,
+            _ => return Err(self.error("expected a comparison")),
+        };
+        self.pos += 1;
+        Ok(Expr::Compare(left, op, self.operand()?))
+    }
+    fn operand(&mut self) -> Result<Operand, DBError> {
+        match self.peek() {
+            Some(Token::Ident(ident))
+                if !["TRUE", "FALSE", "NULL"]
+                    .iter()
+                    .any(|keyword| ident.eq_ignore_ascii_case(keyword)) =>
+            {
+                self.reference()
+            }
+            _ => Ok(Operand::Literal(self.literal()?)),
+        }
+    }
+    fn reference(&mut self) -> Result<Operand, DBError> {
+        let var = self.ident()?;
+        if self.symbol(".") {
+            Ok(Operand::Prop(var, self.ident()?))
+        } else {
+            Ok(Operand::Var(var))
+        }
+    }
+    fn literal(&mut self) -> Result<Value, DBError> {
+        let negative = self.symbol("-");
+        let value = match self.peek() {
+            Some(Token::Int(n)) if negative => Value::Int(-n),
+            Some(Token::Float(n)) if negative => Value::Float(-n),
+            Some(Token::Int(n)) => Value::Int(*n),
+            Some(Token::Float(n)) => Value::Float(*n),
+            Some(Token::Str(s)) if !negative => Value::String(s.clone()),
+            Some(Token::Ident(ident)) if !negative => match ident.to_uppercase().as_str() {
+                "TRUE" => Value::Bool(true),
+                "FALSE" => Value::Bool(false),
+                "NULL" => Value::Null,
+                _ => return Err(self.error("expected a literal")),
+            },
+            _ => return Err(self.error("expected a literal")),
+        };
+        self.pos += 1;
+        Ok(value)
+    }
+}
+
+/// Parses a statement without running it.
+pub fn parse(text: &str) -> Result<Statement, DBError> {
+    let mut parser = Parser {
+        tokens: tokenize(text)?,
+        pos: 0,
+        end: text.len(),
+    };
+    parser.statement()
+}
+
+type LoadFn<D> = dyn for<'d> Fn(&'d QueryExecutor<'d, D>) -> BoxFuture<'d, Result<Vec<(NodeID, Value)>, DBError>>
+    + Send
+    + Sync;
+type GetFn<D> = dyn for<'d> Fn(&'d D, Vec<NodeID>) -> BoxFuture<'d, Result<Vec<(NodeID, Value)>, DBError>>
+    + Send
+    + Sync;
+
+/// Readers of a registered node type, giving nodes as values.
+struct NodeType<D> {
+    load: Arc<LoadFn<D>>,
+    get: Arc<GetFn<D>>,
+}
+
+/// Runs statements against a `DB`. Labels are the names given to
+/// `#[schema(Node)]`, and the node types a statement reads must be
+/// registered, since their properties can't be decoded otherwise.
+pub struct Cypher<'a, D> {
+    db: &'a D,
+    types: HashMap<String, NodeType<D>>,
+}
+impl<'a, D: DB + Sync> Cypher<'a, D> {
+    pub fn new(db: &'a D) -> Self {
+        Self {
+            db,
+            types: HashMap::new(),
+        }
+    }
+    pub fn register<T: Node>(&mut self) -> &mut Self {
+        let load =
+            loader::<D, _>(|query| Box::pin(async move { to_values(query.exec::<T>().await?) }));
+        let get = getter::<D, _>(|db, ids| {
+            Box::pin(async move { to_values(db.get_nodes::<T>(&ids).await?) })
+        });
+        self.types.insert(T::entity_name(), NodeType { load, get });
+        self
+    }
+    /// Runs `text`, giving one map per row keyed by the returned columns.
+    pub async fn run(&self, text: &str) -> Result<Vec<Value>, DBError> {
+        self.exec(&parse(text)?).await
+    }
+    /// Query operations the first node of `statement` is selected with.
+    /// Comparisons of its properties with literals joined by `AND` use
+    /// its indexes, and the whole `WHERE` is still checked on every row.
+    pub fn operations(&self, statement: &Statement) -> Vec<QueryOperation> {
+        self.start_query(statement).operations().to_vec()
+    }
+    pub async fn exec(&self, statement: &Statement) -> Result<Vec<Value>, DBError> {
+        let label = statement
+            .start
+            .label
+            .as_ref()
+            .ok_or_else(|| DBError::QueryError {
+                error: "the first node of the pattern needs a label".to_string(),
+            })?;
+        let node_type = self.node_type(label)?;
+        let query = self.start_query(statement).build()?;
+        let mut rows: Vec<Vec<(NodeID, Value)>> = (node_type.load)(&query)
+            .await?
+            .into_iter()
+            .map(|node| vec![node])
+            .collect();
+
+        let mut vars: HashMap<&str, usize> = HashMap::new();
+        if let Some(var) = &statement.start.var {
+            vars.insert(var, 0);
+        }
+        let mut entities: HashMap<String, HashSet<NodeID>> = HashMap::new();
+        for (position, (rel, node)) in statement.hops.iter().enumerate() {
+            let bound = node.var.as_deref().and_then(|var| vars.get(var).copied());
+            let mut next = Vec::new();
+            for row in rows {
+                let (from, _) = &row[position];
+                for id in self.neighbors(*from, rel).await? {
+                    if bound.is_some_and(|bound| row[bound].0 != id) {
+                        continue;
+                    }
+                    let Some(value) = self.node_value(id, node, &mut entities).await? else {
+                        continue;
+                    };
+                    let matched = node
+                        .props
+                        .iter()
+                        .all(|(prop, expected)| value.get(prop) == Some(expected));
+                    if matched {
+                        let mut row = row.clone();
+                        row.push((id, value));
+                        next.push(row);
+                    }
+                }
+            }
+            rows = next;
+            if let Some(var) = &node.var {
+                vars.entry(var).or_insert(position + 1);
+            }
+        }
+
+        let resolve = |row: &[(NodeID, Value)], operand: &Operand| -> Result<Value, DBError> {
+            let var_value = |var: &str| {
+                vars.get(var)
+                    .map(|index| &row[*index].1)
+                    .ok_or_else(|| DBError::QueryError {
+                        error: format!("unknown variable {}", var),
+                    })
+            };
+            Ok(match operand {
+                Operand::Literal(value) => value.clone(),
+                Operand::Var(var) => var_value(var)?.clone(),
+                Operand::Prop(var, prop) => {
+                    var_value(var)?.get(prop).cloned().unwrap_or(Value::Null)
+                }
+            })
+        };
+        if let Some(filter) = &statement.filter {
+            let mut filtered = Vec::with_capacity(rows.len());
+            for row in rows {
+                if eval(filter, &|operand| resolve(&row, operand))? {
+                    filtered.push(row);
+                }
+            }
+            rows = filtered;
+        }
+        if !statement.order.is_empty() {
+            let mut keyed = rows
+                .into_iter()
+                .map(|row| {
+                    let keys = statement
+                        .order
+                        .iter()
+                        .map(|(operand, _)| resolve(&row, operand))
+                        .collect::<Result<Vec<_>, DBError>>()?;
+                    Ok((keys, row))
+                })
+                .collect::<Result<Vec<_>, DBError>>()?;
+            keyed.sort_by(|(a, _), (b, _)| {
+                let orders = a.iter().zip(b).zip(&statement.order);
+                orders
+                    .map(|((a, b), (_, descending))| match descending {
+                        true => b.cmp(a),
+                        false => a.cmp(b),
+                    })
+                    .find(|order| order.is_ne())
+                    .unwrap_or(Ordering::Equal)
+            });
+            rows = keyed.into_iter().map(|(_, row)| row).collect();
+        }
+
+        let limit = statement.limit.unwrap_or(usize::MAX);
+        rows.iter()
+            .skip(statement.skip)
+            .take(limit)
+            .map(|row| {
+                let columns = statement
+                    .returns
+                    .iter()
+                    .map(|item| Ok((item.name.clone(), resolve(row, &item.operand)?)))
+                    .collect::<Result<BTreeMap<_, _>, DBError>>()?;
+                Ok(Value::Map(columns))
+            })
+            .collect()
+    }
+
+    fn node_type(&self, label: &str) -> Result<&NodeType<D>, DBError> {
+        self.types
+            .get(&entity_name(label))
+            .ok_or_else(|| DBError::QueryError {
+                error: format!("{} is not a registered node type", label),
+            })
+    }
+
+    fn start_query(&self, statement: &Statement) -> QueryBuilder<'a, D> {
+        let mut query = QueryBuilder::new(self.db);
+        for (prop, value) in &statement.start.props {
+            query.by_index(prop, value.clone());
+        }
+        let Some(var) = &statement.start.var else {
+            return query;
+        };
+        let mut conjuncts = Vec::new();
+        if let Some(filter) = &statement.filter {
+            collect_conjuncts(filter, &mut conjuncts);
+        }
+        for conjunct in conjuncts {
+            let (prop_var, prop, op, value) = match conjunct {
+                Expr::Compare(Operand::Prop(v, prop), op, Operand::Literal(value)) => {
+                    (v, prop, *op, value)
+                }
+                Expr::Compare(Operand::Literal(value), op, Operand::Prop(v, prop)) => {
+                    (v, prop, op.flip(), value)
+                }
+                _ => continue,
+            };
+            if prop_var != var || value.is_null() {
+                continue;
+            }
+            let (included, excluded) = (Bound::Included(value), Bound::Excluded(value));
+            let range = match op {
+                CompareOp::Eq => {
+                    query.by_index(prop, value.clone());
+                    continue;
+                }
+                CompareOp::Ne => continue,
+                CompareOp::Lt => (Bound::Unbounded, excluded),
+                CompareOp::Le => (Bound::Unbounded, included),
+                CompareOp::Gt => (excluded, Bound::Unbounded),
+                CompareOp::Ge => (included, Bound::Unbounded),
+            };
+            query.by_index_range(prop, (range.0.cloned(), range.1.cloned()));
+        }
+        query
+    }
+
+    async fn neighbors(&self, id: NodeID, rel: &RelPattern) -> Result<Vec<NodeID>, DBError> {
+        let label = rel.label.as_deref();
+        let mut ids = Vec::new();
+        if rel.direction != RelDirection::In {
+            let edges: Vec<EdgeItem> = self.db.out_edges(id, label).try_collect().await?;
+            ids.extend(edges.iter().map(|edge| edge.to));
+        }
+        if rel.direction != RelDirection::Out {
+            let edges: Vec<EdgeItem> = self.db.in_edges(id, label).try_collect().await?;
+            ids.extend(edges.iter().map(|edge| edge.from));
+        }
+        Ok(ids)
+    }
+
+    /// Value of the node `id` when it matches the label of `node`. Nodes of
+    /// types that aren't registered can only match unlabeled patterns.
+    async fn node_value(
+        &self,
+        id: NodeID,
+        node: &NodePattern,
+        entities: &mut HashMap<String, HashSet<NodeID>>,
+    ) -> Result<Option<Value>, DBError> {
+        let candidates: Vec<String> = match &node.label {
+            Some(label) => {
+                self.node_type(label)?;
+                vec![entity_name(label)]
+            }
+            None => self.types.keys().cloned().collect(),
+        };
+        for entity in candidates {
+            if !entities.contains_key(&entity) {
+                let ids = self.db.get_node_ids(&entity).await?;
+                entities.insert(entity.clone(), ids.into_iter().collect());
+            }
+            if entities[&entity].contains(&id) {
+                let nodes = (self.types[&entity].get)(self.db, vec![id]).await?;
+                return Ok(nodes.into_iter().next().map(|(_, value)| value));
+            }
+        }
+        Ok(node.label.is_none().then_some(Value::Null))
+    }
+}
+
+impl CompareOp {
+    /// Same comparison with the operands swapped.
+    fn flip(self) -> Self {
+        match self {
+            Self::Lt => Self::Gt,
+            Self::Le => Self::Ge,
+            Self::Gt => Self::Lt,
+            Self::Ge => Self::Le,
+            op => op,
+        }
+    }
+}
+
+fn collect_conjuncts<'e>(expr: &'e Expr, conjuncts: &mut Vec<&'e Expr>) {
+    match expr {
+        Expr::And(left, right) => {
+            collect_conjuncts(left, conjuncts);
+            collect_conjuncts(right, conjuncts);
+        }
+        expr => conjuncts.push(expr),
+    }
+}
+
+/// Comparisons with `null`, or between values of different types, are
+/// false, except for `<>` between values of different types.
+fn eval(
+    expr: &Expr,
+    resolve: &dyn Fn(&Operand) -> Result<Value, DBError>,
+) -> Result<bool, DBError> {
+    Ok(match expr {
+        Expr::And(left, right) => eval(left, resolve)? && eval(right, resolve)?,
+        Expr::Or(left, right) => eval(left, resolve)? || eval(right, resolve)?,
+        Expr::Not(expr) => !eval(expr, resolve)?,
+        Expr::Compare(left, op, right) => {
+            let (left, right) = (resolve(left)?, resolve(right)?);
+            if left.is_null() || right.is_null() {
+                return Ok(false);
+            }
+            let comparable = matches!(
+                (&left, &right),
+                (Value::Bool(_), Value::Bool(_)) | (Value::String(_), Value::String(_))
+            ) || (left.as_f64().is_some() && right.as_f64().is_some());
+            let order = left.cmp(&right);
+            match op {
+                CompareOp::Eq => left == right,
+                CompareOp::Ne => left != right,
+                _ if !comparable => false,
+                CompareOp::Lt => order.is_lt(),
+                CompareOp::Le => order.is_le(),
+                CompareOp::Gt => order.is_gt(),
+                CompareOp::Ge => order.is_ge(),
+            }
+        }
+    })
+}
+
+fn to_values<T: Node>(nodes: Vec<T>) -> Result<Vec<(NodeID, Value)>, DBError> {
+    nodes
+        .into_iter()
+        .map(|node| {
+            let value = node.to_value().map_err(|e| DBError::QueryError {
+                error: e.to_string(),
+            })?;
+            Ok((node.key(), value))
+        })
+        .collect()
+}
+
+/// Pins down the higher-ranked signature of a loading closure.
+fn loader<D, F>(cb: F) -> Arc<LoadFn<D>>
+where
+    F: for<'d> Fn(&'d QueryExecutor<'d, D>) -> BoxFuture<'d, Result<Vec<(NodeID, Value)>, DBError>>
+        + Send
+        + Sync
+        + 'static,
+{
+    Arc::new(cb)
+}
+
+fn getter<D, F>(cb: F) -> Arc<GetFn<D>>
+where
+    F: for<'d> Fn(&'d D, Vec<NodeID>) -> BoxFuture<'d, Result<Vec<(NodeID, Value)>, DBError>>
+        + Send
+        + Sync
+        + 'static,
+{
+    Arc::new(cb)
+}
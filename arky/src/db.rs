@@ -1,13 +1,30 @@
 use crate::{
-    core::types::{DynResult, NodeID},
+    core::types::{GeoArea, NodeID, Value, VectorMetric},
+    cypher::Cypher,
     edge::EdgeItem,
     entity::EntityItem,
     node::Node,
     query::{QueryBuilder, QueryExecutor},
+    traversal::Traversal,
 };
 use async_trait::async_trait;
+use futures::stream::BoxStream;
+use std::future::Future;
+use std::ops::Bound;
 use thiserror::Error as ThisError;
 
+/// Edges adjacent to a node, as returned by `DB::out_edges`/`DB::in_edges`.
+pub type EdgeStream<'a> = BoxStream<'a, Result<EdgeItem, DBError>>;
+/// Nodes read one at a time, as returned by `DB::scan_nodes`.
+pub type NodeStream<'a, T> = BoxStream<'a, Result<T, DBError>>;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct BatchItemError {
+    pub index: usize,
+    pub key: String,
+    pub error: String,
+}
+
 #[derive(Debug, ThisError, PartialEq, Clone)]
 pub enum DBError {
     #[error("Failed to connect on Database: {error}")]
@@ -38,15 +55,104 @@ pub enum DBError {
     RemoveEdgeError { key: String, error: String },
     #[error("Failed to update edge: {key}. Error: {error}")]
     UpdateEdgeError { key: String, error: String },
-    #[error("Failed to execute query")]
-    QueryError,
+    #[error("Failed to prepare batch, {} item(s) failed", .items.len())]
+    BatchError { items: Vec<BatchItemError> },
+    #[error("Failed to write batch. Error: {error}")]
+    WriteBatchError { error: String },
+    #[error("Unique constraint violated on {entity}.{field} by value {value}")]
+    UniqueViolation {
+        entity: String,
+        field: String,
+        value: Value,
+    },
+    #[error("Failed to commit transaction. Error: {error}")]
+    TransactionError { error: String },
+    #[error("Failed to execute query: {error}")]
+    QueryError { error: String },
     #[error("Execution error")]
     ExecError,
 }
 
+impl DBError {
+    /// Error of a write of one item made through its batch counterpart: a
+    /// `BatchError` naming the item or a `WriteBatchError` becomes the error
+    /// `single` makes of its message.
+    pub(crate) fn for_item(self, single: impl FnOnce(String) -> Self) -> Self {
+        match self {
+            Self::BatchError { mut items } if items.len() == 1 => single(items.remove(0).error),
+            Self::WriteBatchError { error } => single(error),
+            e => e,
+        }
+    }
+}
+
+/// Serializes every item of a batch up front, so a single failure aborts the
+/// whole batch and is reported along with the other failed items.
+pub(crate) fn serialize_batch<I, E: ToString>(
+    items: &[I],
+    key: impl Fn(&I) -> String,
+    to_bytes: impl Fn(&I) -> Result<Vec<u8>, E>,
+) -> Result<Vec<Vec<u8>>, DBError> {
+    let mut serialized = Vec::with_capacity(items.len());
+    let mut failed = Vec::new();
+
+    for (index, item) in items.iter().enumerate() {
+        match to_bytes(item) {
+            Ok(bytes) => serialized.push(bytes),
+            Err(e) => failed.push(BatchItemError {
+                index,
+                key: key(item),
+                error: e.to_string(),
+            }),
+        }
+    }
+
+    if !failed.is_empty() {
+        return Err(DBError::BatchError { items: failed });
+    }
+    Ok(serialized)
+}
+
+/// Handle given to the `DB::transaction` callback. Writes made through it
+/// are visible to its own reads, and to everyone else once the callback
+/// returns `Ok`; an `Err` rolls them all back. Committing fails with
+/// `TransactionError` when it conflicts with a concurrent write.
+#[async_trait]
+pub trait Transaction: Clone + Send + Sync {
+    /**
+     * Entity methods
+     */
+    async fn get_entity(&self, name: &str) -> Result<EntityItem, DBError>;
+    async fn insert_entity(&self, entity: &EntityItem) -> Result<(), DBError>;
+    async fn remove_entity(&self, entity: &EntityItem) -> Result<(), DBError>;
+
+    /**
+     * Node methods
+     */
+    async fn get_node<T: Node>(&self, id: NodeID) -> Result<T, DBError>;
+    async fn insert_node<T: Node>(&self, node: &T) -> Result<(), DBError>;
+    async fn remove_node<T: Node>(&self, node: &T) -> Result<(), DBError>;
+
+    /**
+     * Edge methods
+     */
+    async fn get_edge(&self, from: NodeID, to: NodeID) -> Result<EdgeItem, DBError>;
+    async fn get_edge_by_label(
+        &self,
+        from: NodeID,
+        to: NodeID,
+        label: &str,
+    ) -> Result<EdgeItem, DBError>;
+    async fn insert_edge(&self, edge: &EdgeItem) -> Result<(), DBError>;
+    async fn remove_edge(&self, edge: &EdgeItem) -> Result<(), DBError>;
+}
+
 #[async_trait]
 pub trait DB {
     type Config;
+    type Transaction<'a>: Transaction
+    where
+        Self: 'a;
 
     fn key(&self) -> String;
     fn new(key: String, config: &Self::Config) -> Result<Self, DBError>
@@ -67,37 +173,148 @@ pub trait DB {
      * Node methods
      */
     async fn get_node<T: Node>(&self, id: NodeID) -> Result<T, DBError>;
+    async fn get_nodes<T: Node>(&self, ids: &[NodeID]) -> Result<Vec<T>, DBError>;
+    async fn get_node_ids(&self, entity: &str) -> Result<Vec<NodeID>, DBError>;
+    /// Nodes of `T` in storage order, starting after the node `after`.
+    fn scan_nodes<T: Node>(&self, after: Option<NodeID>) -> NodeStream<'_, T>;
+    /// Same as `get_nodes`, giving the stored bytes of the nodes of
+    /// `entity`, for readers that don't know their Rust type.
+    async fn get_node_bytes(
+        &self,
+        entity: &str,
+        ids: &[NodeID],
+    ) -> Result<Vec<(NodeID, Vec<u8>)>, DBError>;
+    /// Same as `scan_nodes`, giving the stored bytes of the nodes of
+    /// `entity`.
+    fn scan_node_bytes(
+        &self,
+        entity: &str,
+        after: Option<NodeID>,
+    ) -> NodeStream<'_, (NodeID, Vec<u8>)>;
+    async fn get_node_ids_by_index(
+        &self,
+        entity: &str,
+        field: &str,
+        value: &Value,
+    ) -> Result<Vec<NodeID>, DBError>;
+    /// Ids of the nodes matching `values` on the leading fields of the
+    /// composite index over `fields`.
+    async fn get_node_ids_by_composite_index(
+        &self,
+        entity: &str,
+        fields: &[String],
+        values: &[Value],
+    ) -> Result<Vec<NodeID>, DBError>;
+    /// Ids of the nodes whose `#[index(fulltext)]` field contains any word
+    /// of `query`, with their BM25 score, best first.
+    async fn search_node_ids(
+        &self,
+        entity: &str,
+        field: &str,
+        query: &str,
+    ) -> Result<Vec<(NodeID, f64)>, DBError>;
+    /// The `k` nodes whose `#[index(vector)]` field is the closest to
+    /// `query` according to `metric`, with their distance, closest first.
+    /// Results are approximate.
+    async fn nearest_node_ids(
+        &self,
+        entity: &str,
+        field: &str,
+        metric: VectorMetric,
+        query: &[f32],
+        k: usize,
+    ) -> Result<Vec<(NodeID, f32)>, DBError>;
+    /// Ids of the nodes whose `field` lies within the bounds, ordered by
+    /// the value of the field.
+    async fn get_node_ids_by_index_range(
+        &self,
+        entity: &str,
+        field: &str,
+        start: Bound<&Value>,
+        end: Bound<&Value>,
+    ) -> Result<Vec<NodeID>, DBError>;
+    /// Ids of every node in the index of `field`, in the order of its values
+    /// and then of the ids, starting after the value and id of `after`. The
+    /// index is read as the stream is, and values of any type are given.
+    fn scan_index_ids(
+        &self,
+        entity: &str,
+        field: &str,
+        after: Option<(&Value, NodeID)>,
+    ) -> NodeStream<'_, NodeID>;
+    /// Ids of the nodes whose `#[index(geo)]` field lies within `area`.
+    async fn get_node_ids_by_geo(
+        &self,
+        entity: &str,
+        field: &str,
+        area: &GeoArea,
+    ) -> Result<Vec<NodeID>, DBError>;
     async fn insert_node<T: Node + Sync>(&self, node: &T) -> Result<(), DBError>;
     async fn insert_nodes<T: Node>(&self, nodes: &[T]) -> Result<(), DBError>;
     async fn remove_node<T: Node>(&self, node: &T) -> Result<(), DBError>;
     async fn remove_nodes<T: Node>(&self, nodes: &[T]) -> Result<(), DBError>;
+    /// Removes `nodes` along with every edge from or to them, in one write.
+    async fn remove_nodes_with_edges<T: Node>(&self, nodes: &[T]) -> Result<(), DBError>;
     async fn update_node<T: Node>(&self, node: &T) -> Result<(), DBError>;
 
     /**
      * Edge methods
      */
     async fn get_edge(&self, from: NodeID, to: NodeID) -> Result<EdgeItem, DBError>;
+    async fn get_edges(&self, from: NodeID, to: NodeID) -> Result<Vec<EdgeItem>, DBError>;
+    async fn get_edge_by_label(
+        &self,
+        from: NodeID,
+        to: NodeID,
+        label: &str,
+    ) -> Result<EdgeItem, DBError>;
+    /// Every edge, in the order of their keys as strings.
+    async fn scan_edges(&self) -> Result<Vec<EdgeItem>, DBError>;
+    fn out_edges(&self, id: NodeID, label: Option<&str>) -> EdgeStream<'_>;
+    fn in_edges(&self, id: NodeID, label: Option<&str>) -> EdgeStream<'_>;
     async fn insert_edge(&self, edge: &EdgeItem) -> Result<(), DBError>;
     async fn insert_edges(&self, edges: &[EdgeItem]) -> Result<(), DBError>;
     async fn remove_edge(&self, edge: &EdgeItem) -> Result<(), DBError>;
     async fn remove_edges(&self, edges: &[EdgeItem]) -> Result<(), DBError>;
     async fn update_edge(&self, edge: &EdgeItem) -> Result<(), DBError>;
 
+    /**
+     * Transaction methods
+     */
+    async fn transaction<'a, F, Fut, R>(&'a self, cb: F) -> Result<R, DBError>
+    where
+        F: FnOnce(Self::Transaction<'a>) -> Fut + Send,
+        Fut: Future<Output = Result<R, DBError>> + Send,
+        R: Send;
+
     /**
      * Query methods
      */
-    fn query(&self) -> QueryBuilder<Self>
+    fn query(&self) -> QueryBuilder<'_, Self>
     where
         Self: Sized,
     {
         QueryBuilder::new(self)
     }
 
-    async fn exec<R>(&self, query: QueryExecutor<'async_trait, Self>) -> DynResult<R>
+    fn cypher(&self) -> Cypher<'_, Self>
+    where
+        Self: Sized + Sync,
+    {
+        Cypher::new(self)
+    }
+
+    fn traverse(&self, start: &NodeID) -> Traversal<'_, Self>
+    where
+        Self: Sized + Sync,
+    {
+        Traversal::new(self, *start)
+    }
+
+    async fn exec<T: Node>(&self, query: &QueryExecutor<'_, Self>) -> Result<Vec<T>, DBError>
     where
-        Self: Sized + Send + Sync,
+        Self: Sized + Sync,
     {
-        // TODO: handle error
-        query.exec::<R>()
+        query.exec::<T>().await
     }
 }
@@ -47,11 +47,17 @@ impl EdgeItem {
         let bytes = bincode::serialize(self).map_err(|_| EdgeError::SerializeError)?;
         Ok(bytes)
     }
+    /// Edges are keyed by both endpoints and the label, so several edges
+    /// with different labels can link the same pair of nodes.
     pub fn key(&self) -> String {
-        Self::format_key(self.from, self.to)
+        Self::format_key(self.from, self.to, &self.label)
     }
-    pub fn format_key(from: NodeID, to: NodeID) -> String {
-        format!("{}:{}", from, to)
+    pub fn format_key(from: NodeID, to: NodeID, label: &str) -> String {
+        format!("{}{}", Self::format_prefix(from, to), label)
+    }
+    /// Prefix shared by the keys of every edge from `from` to `to`.
+    pub fn format_prefix(from: NodeID, to: NodeID) -> String {
+        format!("{}:{}:", from, to)
     }
 }
 
@@ -90,7 +96,7 @@ impl EdgeBuilder for Edge {
         self.label.to_string()
     }
     fn key(&self) -> EdgeID {
-        self.id.clone()
+        self.id
     }
 }
 impl Edge {
@@ -128,7 +134,7 @@ impl EdgeBuilder for EdgeList {
         self.label.to_string()
     }
     fn key(&self) -> EdgeID {
-        self.id.clone()
+        self.id
     }
 }
 impl EdgeList {
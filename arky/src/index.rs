@@ -0,0 +1,506 @@
+use crate::db::DBError;
+use crate::node::{Node, NodeError};
+use crate::vector::{VectorEntry, VectorGraph};
+use arkycore::types::{GeoArea, GeoPoint, NodeID, Value, VectorMetric};
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Bound;
+
+/// BM25 term frequency saturation.
+const BM25_K1: f64 = 1.2;
+/// BM25 document length normalization.
+const BM25_B: f64 = 0.75;
+/// Bits of each coordinate in a geo cell.
+const GEO_BITS: u32 = 26;
+/// Cells spanned by a geo query along each coordinate, at most.
+const GEO_SPAN: u64 = 4;
+
+/// Index keys are `{entity}\0{field}\0{value}{id}`, the value being encoded
+/// with `encode_value` and the id as 8 big-endian bytes. Encoded values are
+/// prefix-free, so a value prefix never matches the keys of another value.
+pub(crate) fn index_prefix(entity: &str, field: &str, value: &Value) -> Vec<u8> {
+    let mut key = field_prefix(entity, field);
+    encode_value(value, &mut key);
+    key
+}
+
+/// Composite index keys are `{entity}\0{field},{field}\0{values}{id}`, the
+/// values of the fields being encoded one after the other. Passing fewer
+/// values than fields gives the prefix of all the keys matching them.
+pub(crate) fn composite_prefix(entity: &str, fields: &[String], values: &[Value]) -> Vec<u8> {
+    let mut key = field_prefix(entity, &fields.join(","));
+    for value in values {
+        encode_value(value, &mut key);
+    }
+    key
+}
+
+/// Full-text postings are `{entity}\0{field}:terms\0{term}\0{tf}{id}` and the
+/// number of words of each node is kept as `{entity}\0{field}:lengths\0{len}{id}`,
+/// `tf` and `len` being 4 big-endian bytes. Terms are lowercase words, so
+/// they never contain `\0`.
+fn term_prefix(entity: &str, field: &str, term: &str) -> Vec<u8> {
+    let mut key = field_prefix(entity, &format!("{}:terms", field));
+    key.extend_from_slice(term.as_bytes());
+    key.push(0);
+    key
+}
+
+fn length_prefix(entity: &str, field: &str) -> Vec<u8> {
+    field_prefix(entity, &format!("{}:lengths", field))
+}
+
+/// Lowercase alphanumeric words of `text`.
+pub(crate) fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+fn fulltext_keys(entity: &str, field: &str, text: &str, id: NodeID) -> Vec<Vec<u8>> {
+    let words = tokenize(text);
+    let mut frequencies = BTreeMap::new();
+    for word in &words {
+        *frequencies.entry(word.as_str()).or_insert(0u32) += 1;
+    }
+
+    let mut length_key = length_prefix(entity, field);
+    length_key.extend_from_slice(&(words.len() as u32).to_be_bytes());
+    length_key.extend_from_slice(&encode_id(id));
+    let mut keys = vec![length_key];
+    for (term, frequency) in frequencies {
+        let mut key = term_prefix(entity, field, term);
+        key.extend_from_slice(&frequency.to_be_bytes());
+        key.extend_from_slice(&encode_id(id));
+        keys.push(key);
+    }
+    keys
+}
+
+/// The 4 bytes stored before the id of a full-text key.
+fn fulltext_count(key: &[u8]) -> Option<u32> {
+    let end = key.len().checked_sub(8)?;
+    let bytes = key.get(end.checked_sub(4)?..end)?;
+    Some(u32::from_be_bytes(bytes.try_into().ok()?))
+}
+
+/// Ranks the nodes whose `field` contains any word of `query` with BM25,
+/// best first. `scan` returns the index keys starting with a prefix.
+pub(crate) fn bm25_scores<E>(
+    entity: &str,
+    field: &str,
+    query: &str,
+    mut scan: impl FnMut(&[u8]) -> Result<Vec<Vec<u8>>, E>,
+) -> Result<Vec<(NodeID, f64)>, E> {
+    let mut terms = tokenize(query);
+    terms.sort();
+    terms.dedup();
+    if terms.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let lengths: HashMap<NodeID, u32> = scan(&length_prefix(entity, field))?
+        .iter()
+        .filter_map(|key| Some((index_id(key)?, fulltext_count(key)?)))
+        .collect();
+    let count = lengths.len() as f64;
+    let average_length = lengths.values().map(|len| *len as f64).sum::<f64>() / count.max(1.0);
+
+    let mut scores: HashMap<NodeID, f64> = HashMap::new();
+    for term in terms {
+        let postings: Vec<(NodeID, u32)> = scan(&term_prefix(entity, field, &term))?
+            .iter()
+            .filter_map(|key| Some((index_id(key)?, fulltext_count(key)?)))
+            .collect();
+        let matched = postings.len() as f64;
+        let idf = (1.0 + (count - matched + 0.5) / (matched + 0.5)).ln();
+        for (id, frequency) in postings {
+            let frequency = frequency as f64;
+            let length = lengths.get(&id).copied().unwrap_or_default() as f64;
+            let norm = 1.0 - BM25_B + BM25_B * length / average_length.max(1.0);
+            *scores.entry(id).or_default() +=
+                idf * frequency * (BM25_K1 + 1.0) / (frequency + BM25_K1 * norm);
+        }
+    }
+
+    let mut scores: Vec<_> = scores.into_iter().collect();
+    scores.sort_by(|(a_id, a), (b_id, b)| b.total_cmp(a).then(a_id.0.cmp(&b_id.0)));
+    Ok(scores)
+}
+
+/// Geo keys are `{entity}\0{field}:geo\0{cell}{lat}{lon}{id}`. The cell is
+/// the Z-order interleaving of the coordinates quantized on `GEO_BITS`
+/// bits, as 8 big-endian bytes, so that nearby points share key prefixes.
+/// The coordinates follow as f64 bits to filter the cells exactly.
+fn geo_prefix(entity: &str, field: &str) -> Vec<u8> {
+    field_prefix(entity, &format!("{}:geo", field))
+}
+
+fn geo_key(entity: &str, field: &str, point: &GeoPoint, id: NodeID) -> Vec<u8> {
+    let mut key = geo_prefix(entity, field);
+    let cell = geo_cell(quantize(point.lat, 90.0), quantize(point.lon, 180.0));
+    key.extend_from_slice(&cell.to_be_bytes());
+    key.extend_from_slice(&point.lat.to_bits().to_be_bytes());
+    key.extend_from_slice(&point.lon.to_bits().to_be_bytes());
+    key.extend_from_slice(&encode_id(id));
+    key
+}
+
+/// Location stored in a geo key.
+pub(crate) fn geo_point(key: &[u8]) -> Option<GeoPoint> {
+    let end = key.len().checked_sub(8)?;
+    let bytes = key.get(end.checked_sub(16)?..end)?;
+    let lat = f64::from_bits(u64::from_be_bytes(bytes[..8].try_into().ok()?));
+    let lon = f64::from_bits(u64::from_be_bytes(bytes[8..].try_into().ok()?));
+    Some(GeoPoint::new(lat, lon))
+}
+
+/// Key ranges, as `[start, end)`, holding the geo keys of every point of
+/// `area`. Keys in them may still lie outside of it and must be checked
+/// with `geo_point`.
+pub(crate) fn geo_ranges(entity: &str, field: &str, area: &GeoArea) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let mut cells = Vec::new();
+    for (south_west, north_east) in area.bounds() {
+        let lat = (
+            quantize(south_west.lat, 90.0),
+            quantize(north_east.lat, 90.0),
+        );
+        let lon = (
+            quantize(south_west.lon, 180.0),
+            quantize(north_east.lon, 180.0),
+        );
+        let shift = (0..=GEO_BITS)
+            .find(|shift| {
+                (lat.1 >> shift) - (lat.0 >> shift) < GEO_SPAN
+                    && (lon.1 >> shift) - (lon.0 >> shift) < GEO_SPAN
+            })
+            .unwrap_or(GEO_BITS);
+        for lat_cell in (lat.0 >> shift)..=(lat.1 >> shift) {
+            for lon_cell in (lon.0 >> shift)..=(lon.1 >> shift) {
+                let start = geo_cell(lat_cell << shift, lon_cell << shift);
+                cells.push((start, start + (1 << (2 * shift))));
+            }
+        }
+    }
+    cells.sort_unstable();
+
+    let mut merged: Vec<(u64, u64)> = Vec::with_capacity(cells.len());
+    for (start, end) in cells {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    let prefix = geo_prefix(entity, field);
+    let key = |cell: u64| [prefix.as_slice(), &cell.to_be_bytes()].concat();
+    merged
+        .into_iter()
+        .map(|(start, end)| (key(start), key(end)))
+        .collect()
+}
+
+/// Step of `degrees` when `-max..=max` is split in `2^GEO_BITS` steps.
+fn quantize(degrees: f64, max: f64) -> u64 {
+    let steps = (1u64 << GEO_BITS) as f64;
+    let step = ((degrees + max) / (2.0 * max) * steps).floor();
+    step.clamp(0.0, steps - 1.0) as u64
+}
+
+fn geo_cell(lat: u64, lon: u64) -> u64 {
+    (0..GEO_BITS).fold(0, |cell, bit| {
+        cell | ((lat >> bit) & 1) << (2 * bit) | ((lon >> bit) & 1) << (2 * bit + 1)
+    })
+}
+
+/// Prefix shared by the index keys of every value of `field`.
+pub(crate) fn field_prefix(entity: &str, field: &str) -> Vec<u8> {
+    let mut key = Vec::with_capacity(entity.len() + field.len() + 32);
+    key.extend_from_slice(entity.as_bytes());
+    key.push(0);
+    key.extend_from_slice(field.as_bytes());
+    key.push(0);
+    key
+}
+
+pub(crate) fn index_key(entity: &str, field: &str, value: &Value, id: NodeID) -> Vec<u8> {
+    let mut key = index_prefix(entity, field, value);
+    key.extend_from_slice(&encode_id(id));
+    key
+}
+
+pub(crate) fn index_id(key: &[u8]) -> Option<NodeID> {
+    decode_id(key.get(key.len().checked_sub(8)?..)?)
+}
+
+/// Range of values of an indexed field, compared on their encoding. A range
+/// bounded on one side only stays within the type of its bound, so `..65`
+/// doesn't match `null` or booleans.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct IndexRange {
+    prefix: Vec<u8>,
+    start: Bound<Vec<u8>>,
+    end: Bound<Vec<u8>>,
+}
+impl IndexRange {
+    pub fn new(entity: &str, field: &str, start: Bound<&Value>, end: Bound<&Value>) -> Self {
+        let encode = |value: &Value| {
+            let mut bytes = Vec::new();
+            encode_value(value, &mut bytes);
+            bytes
+        };
+        let mut start = start.map(encode);
+        let mut end = end.map(encode);
+        match (&start, &end) {
+            (Bound::Unbounded, Bound::Included(bytes) | Bound::Excluded(bytes)) => {
+                start = Bound::Included(vec![bytes[0]]);
+            }
+            (Bound::Included(bytes) | Bound::Excluded(bytes), Bound::Unbounded) => {
+                end = Bound::Excluded(vec![bytes[0] + 1]);
+            }
+            _ => {}
+        }
+
+        Self {
+            prefix: field_prefix(entity, field),
+            start,
+            end,
+        }
+    }
+
+    /// Key to start scanning the index from.
+    pub fn seek_key(&self) -> Vec<u8> {
+        let mut key = self.prefix.clone();
+        if let Bound::Included(start) | Bound::Excluded(start) = &self.start {
+            key.extend_from_slice(start);
+        }
+        key
+    }
+
+    /// Whether `key`, reached by scanning from `seek_key`, lies past the end
+    /// of the range.
+    pub fn is_past(&self, key: &[u8]) -> bool {
+        match self.key_value(key) {
+            Some(value) => match &self.end {
+                Bound::Included(end) => value > end.as_slice(),
+                Bound::Excluded(end) => value >= end.as_slice(),
+                Bound::Unbounded => false,
+            },
+            None => true,
+        }
+    }
+
+    /// Whether `key`, reached by scanning from `seek_key`, is within the
+    /// range. Only the keys of an excluded start are skipped.
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        match (&self.start, self.key_value(key)) {
+            (Bound::Excluded(start), Some(value)) => value != start.as_slice(),
+            (_, value) => value.is_some(),
+        }
+    }
+
+    pub fn contains(&self, value: &Value) -> bool {
+        let mut bytes = Vec::new();
+        encode_value(value, &mut bytes);
+        let after_start = match &self.start {
+            Bound::Included(start) => &bytes >= start,
+            Bound::Excluded(start) => &bytes > start,
+            Bound::Unbounded => true,
+        };
+        let before_end = match &self.end {
+            Bound::Included(end) => &bytes <= end,
+            Bound::Excluded(end) => &bytes < end,
+            Bound::Unbounded => true,
+        };
+        after_start && before_end
+    }
+
+    fn key_value<'k>(&self, key: &'k [u8]) -> Option<&'k [u8]> {
+        let value = key.strip_prefix(self.prefix.as_slice())?;
+        value.get(..value.len().checked_sub(8)?)
+    }
+}
+
+/// Value claimed by a node under a unique index. Its key is the index
+/// prefix of the value and maps to the id of the node owning it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct UniqueEntry {
+    pub field: String,
+    pub value: Value,
+    pub key: Vec<u8>,
+}
+impl UniqueEntry {
+    pub fn violation(&self, entity: &str) -> DBError {
+        DBError::UniqueViolation {
+            entity: entity.to_string(),
+            field: self.field.clone(),
+            value: self.value.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct IndexEntries {
+    pub keys: Vec<Vec<u8>>,
+    pub unique: Vec<UniqueEntry>,
+    pub vectors: Vec<VectorEntry>,
+}
+
+/// Index keys of `node`, plus the values it claims under unique indexes.
+pub(crate) fn index_entries<T: Node>(node: &T) -> Result<IndexEntries, NodeError> {
+    let indexes = T::indexes();
+    let composite_indexes = T::composite_indexes();
+    let mut entries = IndexEntries::default();
+    if indexes.is_empty() && composite_indexes.is_empty() {
+        return Ok(entries);
+    }
+
+    let value = node.to_value()?;
+    let entity = node.entity();
+    for index in indexes {
+        let field_value = value.get(&index.field).unwrap_or(&Value::Null);
+        if index.is_equality() {
+            let key = index_key(&entity, &index.field, field_value, node.key());
+            entries.keys.push(key);
+        }
+        if let (true, Value::String(text)) = (index.fulltext, field_value) {
+            let keys = fulltext_keys(&entity, &index.field, text, node.key());
+            entries.keys.extend(keys);
+        }
+        if let Some(metric) = index.vector {
+            let vector = to_vector(field_value).ok_or_else(|| {
+                NodeError::ValueError(format!("{} is not a list of numbers", index.field))
+            })?;
+            if !vector.is_empty() {
+                entries.vectors.push(VectorEntry {
+                    graph: vector_graph(&entity, &index.field, metric),
+                    vector,
+                });
+            }
+        }
+        if index.geo && !field_value.is_null() {
+            let point = GeoPoint::from_value(field_value).ok_or_else(|| {
+                NodeError::ValueError(format!("{} is not a location", index.field))
+            })?;
+            let key = geo_key(&entity, &index.field, &point, node.key());
+            entries.keys.push(key);
+        }
+        if index.unique && !field_value.is_null() {
+            entries.unique.push(UniqueEntry {
+                key: index_prefix(&entity, &index.field, field_value),
+                field: index.field,
+                value: field_value.clone(),
+            });
+        }
+    }
+    for index in composite_indexes {
+        let values: Vec<Value> = index
+            .fields
+            .iter()
+            .map(|field| value.get(field).cloned().unwrap_or(Value::Null))
+            .collect();
+        let mut key = composite_prefix(&entity, &index.fields, &values);
+        key.extend_from_slice(&encode_id(node.key()));
+        entries.keys.push(key);
+    }
+    Ok(entries)
+}
+
+pub(crate) fn vector_graph(entity: &str, field: &str, metric: VectorMetric) -> VectorGraph {
+    VectorGraph {
+        prefix: field_prefix(entity, field),
+        metric,
+    }
+}
+
+/// Components of a vector property, a missing one being empty.
+pub(crate) fn to_vector(value: &Value) -> Option<Vec<f32>> {
+    match value {
+        Value::Null => Some(Vec::new()),
+        Value::List(items) => items
+            .iter()
+            .map(|item| item.as_f64().map(|x| x as f32))
+            .collect(),
+        _ => None,
+    }
+}
+
+pub(crate) fn encode_id(id: NodeID) -> [u8; 8] {
+    id.0.to_be_bytes()
+}
+
+pub(crate) fn decode_id(bytes: &[u8]) -> Option<NodeID> {
+    Some(NodeID(u64::from_be_bytes(bytes.try_into().ok()?)))
+}
+
+const NULL_TAG: u8 = 0x01;
+const BOOL_TAG: u8 = 0x02;
+const NUMBER_TAG: u8 = 0x03;
+const STRING_TAG: u8 = 0x04;
+const LIST_TAG: u8 = 0x05;
+const MAP_TAG: u8 = 0x06;
+
+/// Order-preserving encoding: the bytes of two values compare like the
+/// values do. Each value starts with a tag following the order of kinds in
+/// `Value::cmp`, so it also keeps values of different kinds apart.
+///
+/// Numbers compare equal across `Int`, `UInt` and `Float`, so they share a
+/// representation: their floor as a sign-flipped big-endian `i128`, then the
+/// bits of their fractional part. Numbers beyond the `i128` range share an
+/// encoding with its bounds. As in `Value::cmp`, NaN comes after every
+/// number, or with the lowest ones when negative. Strings are terminated by
+/// `00 01`, their `00` bytes being escaped as `00 FF`. Lists and maps put a
+/// `01` before each item and a `00` after the last one.
+pub(crate) fn encode_value(value: &Value, bytes: &mut Vec<u8>) {
+    match value {
+        Value::Null => bytes.push(NULL_TAG),
+        Value::Bool(b) => bytes.extend([BOOL_TAG, *b as u8]),
+        Value::Int(n) => encode_number(i128::from(*n), 0.0, bytes),
+        Value::UInt(n) => encode_number(i128::from(*n), 0.0, bytes),
+        Value::Float(f) if f.is_nan() => match f.is_sign_positive() {
+            true => encode_number(i128::MAX, *f, bytes),
+            false => encode_number(i128::MIN, 0.0, bytes),
+        },
+        Value::Float(f) => {
+            let floor = f.floor();
+            let fract = if f.is_finite() { f - floor } else { 0.0 };
+            encode_number(floor as i128, fract, bytes)
+        }
+        Value::String(s) => {
+            bytes.push(STRING_TAG);
+            encode_str(s, bytes);
+        }
+        Value::List(items) => {
+            bytes.push(LIST_TAG);
+            for item in items {
+                bytes.push(1);
+                encode_value(item, bytes);
+            }
+            bytes.push(0);
+        }
+        Value::Map(map) => {
+            bytes.push(MAP_TAG);
+            for (key, item) in map {
+                bytes.push(1);
+                encode_str(key, bytes);
+                encode_value(item, bytes);
+            }
+            bytes.push(0);
+        }
+    }
+}
+
+fn encode_number(floor: i128, fract: f64, bytes: &mut Vec<u8>) {
+    bytes.push(NUMBER_TAG);
+    bytes.extend(((floor as u128) ^ (1 << 127)).to_be_bytes());
+    bytes.extend(fract.to_bits().to_be_bytes());
+}
+
+fn encode_str(s: &str, bytes: &mut Vec<u8>) {
+    for byte in s.bytes() {
+        match byte {
+            0 => bytes.extend([0, 0xFF]),
+            byte => bytes.push(byte),
+        }
+    }
+    bytes.extend([0, 1]);
+}
@@ -5,6 +5,7 @@ pub mod prelude {
     pub use super::ArkyDB;
     pub use crate::db::{DBError, DB};
     pub use crate::storage::{Storage, StorageError};
+    pub use crate::storages::memory::{MemoryConfig, MemoryStorage};
     pub use crate::storages::rocksdb::{RocksDB, RocksDBConfig};
 }
 
@@ -4,7 +4,17 @@ pub mod inst;
 pub mod node;
 pub mod storage;
 
+pub mod aggregate;
+pub mod cursor;
+pub mod cypher;
 pub mod db;
 pub mod entity;
+mod index;
+pub mod path;
+pub mod plan;
 pub mod query;
+mod reader;
+pub mod select;
 pub mod storages;
+pub mod traversal;
+mod vector;
@@ -1,6 +1,10 @@
-pub use arkycore::types::{Deserialize, NodeID, Serialize};
+pub use arkycore::types::{
+    CompositeIndex, Deserialize, GeoArea, GeoPoint, Index, NodeID, Serialize, Value, VectorMetric,
+};
 use arkycore::utils;
 pub use arkymacros_schema::schema;
+use std::collections::HashMap;
+use std::sync::{OnceLock, RwLock};
 use thiserror::Error as ThisError;
 
 pub mod prelude {
@@ -13,6 +17,8 @@ pub enum NodeError {
     SerializeError,
     #[error("Failed to deserialize node")]
     DeserializeError,
+    #[error("Failed to convert node into value: {0}")]
+    ValueError(String),
 }
 
 pub trait Node
@@ -20,10 +26,21 @@ where
     Self: Sized + Send + Sync + Serialize + for<'de> Deserialize<'de> + 'static,
 {
     fn key(&self) -> NodeID;
-    fn entity(&self) -> String {
+    fn entity_name() -> String {
         utils::format_entity("WeakNode")
     }
+    fn entity(&self) -> String {
+        Self::entity_name()
+    }
+    fn indexes() -> Vec<Index> {
+        Vec::new()
+    }
+    fn composite_indexes() -> Vec<CompositeIndex> {
+        Vec::new()
+    }
+    #[allow(clippy::new_ret_no_self)]
     fn new<T: Node>(node: T) -> T {
+        register::<T>();
         node
     }
     fn from_bytes(bytes: &[u8]) -> Result<Self, NodeError> {
@@ -34,4 +51,65 @@ where
         let bytes = bincode::serialize(self).map_err(|_| NodeError::SerializeError)?;
         Ok(bytes)
     }
+    fn to_value(&self) -> Result<Value, NodeError> {
+        Value::from_serialize(self).map_err(|e| NodeError::ValueError(e.to_string()))
+    }
+}
+
+/// What queries need to know of a node type, besides its Rust type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schema {
+    pub entity: String,
+    pub indexes: Vec<Index>,
+    pub composite_indexes: Vec<CompositeIndex>,
+}
+impl Schema {
+    pub fn of<T: Node>() -> Self {
+        Self {
+            entity: T::entity_name(),
+            indexes: T::indexes(),
+            composite_indexes: T::composite_indexes(),
+        }
+    }
+    pub fn index(&self, field: &str) -> Option<&Index> {
+        self.indexes.iter().find(|index| index.field == field)
+    }
+}
+
+/// Node type registered under its entity name, read without its Rust type.
+#[derive(Clone, Copy)]
+pub(crate) struct NodeType {
+    pub(crate) schema: fn() -> Schema,
+    pub(crate) decode: fn(&[u8]) -> Result<Value, NodeError>,
+}
+
+fn registry() -> &'static RwLock<HashMap<String, NodeType>> {
+    static REGISTRY: OnceLock<RwLock<HashMap<String, NodeType>>> = OnceLock::new();
+    REGISTRY.get_or_init(Default::default)
+}
+
+/// Registers `T` under its entity name, so `QueryExecutor::select` can read
+/// its nodes by that name. `Node::new` and the typed queries register the
+/// types they handle, so this is only needed for types a process never
+/// builds nor queries by type.
+pub fn register<T: Node>() {
+    fn decode<T: Node>(bytes: &[u8]) -> Result<Value, NodeError> {
+        T::from_bytes(bytes)?.to_value()
+    }
+
+    let entity = T::entity_name();
+    if registry().read().unwrap().contains_key(&entity) {
+        return;
+    }
+    registry().write().unwrap().insert(
+        entity,
+        NodeType {
+            schema: Schema::of::<T>,
+            decode: decode::<T>,
+        },
+    );
+}
+
+pub(crate) fn node_type(entity: &str) -> Option<NodeType> {
+    registry().read().unwrap().get(entity).copied()
 }
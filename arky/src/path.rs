@@ -0,0 +1,273 @@
+use crate::db::{DBError, DB};
+use crate::edge::{Data, EdgeItem};
+use arkycore::types::NodeID;
+use futures::TryStreamExt;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::sync::Arc;
+
+type WeightFn = dyn Fn(&Data) -> Option<f64> + Send + Sync;
+type HeuristicFn = dyn Fn(NodeID) -> f64 + Send + Sync;
+
+/// Constraints of `QueryBuilder::short_path_with`. Without a weight, the
+/// path with the fewest edges is found with a breadth-first search.
+/// Otherwise the cheapest one is found with Dijkstra, or A* when a
+/// heuristic is given too.
+#[derive(Clone, Default)]
+pub struct PathOptions {
+    labels: Vec<String>,
+    pub(crate) max_depth: Option<usize>,
+    weight: Option<Arc<WeightFn>>,
+    heuristic: Option<Arc<HeuristicFn>>,
+}
+impl PathOptions {
+    /// Only follows edges with one of `labels`.
+    pub fn labels(mut self, labels: &[&str]) -> Self {
+        self.labels = labels.iter().map(|label| label.to_string()).collect();
+        self
+    }
+    /// Only finds paths of at most `max_depth` edges.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+    /// Cost of an edge from its payload. Edges it returns `None` for aren't
+    /// followed, and costs must not be negative.
+    pub fn weight(mut self, cb: impl Fn(&Data) -> Option<f64> + Send + Sync + 'static) -> Self {
+        self.weight = Some(Arc::new(cb));
+        self
+    }
+    /// Estimate of the cost left from a node to the target, which must never
+    /// exceed the actual cost for the path found to be the cheapest.
+    pub fn heuristic(mut self, cb: impl Fn(NodeID) -> f64 + Send + Sync + 'static) -> Self {
+        self.heuristic = Some(Arc::new(cb));
+        self
+    }
+    fn follows(&self, edge: &EdgeItem) -> bool {
+        self.labels.is_empty() || self.labels.contains(&edge.label)
+    }
+}
+impl PartialEq for PathOptions {
+    fn eq(&self, other: &Self) -> bool {
+        fn same<F: ?Sized>(a: &Option<Arc<F>>, b: &Option<Arc<F>>) -> bool {
+            match (a, b) {
+                (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+                (None, None) => true,
+                _ => false,
+            }
+        }
+        self.labels == other.labels
+            && self.max_depth == other.max_depth
+            && same(&self.weight, &other.weight)
+            && same(&self.heuristic, &other.heuristic)
+    }
+}
+impl Eq for PathOptions {}
+impl fmt::Debug for PathOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PathOptions")
+            .field("labels", &self.labels)
+            .field("max_depth", &self.max_depth)
+            .field("weight", &self.weight.is_some())
+            .field("heuristic", &self.heuristic.is_some())
+            .finish()
+    }
+}
+
+/// Nodes from the start to the target, in order, and the edges between
+/// them. The cost is the number of edges for unweighted searches.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Path {
+    pub nodes: Vec<NodeID>,
+    pub edges: Vec<EdgeItem>,
+    pub cost: f64,
+}
+
+/// Node reached by a search, pointing back to the step it was reached from.
+struct Step {
+    node: NodeID,
+    depth: usize,
+    cost: f64,
+    parent: Option<(usize, EdgeItem)>,
+}
+
+/// Step waiting in the Dijkstra queue, cheapest estimate first.
+struct Queued {
+    estimate: f64,
+    step: usize,
+}
+impl PartialEq for Queued {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl Eq for Queued {}
+impl PartialOrd for Queued {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Queued {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .estimate
+            .total_cmp(&self.estimate)
+            .then(other.step.cmp(&self.step))
+    }
+}
+
+/// Shortest path from `from` to `to` following outgoing edges, `None` when
+/// `to` can't be reached.
+pub(crate) async fn short_path<D: DB>(
+    db: &D,
+    from: NodeID,
+    to: NodeID,
+    options: &PathOptions,
+) -> Result<Option<Path>, DBError> {
+    match &options.weight {
+        None => _bfs(db, from, to, options).await,
+        Some(weight) => _dijkstra(db, from, to, options, weight.as_ref()).await,
+    }
+}
+
+async fn _bfs<D: DB>(
+    db: &D,
+    from: NodeID,
+    to: NodeID,
+    options: &PathOptions,
+) -> Result<Option<Path>, DBError> {
+    let mut steps = vec![Step {
+        node: from,
+        depth: 0,
+        cost: 0.0,
+        parent: None,
+    }];
+    let mut visited = HashSet::from([from]);
+    let mut queue = VecDeque::from([0]);
+
+    while let Some(index) = queue.pop_front() {
+        let (node, depth) = (steps[index].node, steps[index].depth);
+        if node == to {
+            return Ok(Some(_path(steps, index)));
+        }
+        if options
+            .max_depth
+            .is_some_and(|max_depth| depth >= max_depth)
+        {
+            continue;
+        }
+
+        let edges: Vec<EdgeItem> = db.out_edges(node, None).try_collect().await?;
+        for edge in edges {
+            if !options.follows(&edge) || !visited.insert(edge.to) {
+                continue;
+            }
+            queue.push_back(steps.len());
+            steps.push(Step {
+                node: edge.to,
+                depth: depth + 1,
+                cost: (depth + 1) as f64,
+                parent: Some((index, edge)),
+            });
+        }
+    }
+
+    Ok(None)
+}
+
+/// Steps are `(node, depth)` states when the depth is bounded, as the
+/// cheapest path may be too long and a costlier but shorter one needed.
+/// A node settled at some depth makes later states of it at that depth or
+/// deeper useless.
+async fn _dijkstra<D: DB>(
+    db: &D,
+    from: NodeID,
+    to: NodeID,
+    options: &PathOptions,
+    weight: &WeightFn,
+) -> Result<Option<Path>, DBError> {
+    let estimate = |node: NodeID, cost: f64| match &options.heuristic {
+        Some(heuristic) => cost + heuristic(node),
+        None => cost,
+    };
+    let mut steps = vec![Step {
+        node: from,
+        depth: 0,
+        cost: 0.0,
+        parent: None,
+    }];
+    let mut settled: HashMap<NodeID, usize> = HashMap::new();
+    let dominated = |settled: &HashMap<NodeID, usize>, node: NodeID, depth: usize| {
+        settled
+            .get(&node)
+            .is_some_and(|settled| options.max_depth.is_none() || *settled <= depth)
+    };
+    let mut queue = BinaryHeap::from([Queued {
+        estimate: estimate(from, 0.0),
+        step: 0,
+    }]);
+
+    while let Some(Queued { step: index, .. }) = queue.pop() {
+        let Step {
+            node, depth, cost, ..
+        } = steps[index];
+        if dominated(&settled, node, depth) {
+            continue;
+        }
+        settled.insert(node, depth);
+        if node == to {
+            return Ok(Some(_path(steps, index)));
+        }
+        if options
+            .max_depth
+            .is_some_and(|max_depth| depth >= max_depth)
+        {
+            continue;
+        }
+
+        let edges: Vec<EdgeItem> = db.out_edges(node, None).try_collect().await?;
+        for edge in edges {
+            if !options.follows(&edge) {
+                continue;
+            }
+            let Some(edge_cost) = weight(&edge.data) else {
+                continue;
+            };
+            if edge_cost.is_nan() || edge_cost < 0.0 {
+                return Err(DBError::QueryError {
+                    error: format!("invalid weight {} for edge {}", edge_cost, edge.key()),
+                });
+            }
+            if dominated(&settled, edge.to, depth + 1) {
+                continue;
+            }
+            queue.push(Queued {
+                estimate: estimate(edge.to, cost + edge_cost),
+                step: steps.len(),
+            });
+            steps.push(Step {
+                node: edge.to,
+                depth: depth + 1,
+                cost: cost + edge_cost,
+                parent: Some((index, edge)),
+            });
+        }
+    }
+
+    Ok(None)
+}
+
+fn _path(mut steps: Vec<Step>, mut index: usize) -> Path {
+    let cost = steps[index].cost;
+    let mut nodes = vec![steps[index].node];
+    let mut edges = Vec::new();
+    while let Some((parent, edge)) = steps[index].parent.take() {
+        nodes.push(steps[parent].node);
+        edges.push(edge);
+        index = parent;
+    }
+    nodes.reverse();
+    edges.reverse();
+    Path { nodes, edges, cost }
+}
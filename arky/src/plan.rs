@@ -0,0 +1,251 @@
+use crate::{
+    db::{DBError, DB},
+    node::Schema,
+    query::{entity_name, QueryOperation},
+};
+use futures::TryStreamExt;
+use std::fmt;
+
+/// Nodes a step must be known to leave at most for the index lookups after
+/// it to be checked on the loaded nodes instead.
+const RESIDUAL_LIMIT: usize = 32;
+
+/// How a step of a plan reaches its nodes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Access {
+    /// Compares the entity of the query, without reading anything.
+    EntityCheck,
+    Id,
+    UniqueIndex,
+    /// Edges between two given nodes.
+    Edge,
+    /// Edges of a given node.
+    Adjacency,
+    CompositeIndex,
+    Index,
+    GeoIndex,
+    FullTextIndex,
+    RangeIndex,
+    Path,
+    /// Every edge of the database.
+    EdgeScan,
+    VectorIndex,
+    /// Every node of the entity.
+    EntityScan,
+    /// Checked on the loaded nodes, as the selection before it is small.
+    Residual,
+    /// Checked on the loaded nodes, as no index serves it.
+    Filter,
+    /// Reads the nodes in the order of the index of the property.
+    IndexOrder(String),
+    /// Sorts the loaded nodes by the property.
+    Sort(String),
+}
+impl Access {
+    /// Order of the steps served from storage, cheapest first.
+    fn rank(&self) -> u8 {
+        match self {
+            Self::EntityCheck => 0,
+            Self::Id | Self::UniqueIndex => 1,
+            Self::Edge => 2,
+            Self::Adjacency => 3,
+            Self::CompositeIndex => 4,
+            Self::Index => 5,
+            Self::GeoIndex => 6,
+            Self::FullTextIndex => 7,
+            Self::RangeIndex => 8,
+            Self::Path => 9,
+            Self::EdgeScan => 10,
+            Self::VectorIndex => 11,
+            _ => 12,
+        }
+    }
+    /// Whether the estimate of the step is its actual size, or a bound.
+    fn is_exact(&self) -> bool {
+        matches!(
+            self,
+            Self::Id | Self::UniqueIndex | Self::Edge | Self::Adjacency
+        )
+    }
+    /// Whether the loaded nodes can be checked against the operation.
+    fn is_checked(&self) -> bool {
+        matches!(
+            self,
+            Self::UniqueIndex
+                | Self::CompositeIndex
+                | Self::Index
+                | Self::GeoIndex
+                | Self::RangeIndex
+        )
+    }
+    pub fn is_storage(&self) -> bool {
+        self.rank() < 12
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlanStep {
+    pub access: Access,
+    pub operation: Option<QueryOperation>,
+    /// Estimated number of nodes left after the step.
+    pub estimate: usize,
+}
+
+/// Steps a query runs, in order. Estimates of the index lookups are rough
+/// fractions of the nodes of the entity, while ids and adjacencies are
+/// counted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Plan {
+    pub steps: Vec<PlanStep>,
+}
+impl Plan {
+    pub fn estimate(&self) -> usize {
+        self.steps.last().map_or(0, |step| step.estimate)
+    }
+}
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, step) in self.steps.iter().enumerate() {
+            write!(f, "{}. {:?}", index + 1, step.access)?;
+            if let Some(operation) = &step.operation {
+                write!(f, " {:?}", operation)?;
+            }
+            writeln!(f, " (~{} nodes)", step.estimate)?;
+        }
+        Ok(())
+    }
+}
+
+/// Plans `operations` over the `total` nodes of the `schema` entity.
+/// Searches keep their ranking and paths their order wherever they run, and
+/// nearest neighbors are taken last, among the nodes selected by the other
+/// steps.
+pub(crate) async fn plan<D: DB>(
+    db: &D,
+    schema: &Schema,
+    operations: &[QueryOperation],
+    total: usize,
+    sort: Option<&str>,
+) -> Result<Plan, DBError> {
+    let fraction = |divisor: usize| (total / divisor).max(total.min(1));
+    let index = |field: &str| schema.index(field);
+
+    let mut steps = Vec::with_capacity(operations.len());
+    for operation in operations {
+        let (access, estimate) = match operation {
+            QueryOperation::ByID(_) => (Access::Id, 1),
+            QueryOperation::ByEntityName(name) => {
+                let estimate = if entity_name(name) == schema.entity {
+                    total
+                } else {
+                    0
+                };
+                (Access::EntityCheck, estimate)
+            }
+            QueryOperation::ByEdge(..) => (Access::Edge, 2),
+            QueryOperation::ByEdgeFrom(from) => {
+                let count = db
+                    .out_edges(*from, None)
+                    .try_fold(0, |n, _| async move { Ok(n + 1) });
+                (Access::Adjacency, count.await?)
+            }
+            QueryOperation::ByEdgeTo(to) => {
+                let count = db
+                    .in_edges(*to, None)
+                    .try_fold(0, |n, _| async move { Ok(n + 1) });
+                (Access::Adjacency, count.await?)
+            }
+            QueryOperation::ByEdgeLabel(_) | QueryOperation::ByEdgeData(_) => {
+                (Access::EdgeScan, total)
+            }
+            QueryOperation::ByIndex(field, _) => match index(field) {
+                Some(index) if index.unique => (Access::UniqueIndex, 1),
+                Some(index) if index.is_equality() => (Access::Index, fraction(10)),
+                _ => (Access::Filter, total),
+            },
+            QueryOperation::ByIndexRange(field, ..) => match index(field) {
+                Some(index) if index.range => (Access::RangeIndex, fraction(3)),
+                _ => (Access::Filter, total),
+            },
+            QueryOperation::ByCompositeIndex(fields, _) => {
+                let served = schema
+                    .composite_indexes
+                    .iter()
+                    .any(|index| index.fields.starts_with(fields));
+                match served {
+                    true => (Access::CompositeIndex, fraction(100)),
+                    false => (Access::Filter, total),
+                }
+            }
+            QueryOperation::Search(field, _) => match index(field) {
+                Some(index) if index.fulltext => (Access::FullTextIndex, fraction(5)),
+                _ => (Access::Filter, total),
+            },
+            QueryOperation::Within(field, _) => match index(field) {
+                Some(index) if index.geo => (Access::GeoIndex, fraction(10)),
+                _ => (Access::Filter, total),
+            },
+            QueryOperation::Nearest(_, _, k) => (Access::VectorIndex, *k),
+            QueryOperation::ShortPath(_, _, options) => {
+                let estimate = options
+                    .max_depth
+                    .map_or(total, |depth| depth.saturating_add(1));
+                (Access::Path, estimate)
+            }
+            QueryOperation::Filter(_) | QueryOperation::FilterByProp(..) => (Access::Filter, total),
+        };
+        steps.push(PlanStep {
+            access,
+            operation: Some(operation.clone()),
+            estimate,
+        });
+    }
+    steps.sort_by_key(|step| (step.access.rank(), step.estimate));
+
+    let mut bound: Option<usize> = None;
+    for step in &mut steps {
+        if bound.is_some_and(|bound| bound <= RESIDUAL_LIMIT) && step.access.is_checked() {
+            step.access = Access::Residual;
+        } else if step.access.is_exact() {
+            bound = Some(bound.map_or(step.estimate, |bound| bound.min(step.estimate)));
+        }
+    }
+    if !steps
+        .iter()
+        .any(|step| step.access.is_storage() && step.access != Access::EntityCheck)
+    {
+        let position = steps
+            .iter()
+            .position(|step| step.access != Access::EntityCheck)
+            .unwrap_or(steps.len());
+        steps.insert(
+            position,
+            PlanStep {
+                access: Access::EntityScan,
+                operation: None,
+                estimate: total,
+            },
+        );
+    }
+    if let Some(prop) = sort {
+        let ordered = index(prop).is_some_and(|index| index.range);
+        steps.push(PlanStep {
+            access: match ordered {
+                true => Access::IndexOrder(prop.to_string()),
+                false => Access::Sort(prop.to_string()),
+            },
+            operation: None,
+            estimate: 0,
+        });
+    }
+
+    let mut rows = total;
+    for step in &mut steps {
+        match step.access {
+            Access::Residual | Access::Filter | Access::IndexOrder(_) | Access::Sort(_) => {}
+            _ => rows = rows.min(step.estimate),
+        }
+        step.estimate = rows;
+    }
+    Ok(Plan { steps })
+}
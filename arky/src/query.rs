@@ -1,64 +1,267 @@
-use crate::db::DB;
-use arkycore::types::{Data, DynResult, NodeID};
+use crate::{
+    aggregate::{Aggregate, AggregateRow, Aggregator},
+    cursor::{fingerprint, Cursor, Page, Position},
+    db::{DBError, NodeStream, Transaction, DB},
+    edge::EdgeItem,
+    index::{to_vector, tokenize, IndexRange},
+    node::{Node, Schema},
+    path::{short_path, Path, PathOptions},
+    plan::{plan, Plan},
+    reader::{Item, Reader, Typed},
+    select::Select,
+};
+use arkycore::types::{Data, GeoArea, GeoPoint, NodeID, Value};
+use arkycore::utils;
+use futures::stream::{self, BoxStream};
+use futures::{future, StreamExt, TryStreamExt};
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::ops::{Bound, RangeBounds};
+use std::sync::Arc;
 
+/// Nodes loaded at once when reading them in index order.
+const SCAN_CHUNK: usize = 128;
+
+type PredicateFn = dyn Fn(&dyn Any) -> Option<bool> + Send + Sync;
+
+/// Type-erased closure used by `QueryOperation::Filter`. It only matches
+/// nodes of the type it was created for, and combines with `and`, `or` and
+/// `!` into a single operation.
+#[derive(Clone)]
+pub struct Predicate(Arc<PredicateFn>);
+impl Predicate {
+    pub fn new<T: Node>(cb: impl Fn(&T) -> bool + Send + Sync + 'static) -> Self {
+        Self(Arc::new(move |node: &dyn Any| {
+            node.downcast_ref::<T>().map(&cb)
+        }))
+    }
+    /// `None` when the predicate was created for another type than `T`.
+    pub fn test<T: Node>(&self, node: &T) -> Option<bool> {
+        (self.0)(node)
+    }
+    pub fn and(&self, other: &Predicate) -> Self {
+        let (a, b) = (self.0.clone(), other.0.clone());
+        Self(Arc::new(move |node: &dyn Any| Some(a(node)? && b(node)?)))
+    }
+    pub fn or(&self, other: &Predicate) -> Self {
+        let (a, b) = (self.0.clone(), other.0.clone());
+        Self(Arc::new(move |node: &dyn Any| Some(a(node)? || b(node)?)))
+    }
+}
+impl std::ops::Not for Predicate {
+    type Output = Self;
+
+    fn not(self) -> Self {
+        Self(Arc::new(move |node: &dyn Any| {
+            (self.0)(node).map(|matched| !matched)
+        }))
+    }
+}
+impl PartialEq for Predicate {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+impl Eq for Predicate {}
+impl fmt::Debug for Predicate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Predicate")
+    }
+}
+
+/// Query vector of `QueryOperation::Nearest`, compared bit for bit.
+#[derive(Debug, Clone)]
+pub struct Embedding(pub Vec<f32>);
+impl PartialEq for Embedding {
+    fn eq(&self, other: &Self) -> bool {
+        let bits = |vector: &[f32]| vector.iter().map(|x| x.to_bits()).collect::<Vec<_>>();
+        bits(&self.0) == bits(&other.0)
+    }
+}
+impl Eq for Embedding {}
+
+/// Edge based operations select the endpoints of the matched edges, except
+/// `ByEdgeFrom`/`ByEdgeTo` which select the opposite endpoint only.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum QueryOperation {
     ByID(NodeID),
-    ByIndex(String, Data),
+    ByIndex(String, Value),
+    ByIndexRange(String, Bound<Value>, Bound<Value>),
+    ByCompositeIndex(Vec<String>, Vec<Value>),
+    Search(String, String),
+    Nearest(String, Embedding, usize),
+    Within(String, GeoArea),
     ByEntityName(String),
     ByEdge(NodeID, NodeID),
     ByEdgeLabel(String),
     ByEdgeData(Data),
     ByEdgeFrom(NodeID),
     ByEdgeTo(NodeID),
-    // Filter(Box<dyn Fn(&dyn Node) -> bool>),
-    FilterByProp(String, Data),
-    ShortPath(NodeID, NodeID),
+    Filter(Predicate),
+    FilterByProp(String, Value),
+    ShortPath(NodeID, NodeID, PathOptions),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct QueryBuilder<'a, D: DB> {
     db: &'a D,
+    operations: Vec<QueryOperation>,
 }
 impl<'a, D: DB> QueryBuilder<'a, D> {
     pub fn new(db: &'a D) -> Self {
-        Self { db }
+        Self {
+            db,
+            operations: Vec::new(),
+        }
+    }
+    fn push(&mut self, operation: QueryOperation) -> &mut Self {
+        self.operations.push(operation);
+        self
+    }
+    pub fn operations(&self) -> &[QueryOperation] {
+        &self.operations
+    }
+    pub fn by_id(&mut self, id: &NodeID) -> &mut Self {
+        self.push(QueryOperation::ByID(*id))
+    }
+    /// Served by the secondary index when `index` is declared with
+    /// `#[index]` on the node, by filtering the loaded nodes otherwise.
+    pub fn by_index<C: Into<Value>>(&mut self, index: &str, value: C) -> &mut Self {
+        self.push(QueryOperation::ByIndex(index.to_string(), value.into()))
     }
-    pub fn by_id(&self, id: &NodeID) -> &Self {
-        todo!()
+    /// Served by scanning the secondary index when `index` is declared with
+    /// `#[index(range)]` on the node. A range bounded on one side only
+    /// matches values of the type of its bound.
+    pub fn by_index_range<C: Into<Value> + Clone>(
+        &mut self,
+        index: &str,
+        range: impl RangeBounds<C>,
+    ) -> &mut Self {
+        let start = range.start_bound().cloned().map(Into::into);
+        let end = range.end_bound().cloned().map(Into::into);
+        self.push(QueryOperation::ByIndexRange(index.to_string(), start, end))
     }
-    pub fn by_index<C>(&self, index: &str, value: C) -> &Self {
-        todo!()
+    /// Matches nodes equal to `values` on each of `fields`. Served by a
+    /// composite index declared with `#[index(...)]` on the node when
+    /// `fields` are its leading fields, by filtering the loaded nodes
+    /// otherwise.
+    pub fn by_composite_index(&mut self, fields: &[&str], values: &[Value]) -> &mut Self {
+        let fields = fields.iter().map(|field| field.to_string()).collect();
+        self.push(QueryOperation::ByCompositeIndex(fields, values.to_vec()))
     }
-    pub fn by_entity_name(&self, entity_name: String) -> &Self {
-        todo!()
+    /// Matches nodes whose `field` contains any word of `terms`, ranked by
+    /// BM25 unless sorted otherwise. Served by the full-text index when
+    /// `field` is declared with `#[index(fulltext)]`, by filtering the
+    /// loaded nodes without ranking otherwise.
+    pub fn search(&mut self, field: &str, terms: &str) -> &mut Self {
+        self.push(QueryOperation::Search(field.to_string(), terms.to_string()))
     }
-    pub fn by_edge(&self, from: &NodeID, to: &NodeID) -> &Self {
-        todo!()
+    /// Keeps the `k` nodes whose `field`, declared with `#[index(vector)]`,
+    /// is the closest to `query`, closest first unless sorted otherwise.
+    /// Applies to the nodes selected by the other operations, and searches
+    /// the vector index when there are none.
+    pub fn nearest(&mut self, field: &str, query: &[f32], k: usize) -> &mut Self {
+        let query = Embedding(query.to_vec());
+        self.push(QueryOperation::Nearest(field.to_string(), query, k))
     }
-    pub fn by_edge_label(&self, edge_type: &str) -> &Self {
-        todo!()
+    /// Matches nodes whose `field`, a `GeoPoint` or a `(lat, lon)` tuple, is
+    /// at most `meters` away from `center`. Served by the geo index when
+    /// `field` is declared with `#[index(geo)]`, by filtering the loaded
+    /// nodes otherwise.
+    pub fn within_radius(&mut self, field: &str, center: GeoPoint, meters: f64) -> &mut Self {
+        let area = GeoArea::Radius { center, meters };
+        self.push(QueryOperation::Within(field.to_string(), area))
     }
-    pub fn by_edge_data(&self, data: &Data) -> &Self {
-        todo!()
+    /// Matches nodes whose `field` lies within the box between the corners.
+    /// The box crosses the antimeridian when `south_west` is east of
+    /// `north_east`.
+    pub fn within_bbox(
+        &mut self,
+        field: &str,
+        south_west: GeoPoint,
+        north_east: GeoPoint,
+    ) -> &mut Self {
+        let area = GeoArea::BBox {
+            south_west,
+            north_east,
+        };
+        self.push(QueryOperation::Within(field.to_string(), area))
     }
-    pub fn by_edge_from(&self, from: &NodeID) -> &Self {
-        todo!()
+    pub fn by_entity_name(&mut self, entity_name: String) -> &mut Self {
+        self.push(QueryOperation::ByEntityName(entity_name))
     }
-    pub fn by_edge_to(&self, to: &NodeID) -> &Self {
-        todo!()
+    pub fn by_edge(&mut self, from: &NodeID, to: &NodeID) -> &mut Self {
+        self.push(QueryOperation::ByEdge(*from, *to))
     }
-    pub fn filter<T>(&self, cb: &impl Fn(&T) -> bool) -> &Self {
-        todo!()
+    pub fn by_edge_label(&mut self, edge_type: &str) -> &mut Self {
+        self.push(QueryOperation::ByEdgeLabel(edge_type.to_string()))
     }
-    pub fn filter_by_prop<C>(&self, prop: &str, value: C) -> &Self {
-        todo!()
+    pub fn by_edge_data(&mut self, data: &Data) -> &mut Self {
+        self.push(QueryOperation::ByEdgeData(data.clone()))
     }
-    pub fn short_path(&self, from: &NodeID, to: &NodeID) -> &Self {
-        todo!()
+    pub fn by_edge_from(&mut self, from: &NodeID) -> &mut Self {
+        self.push(QueryOperation::ByEdgeFrom(*from))
     }
-    pub fn build(&self) -> DynResult<QueryExecutor<&'a D>> {
-        todo!()
+    pub fn by_edge_to(&mut self, to: &NodeID) -> &mut Self {
+        self.push(QueryOperation::ByEdgeTo(*to))
+    }
+    pub fn filter<T: Node>(
+        &mut self,
+        cb: impl Fn(&T) -> bool + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.push(QueryOperation::Filter(Predicate::new(cb)))
+    }
+    pub fn filter_with(&mut self, predicate: &Predicate) -> &mut Self {
+        self.push(QueryOperation::Filter(predicate.clone()))
+    }
+    pub fn filter_by_prop<C: Into<Value>>(&mut self, prop: &str, value: C) -> &mut Self {
+        self.push(QueryOperation::FilterByProp(prop.to_string(), value.into()))
+    }
+    /// Matches the nodes on the path with the fewest edges from `from` to
+    /// `to`, in path order. `QueryExecutor::path` gives the edges too.
+    pub fn short_path(&mut self, from: &NodeID, to: &NodeID) -> &mut Self {
+        self.short_path_with(from, to, PathOptions::default())
+    }
+    /// Same as `short_path`, restricted and weighted by `options`.
+    pub fn short_path_with(
+        &mut self,
+        from: &NodeID,
+        to: &NodeID,
+        options: PathOptions,
+    ) -> &mut Self {
+        self.push(QueryOperation::ShortPath(*from, *to, options))
+    }
+    pub fn build(&self) -> Result<QueryExecutor<'a, D>, DBError> {
+        let mismatched = self.operations.iter().any(|operation| {
+            matches!(operation, QueryOperation::ByCompositeIndex(fields, values)
+                if fields.len() != values.len())
+        });
+        if mismatched {
+            return Err(DBError::QueryError {
+                error: "by_composite_index needs one value per field".to_string(),
+            });
+        }
+        let paths = self
+            .operations
+            .iter()
+            .filter(|operation| matches!(operation, QueryOperation::ShortPath(..)))
+            .count();
+        if paths > 1 {
+            return Err(DBError::QueryError {
+                error: "a query can only look for one short_path".to_string(),
+            });
+        }
+
+        Ok(QueryExecutor {
+            db: self.db,
+            operations: self.operations.clone(),
+            sort: None,
+            group: None,
+            skip: 0,
+            limit: None,
+            after: None,
+        })
     }
 }
 
@@ -66,28 +269,772 @@ impl<'a, D: DB> QueryBuilder<'a, D> {
 pub struct QueryExecutor<'a, D> {
     db: &'a D,
     operations: Vec<QueryOperation>,
-    result: Vec<Data>,
+    sort: Option<String>,
+    group: Option<String>,
+    skip: usize,
+    limit: Option<usize>,
+    after: Option<Cursor>,
 }
-impl<'a, D: DB> QueryExecutor<'a, D> {
-    pub fn sort_by_prop(&self, prop: &str) -> &Self {
-        todo!()
+impl<'a, D: DB + Sync> QueryExecutor<'a, D> {
+    pub fn operations(&self) -> &[QueryOperation] {
+        &self.operations
+    }
+    /// Nodes are read in index order when `prop` is declared with
+    /// `#[index(range)]`, so only the ones up to `skip + limit` are loaded.
+    pub fn sort_by_prop(&mut self, prop: &str) -> &mut Self {
+        self.sort = Some(prop.to_string());
+        self
+    }
+    /// Splits the rows of `aggregate`/`aggregate_edges` by the value of
+    /// `prop`.
+    pub fn group_by(&mut self, prop: &str) -> &mut Self {
+        self.group = Some(prop.to_string());
+        self
+    }
+    /// Adds a filter to the built query, checked like the ones given to
+    /// `QueryBuilder::filter`.
+    pub fn filter<T: Node>(
+        &mut self,
+        cb: impl Fn(&T) -> bool + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.operations
+            .push(QueryOperation::Filter(Predicate::new(cb)));
+        self
+    }
+    /// Resumes `page` after the last node of the page `cursor` came with.
+    pub fn after(&mut self, cursor: &Cursor) -> &mut Self {
+        self.after = Some(cursor.clone());
+        self
+    }
+    pub fn limit(&mut self, limit: usize) -> &mut Self {
+        self.limit = Some(limit);
+        self
     }
-    pub fn limit(&self, limit: usize) -> &Self {
-        todo!()
+    pub fn skip(&mut self, skip: usize) -> &mut Self {
+        self.skip = skip;
+        self
     }
-    pub fn skip(&self, skip: usize) -> &Self {
-        todo!()
+    /// Path searched by `short_path`, `None` when the target can't be
+    /// reached. Other operations don't apply to it.
+    pub async fn path(&self) -> Result<Option<Path>, DBError> {
+        let operation = self
+            .operations
+            .iter()
+            .find_map(|operation| match operation {
+                QueryOperation::ShortPath(from, to, options) => Some((from, to, options)),
+                _ => None,
+            });
+        let Some((from, to, options)) = operation else {
+            return Err(DBError::QueryError {
+                error: "path needs a short_path operation".to_string(),
+            });
+        };
+        short_path(self.db, *from, *to, options).await
     }
-    pub fn count(&self) -> DynResult<usize> {
-        todo!()
+    /// Plan the query runs with, estimated over the nodes of `T`.
+    pub async fn explain<T: Node>(&self) -> Result<Plan, DBError> {
+        let schema = Schema::of::<T>();
+        let total = self.db.get_node_ids(&schema.entity).await?.len();
+        plan(
+            self.db,
+            &schema,
+            &self.operations,
+            total,
+            self.sort.as_deref(),
+        )
+        .await
+    }
+    pub async fn count<T: Node>(&self) -> Result<usize, DBError> {
+        Ok(self.matches(Typed::<T>::new()).await?.len())
     }
     // pub fn sort(&self, cb: &impl Fn(&dyn Node, &dyn Node) -> bool) -> &Self {
     //     todo!()
     // }
-    // pub fn update(&self, cb: &impl Fn<T: Node>(&mut T) -> impl Node) -> &Self {
-    //     todo!()
-    // }
-    pub fn exec<R>(&self) -> DynResult<R> {
-        todo!()
+    /// Projects the matching nodes to `props`, reading the entities given
+    /// to `Select::from` by name.
+    pub fn select(&self, props: &[&str]) -> Select<'_, 'a, D> {
+        Select::new(self, props)
+    }
+    /// Applies `cb` to the nodes `exec` returns and writes them back in one
+    /// transaction, keeping their indexes up to date. The nodes are read
+    /// again in the transaction and dropped when they no longer match the
+    /// query's properties, and the commit fails if any of them was written
+    /// in the meantime. `cb` can't change the id of a node. Returns the
+    /// number of updated nodes.
+    pub async fn update<T: Node>(
+        &self,
+        mut cb: impl FnMut(&mut T) + Send,
+    ) -> Result<usize, DBError> {
+        let ids: Vec<NodeID> = self.exec::<T>().await?.iter().map(T::key).collect();
+        let schema = Schema::of::<T>();
+        let update = |tx: D::Transaction<'a>| async move {
+            let mut nodes = Vec::with_capacity(ids.len());
+            for id in ids {
+                nodes.push(tx.get_node::<T>(id).await?);
+            }
+            let mut nodes = self.retain(&schema, nodes)?;
+            for node in &mut nodes {
+                let id = node.key();
+                cb(node);
+                if node.key() != id {
+                    return Err(DBError::QueryError {
+                        error: format!("update changed the id of node {}", id),
+                    });
+                }
+                tx.insert_node(node).await?;
+            }
+            Ok(nodes.len())
+        };
+        self.db.transaction(update).await
+    }
+    /// Removes the nodes `exec` returns, with their edges, in one write.
+    /// Returns the number of removed nodes.
+    pub async fn delete<T: Node>(&self) -> Result<usize, DBError> {
+        let nodes = self.exec::<T>().await?;
+        self.db.remove_nodes_with_edges(&nodes).await?;
+        Ok(nodes.len())
+    }
+    pub async fn exec<T: Node>(&self) -> Result<Vec<T>, DBError> {
+        self.read(&[Typed::<T>::new()]).await
+    }
+    /// Page of at most `limit` nodes, starting after the cursor given to
+    /// `after`. Pages follow `sort_by_prop` when set. Otherwise a query
+    /// selecting a whole entity pages in storage order, and one narrowed by
+    /// indexes or edges in id order. A page sorted on a range index or in
+    /// storage order only reads the index keys and nodes it needs. A page
+    /// narrowed by indexes or edges first gathers the ids of every
+    /// candidate, and one sorted on a property without range index loads and
+    /// sorts every matching node, so each of their pages costs as much as
+    /// the whole query. `skip` doesn't apply to pages, and queries with a
+    /// `filter` can't be paged, as their cursors couldn't tell the closures
+    /// apart.
+    pub async fn page<T: Node>(&self) -> Result<Page<T>, DBError> {
+        self.read_page(&[Typed::<T>::new()]).await
+    }
+    /// Aggregates the properties of the matching nodes, reading them as
+    /// `exec_stream` does.
+    pub async fn aggregate<T: Node>(
+        &self,
+        aggregates: &[Aggregate],
+    ) -> Result<Vec<AggregateRow>, DBError> {
+        let mut aggregator = Aggregator::new(self.group.as_deref(), aggregates);
+        let mut nodes = self.exec_stream::<T>();
+        while let Some(node) = nodes.try_next().await? {
+            let value = node.to_value().map_err(|e| DBError::QueryError {
+                error: e.to_string(),
+            })?;
+            aggregator.add(&value)?;
+        }
+        Ok(aggregator.rows())
+    }
+    /// Aggregates the outgoing `label` edges of the matching nodes. Their
+    /// properties are `from`, `to`, `label` and the fields of the payload,
+    /// or `data` when the payload isn't a struct.
+    pub async fn aggregate_edges<T: Node>(
+        &self,
+        label: &str,
+        aggregates: &[Aggregate],
+    ) -> Result<Vec<AggregateRow>, DBError> {
+        let mut aggregator = Aggregator::new(self.group.as_deref(), aggregates);
+        let mut nodes = self.exec_stream::<T>();
+        while let Some(node) = nodes.try_next().await? {
+            let mut edges = self.db.out_edges(node.key(), Some(label));
+            while let Some(edge) = edges.try_next().await? {
+                aggregator.add(&edge_value(&edge)?)?;
+            }
+        }
+        Ok(aggregator.rows())
+    }
+    /// Matching nodes read incrementally. Index selections and sorts on a
+    /// range index load `SCAN_CHUNK` nodes at a time, and a query selecting
+    /// the whole entity follows a storage iterator. A sort on a property
+    /// without range index still loads every match first.
+    pub fn exec_stream<T: Node>(&self) -> NodeStream<'_, T> {
+        let reader = Typed::<T>::new();
+        let batches = async move {
+            let batches = match &self.sort {
+                Some(prop) if is_range_index(&Reader::<D>::schema(&reader), prop) => {
+                    self.chunks(reader, self.sorted_ids(reader, prop, None).await?)
+                }
+                Some(prop) => {
+                    let nodes = sort_nodes(self.matches(reader).await?, prop)?;
+                    stream::once(future::ready(Ok(nodes))).boxed()
+                }
+                None => match self.candidates(reader).await? {
+                    Some(ids) => self.chunks(reader, stream::iter(ids.into_iter().map(Ok)).boxed()),
+                    None => self.scan(reader, None),
+                },
+            };
+            Ok::<_, DBError>(batches)
+        };
+
+        let mut skipped = 0;
+        stream::once(batches)
+            .try_flatten()
+            .map_ok(|nodes| stream::iter(nodes.into_iter().map(Ok)))
+            .try_flatten()
+            .try_skip_while(move |_| {
+                skipped += 1;
+                future::ready(Ok(skipped <= self.skip))
+            })
+            .take(self.limit.unwrap_or(usize::MAX))
+            .boxed()
     }
+
+    /// Matching nodes of `readers`, sorted, skipped and limited together.
+    /// Without sort, the nodes of each reader follow the ones of the reader
+    /// before it.
+    pub(crate) async fn read<R: Reader<D>>(&self, readers: &[R]) -> Result<Vec<R::Node>, DBError> {
+        let limit = self.limit.unwrap_or(usize::MAX);
+        let wanted = self.skip.saturating_add(limit);
+        let mut nodes = Vec::new();
+        for reader in readers {
+            if self.sort.is_none() && nodes.len() >= wanted {
+                break;
+            }
+            nodes.extend(self.first(*reader, wanted).await?);
+        }
+        if let Some(prop) = self.sort.as_ref().filter(|_| readers.len() > 1) {
+            nodes = sort_nodes(nodes, prop)?;
+        }
+
+        Ok(nodes.into_iter().skip(self.skip).take(limit).collect())
+    }
+    /// Matching nodes of `reader` in the order of the query, at least the
+    /// first `wanted` of them. Sorting on a range index only loads those.
+    async fn first<R: Reader<D>>(&self, reader: R, wanted: usize) -> Result<Vec<R::Node>, DBError> {
+        let sort = self.sort.as_ref();
+        if let Some(prop) = sort.filter(|prop| is_range_index(&reader.schema(), prop)) {
+            return self.scan_sorted(reader, prop, wanted).await;
+        }
+
+        let mut nodes = self.matches(reader).await?;
+        if let Some(prop) = sort {
+            nodes = sort_nodes(nodes, prop)?;
+        }
+        Ok(nodes)
+    }
+
+    /// Page of the matching nodes of `readers`, in the order `page`
+    /// documents. Pages of several readers are merged on the sort, or go
+    /// through the readers one after the other without it.
+    pub(crate) async fn read_page<R: Reader<D>>(
+        &self,
+        readers: &[R],
+    ) -> Result<Page<R::Node>, DBError> {
+        let filtered = self
+            .operations
+            .iter()
+            .any(|operation| matches!(operation, QueryOperation::Filter(_)));
+        if filtered {
+            return Err(DBError::QueryError {
+                error: "queries with a filter can't be paged".to_string(),
+            });
+        }
+        let entities: Vec<String> = readers
+            .iter()
+            .map(|reader| reader.schema().entity)
+            .collect();
+        let query = format!("{}{:?}{:?}", entities.join(","), self.operations, self.sort);
+        let query = fingerprint(&query);
+        let after = match &self.after {
+            Some(cursor) => Some(Position::from_cursor(cursor, query)?),
+            None => None,
+        };
+        // Without sort, the readers before the one of the cursor are done.
+        let start = match (&self.sort, &after) {
+            (None, Some(after)) => entities
+                .iter()
+                .position(|entity| *entity == after.entity)
+                .unwrap_or_default(),
+            _ => 0,
+        };
+
+        let size = self.limit.unwrap_or(usize::MAX);
+        let mut nodes = Vec::new();
+        let mut owners = HashMap::new();
+        for (index, reader) in readers.iter().enumerate().skip(start) {
+            if self.sort.is_none() && nodes.len() > size {
+                break;
+            }
+            let after = match self.sort.is_some() || index == start {
+                true => after.clone(),
+                false => None,
+            };
+            let page: Vec<R::Node> = self
+                .page_batches(*reader, after)
+                .await?
+                .map_ok(|nodes| stream::iter(nodes.into_iter().map(Ok)))
+                .try_flatten()
+                .take(size.saturating_add(1))
+                .try_collect()
+                .await?;
+            owners.extend(page.iter().map(|node| (node.id(), index)));
+            nodes.extend(page);
+        }
+        if let Some(prop) = self.sort.as_ref().filter(|_| readers.len() > 1) {
+            nodes = sort_nodes(nodes, prop)?;
+        }
+        if nodes.len() <= size {
+            return Ok(Page { nodes, next: None });
+        }
+
+        nodes.truncate(size);
+        let next = match nodes.last() {
+            Some(last) => {
+                let value = match &self.sort {
+                    Some(prop) => Some(prop_value(last, prop)?),
+                    None => None,
+                };
+                let id = last.id();
+                let entity = entities[owners[&id]].clone();
+                Position {
+                    query,
+                    id,
+                    entity,
+                    value,
+                }
+                .to_cursor()
+            }
+            // An empty page starts where it was asked to.
+            None => match &self.after {
+                Some(cursor) => cursor.clone(),
+                None => return Ok(Page { nodes, next: None }),
+            },
+        };
+        Ok(Page {
+            nodes,
+            next: Some(next),
+        })
+    }
+
+    /// Matching nodes among `ids`, loaded `SCAN_CHUNK` at a time, or as many
+    /// as the query can return when fewer, so small pages read few ids.
+    fn chunks<R: Reader<D>>(
+        &self,
+        reader: R,
+        ids: BoxStream<'a, Result<NodeID, DBError>>,
+    ) -> BoxStream<'_, Result<Vec<R::Node>, DBError>> {
+        let schema = reader.schema();
+        let size = match self.limit {
+            Some(limit) => self.skip.saturating_add(limit).saturating_add(1),
+            None => SCAN_CHUNK,
+        };
+        ids.chunks(size.min(SCAN_CHUNK))
+            .then(move |chunk| {
+                let schema = schema.clone();
+                async move {
+                    let chunk = chunk.into_iter().collect::<Result<Vec<_>, _>>()?;
+                    self.retain(&schema, reader.load(self.db, &chunk).await?)
+                }
+            })
+            .boxed()
+    }
+
+    /// Matching nodes of the whole entity, following a storage iterator.
+    fn scan<R: Reader<D>>(
+        &self,
+        reader: R,
+        after: Option<NodeID>,
+    ) -> BoxStream<'_, Result<Vec<R::Node>, DBError>> {
+        let schema = reader.schema();
+        reader
+            .scan(self.db, after)
+            .map(move |node| self.retain(&schema, vec![node?]))
+            .boxed()
+    }
+
+    /// Next page of the candidates, in the order `page` documents.
+    async fn page_batches<R: Reader<D>>(
+        &self,
+        reader: R,
+        after: Option<Position>,
+    ) -> Result<BoxStream<'_, Result<Vec<R::Node>, DBError>>, DBError> {
+        let batches = match &self.sort {
+            Some(prop) if is_range_index(&reader.schema(), prop) => {
+                self.chunks(reader, self.sorted_ids(reader, prop, after.as_ref()).await?)
+            }
+            // Without an index to follow, every page sorts the whole query.
+            Some(prop) => {
+                let mut nodes = sort_nodes(self.matches(reader).await?, prop)?;
+                if let Some(after) = after {
+                    let start = (after.value.unwrap_or(Value::Null), after.id.0);
+                    let mut kept = Vec::with_capacity(nodes.len());
+                    for node in nodes {
+                        if (prop_value(&node, prop)?, node.id().0) > start {
+                            kept.push(node);
+                        }
+                    }
+                    nodes = kept;
+                }
+                stream::once(future::ready(Ok(nodes))).boxed()
+            }
+            None => match self.candidates(reader).await? {
+                Some(mut ids) => {
+                    ids.sort_by_key(|id| id.0);
+                    ids.retain(|id| after.as_ref().is_none_or(|after| id.0 > after.id.0));
+                    self.chunks(reader, stream::iter(ids.into_iter().map(Ok)).boxed())
+                }
+                None => self.scan(reader, after.map(|after| after.id)),
+            },
+        };
+        Ok(batches)
+    }
+
+    /// Ids of the candidates in the order of the `prop` index, starting
+    /// after the node at `after`. The index is read as the ids are.
+    async fn sorted_ids<R: Reader<D>>(
+        &self,
+        reader: R,
+        prop: &str,
+        after: Option<&Position>,
+    ) -> Result<BoxStream<'a, Result<NodeID, DBError>>, DBError> {
+        let entity = reader.schema().entity;
+        let after = after.map(|after| (after.value.as_ref().unwrap_or(&Value::Null), after.id));
+        let ids = self.db.scan_index_ids(&entity, prop, after);
+        let ids = match self.candidates(reader).await? {
+            Some(candidates) => {
+                let candidates: HashSet<NodeID> = candidates.into_iter().collect();
+                ids.try_filter(move |id| future::ready(candidates.contains(id)))
+                    .boxed()
+            }
+            None => ids,
+        };
+        Ok(ids)
+    }
+
+    /// Loads the matching nodes in the order of the `prop` index, stopping
+    /// once `wanted` of them are found.
+    async fn scan_sorted<R: Reader<D>>(
+        &self,
+        reader: R,
+        prop: &str,
+        wanted: usize,
+    ) -> Result<Vec<R::Node>, DBError> {
+        let mut batches = self.chunks(reader, self.sorted_ids(reader, prop, None).await?);
+        let mut nodes = Vec::new();
+        while nodes.len() < wanted {
+            match batches.try_next().await? {
+                Some(batch) => nodes.extend(batch),
+                None => break,
+            }
+        }
+        Ok(nodes)
+    }
+
+    async fn matches<R: Reader<D>>(&self, reader: R) -> Result<Vec<R::Node>, DBError> {
+        let schema = reader.schema();
+        let ids = match self.candidates(reader).await? {
+            Some(ids) => ids,
+            None => self.db.get_node_ids(&schema.entity).await?,
+        };
+        let nodes = reader.load(self.db, &ids).await?;
+        self.retain(&schema, nodes)
+    }
+
+    /// Ids selected by the steps of the plan served from storage, `None`
+    /// when none of them narrows the entity down.
+    async fn candidates<R: Reader<D>>(&self, reader: R) -> Result<Option<Vec<NodeID>>, DBError> {
+        let schema = reader.schema();
+        let plan = plan(self.db, &schema, &self.operations, usize::MAX, None).await?;
+        let mut candidates: Option<Vec<NodeID>> = None;
+        let operations = plan
+            .steps
+            .iter()
+            .filter(|step| step.access.is_storage())
+            .filter_map(|step| step.operation.as_ref());
+        for operation in operations {
+            match operation {
+                QueryOperation::ByID(id) => narrow(&mut candidates, vec![*id]),
+                QueryOperation::ByEntityName(name) => {
+                    if entity_name(name) != schema.entity {
+                        return Ok(Some(Vec::new()));
+                    }
+                }
+                QueryOperation::ByEdge(from, to) => {
+                    let edges = self.db.get_edges(*from, *to).await?;
+                    narrow(
+                        &mut candidates,
+                        edges.iter().flat_map(|e| [e.from, e.to]).collect(),
+                    );
+                }
+                QueryOperation::ByEdgeLabel(label) => narrow(
+                    &mut candidates,
+                    self.edge_endpoints(|edge| &edge.label == label).await?,
+                ),
+                QueryOperation::ByEdgeData(data) => narrow(
+                    &mut candidates,
+                    self.edge_endpoints(|edge| &edge.data == data).await?,
+                ),
+                QueryOperation::ByEdgeFrom(from) => {
+                    let edges: Vec<EdgeItem> = self.db.out_edges(*from, None).try_collect().await?;
+                    narrow(&mut candidates, edges.iter().map(|e| e.to).collect())
+                }
+                QueryOperation::ByEdgeTo(to) => {
+                    let edges: Vec<EdgeItem> = self.db.in_edges(*to, None).try_collect().await?;
+                    narrow(&mut candidates, edges.iter().map(|e| e.from).collect())
+                }
+                QueryOperation::ByIndex(field, value) => {
+                    let ids = self
+                        .db
+                        .get_node_ids_by_index(&schema.entity, field, value)
+                        .await?;
+                    narrow(&mut candidates, ids)
+                }
+                QueryOperation::ByIndexRange(field, start, end) => {
+                    let (start, end) = (start.as_ref(), end.as_ref());
+                    let ids = self
+                        .db
+                        .get_node_ids_by_index_range(&schema.entity, field, start, end)
+                        .await?;
+                    narrow(&mut candidates, ids)
+                }
+                QueryOperation::ByCompositeIndex(fields, values) => {
+                    let index = schema
+                        .composite_indexes
+                        .iter()
+                        .find(|index| index.fields.starts_with(fields));
+                    if let Some(index) = index {
+                        let ids = self
+                            .db
+                            .get_node_ids_by_composite_index(&schema.entity, &index.fields, values)
+                            .await?;
+                        narrow(&mut candidates, ids)
+                    }
+                }
+                QueryOperation::Search(field, terms) => {
+                    // The ranking of the search is kept whatever ran before.
+                    let scores = self
+                        .db
+                        .search_node_ids(&schema.entity, field, terms)
+                        .await?;
+                    let ids = scores.into_iter().map(|(id, _)| id).collect();
+                    reorder(&mut candidates, ids);
+                }
+                QueryOperation::Within(field, area) => {
+                    let ids = self
+                        .db
+                        .get_node_ids_by_geo(&schema.entity, field, area)
+                        .await?;
+                    narrow(&mut candidates, ids)
+                }
+                QueryOperation::ShortPath(from, to, options) => {
+                    // The path order is kept whatever ran before.
+                    let nodes = match short_path(self.db, *from, *to, options).await? {
+                        Some(path) => path.nodes,
+                        None => Vec::new(),
+                    };
+                    let entity_ids: HashSet<NodeID> = self
+                        .db
+                        .get_node_ids(&schema.entity)
+                        .await?
+                        .into_iter()
+                        .collect();
+                    reorder(
+                        &mut candidates,
+                        nodes
+                            .into_iter()
+                            .filter(|id| entity_ids.contains(id))
+                            .collect(),
+                    )
+                }
+                QueryOperation::Nearest(field, Embedding(query), k) => {
+                    let metric = schema
+                        .index(field)
+                        .and_then(|index| index.vector)
+                        .ok_or_else(|| DBError::QueryError {
+                            error: format!("{} is not a vector index of {}", field, schema.entity),
+                        })?;
+                    let ids = match candidates.take() {
+                        None => {
+                            self.db
+                                .nearest_node_ids(&schema.entity, field, metric, query, *k)
+                                .await?
+                        }
+                        Some(ids) => {
+                            let mut scored = Vec::with_capacity(ids.len());
+                            for node in reader.load(self.db, &ids).await? {
+                                let vector =
+                                    to_vector(&prop_value(&node, field)?).unwrap_or_default();
+                                if vector.len() == query.len() && !vector.is_empty() {
+                                    scored.push((node.id(), metric.distance(query, &vector)));
+                                }
+                            }
+                            scored.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+                            scored.truncate(*k);
+                            scored
+                        }
+                    };
+                    candidates = Some(ids.into_iter().map(|(id, _)| id).collect());
+                }
+                QueryOperation::Filter(_) | QueryOperation::FilterByProp(..) => {}
+            }
+        }
+
+        Ok(candidates)
+    }
+
+    /// Keeps the nodes passing the operations that need the node itself.
+    /// Index selections are checked again so stale entries can't leak.
+    fn retain<N: Item>(&self, schema: &Schema, mut nodes: Vec<N>) -> Result<Vec<N>, DBError> {
+        for operation in &self.operations {
+            match operation {
+                QueryOperation::ByIndex(prop, value)
+                | QueryOperation::FilterByProp(prop, value) => {
+                    let mut filtered = Vec::with_capacity(nodes.len());
+                    for node in nodes {
+                        if &prop_value(&node, prop)? == value {
+                            filtered.push(node);
+                        }
+                    }
+                    nodes = filtered;
+                }
+                QueryOperation::ByCompositeIndex(props, values) => {
+                    let mut filtered = Vec::with_capacity(nodes.len());
+                    for node in nodes {
+                        let value = node.value()?;
+                        let matched = props.iter().zip(values).all(|(prop, expected)| {
+                            value.get(prop).unwrap_or(&Value::Null) == expected
+                        });
+                        if matched {
+                            filtered.push(node);
+                        }
+                    }
+                    nodes = filtered;
+                }
+                QueryOperation::Search(prop, terms) => {
+                    let terms: HashSet<String> = tokenize(terms).into_iter().collect();
+                    let mut filtered = Vec::with_capacity(nodes.len());
+                    for node in nodes {
+                        let matched = match prop_value(&node, prop)? {
+                            Value::String(text) => {
+                                tokenize(&text).iter().any(|word| terms.contains(word))
+                            }
+                            _ => false,
+                        };
+                        if matched {
+                            filtered.push(node);
+                        }
+                    }
+                    nodes = filtered;
+                }
+                QueryOperation::ByIndexRange(prop, start, end) => {
+                    let range = IndexRange::new(&schema.entity, prop, start.as_ref(), end.as_ref());
+                    let mut filtered = Vec::with_capacity(nodes.len());
+                    for node in nodes {
+                        if range.contains(&prop_value(&node, prop)?) {
+                            filtered.push(node);
+                        }
+                    }
+                    nodes = filtered;
+                }
+                QueryOperation::Within(prop, area) => {
+                    let mut filtered = Vec::with_capacity(nodes.len());
+                    for node in nodes {
+                        let point = GeoPoint::from_value(&prop_value(&node, prop)?);
+                        if point.is_some_and(|point| area.contains(&point)) {
+                            filtered.push(node);
+                        }
+                    }
+                    nodes = filtered;
+                }
+                QueryOperation::Filter(predicate) => {
+                    let mut filtered = Vec::with_capacity(nodes.len());
+                    for node in nodes {
+                        match (predicate.0)(node.as_any()) {
+                            Some(true) => filtered.push(node),
+                            Some(false) => {}
+                            None => {
+                                return Err(DBError::QueryError {
+                                    error: format!("filter does not apply to {}", schema.entity),
+                                })
+                            }
+                        }
+                    }
+                    nodes = filtered;
+                }
+                _ => {}
+            }
+        }
+
+        Ok(nodes)
+    }
+
+    async fn edge_endpoints(&self, cb: impl Fn(&EdgeItem) -> bool) -> Result<Vec<NodeID>, DBError> {
+        let edges = self.db.scan_edges().await?;
+        Ok(edges
+            .iter()
+            .filter(|edge| cb(edge))
+            .flat_map(|edge| [edge.from, edge.to])
+            .collect())
+    }
+}
+
+fn edge_value(edge: &EdgeItem) -> Result<Value, DBError> {
+    let data = edge.data.to_value().ok_or_else(|| DBError::QueryError {
+        error: format!("data of edge {} is of an unregistered type", edge.key()),
+    })?;
+    let mut value = match data {
+        Value::Map(map) => map,
+        Value::Null => BTreeMap::new(),
+        data => BTreeMap::from([("data".to_string(), data)]),
+    };
+    value.insert("from".to_string(), edge.from.into());
+    value.insert("to".to_string(), edge.to.into());
+    value.insert("label".to_string(), edge.label.clone().into());
+    Ok(Value::Map(value))
+}
+
+/// Keeps the candidates that are among `ids` too, in their current order.
+fn narrow(candidates: &mut Option<Vec<NodeID>>, ids: Vec<NodeID>) {
+    *candidates = Some(match candidates.take() {
+        None => dedup(ids),
+        Some(current) => {
+            let ids: HashSet<NodeID> = ids.into_iter().collect();
+            current.into_iter().filter(|id| ids.contains(id)).collect()
+        }
+    });
+}
+
+/// Keeps the candidates among `ids`, in the order of `ids`.
+fn reorder(candidates: &mut Option<Vec<NodeID>>, ids: Vec<NodeID>) {
+    *candidates = Some(match candidates.take() {
+        None => ids,
+        Some(current) => {
+            let current: HashSet<NodeID> = current.into_iter().collect();
+            ids.into_iter().filter(|id| current.contains(id)).collect()
+        }
+    });
+}
+
+fn dedup(ids: Vec<NodeID>) -> Vec<NodeID> {
+    let mut seen = HashSet::new();
+    ids.into_iter().filter(|id| seen.insert(*id)).collect()
+}
+
+/// Sorts `nodes` by `prop`, then by id.
+fn sort_nodes<N: Item>(nodes: Vec<N>, prop: &str) -> Result<Vec<N>, DBError> {
+    let mut keyed = nodes
+        .into_iter()
+        .map(|node| Ok((prop_value(&node, prop)?, node)))
+        .collect::<Result<Vec<_>, DBError>>()?;
+    keyed.sort_by(|(a, x), (b, y)| a.cmp(b).then(x.id().0.cmp(&y.id().0)));
+    Ok(keyed.into_iter().map(|(_, node)| node).collect())
+}
+
+fn is_range_index(schema: &Schema, field: &str) -> bool {
+    schema.index(field).is_some_and(|index| index.range)
+}
+
+pub(crate) fn entity_name(name: &str) -> String {
+    if name.starts_with(&utils::format_entity("")) {
+        name.to_string()
+    } else {
+        utils::format_entity(name)
+    }
+}
+
+fn prop_value<N: Item>(node: &N, prop: &str) -> Result<Value, DBError> {
+    let value = node.value()?;
+    Ok(value.get(prop).cloned().unwrap_or(Value::Null))
 }
@@ -0,0 +1,137 @@
+use crate::{
+    db::{DBError, NodeStream, DB},
+    node::{node_type, register, Node, NodeType, Schema},
+    query::entity_name,
+};
+use arkycore::types::{NodeID, Value};
+use futures::future::BoxFuture;
+use futures::StreamExt;
+use std::any::Any;
+use std::marker::PhantomData;
+
+/// Node as the executor handles it, typed or as a row.
+pub(crate) trait Item: Send + Sync + 'static {
+    fn id(&self) -> NodeID;
+    fn value(&self) -> Result<Value, DBError>;
+    fn as_any(&self) -> &dyn Any;
+}
+impl<T: Node> Item for T {
+    fn id(&self) -> NodeID {
+        self.key()
+    }
+    fn value(&self) -> Result<Value, DBError> {
+        self.to_value().map_err(|e| DBError::QueryError {
+            error: e.to_string(),
+        })
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Node read without its Rust type, as the value of its properties.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Row {
+    pub(crate) id: NodeID,
+    pub(crate) value: Value,
+}
+impl Item for Row {
+    fn id(&self) -> NodeID {
+        self.id
+    }
+    fn value(&self) -> Result<Value, DBError> {
+        Ok(self.value.clone())
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Reads the nodes of one entity for the executor, so the same plans, sorts
+/// and cursors serve typed queries and `select`.
+pub(crate) trait Reader<D>: Copy + Send + Sync + 'static {
+    type Node: Item;
+
+    fn schema(&self) -> Schema;
+    /// Nodes of the entity among `ids`, in the order of `ids`.
+    fn load<'d>(
+        self,
+        db: &'d D,
+        ids: &'d [NodeID],
+    ) -> BoxFuture<'d, Result<Vec<Self::Node>, DBError>>;
+    /// Nodes of the entity in storage order, starting after the node `after`.
+    fn scan(self, db: &D, after: Option<NodeID>) -> NodeStream<'_, Self::Node>;
+}
+
+/// Reads nodes as `T`.
+pub(crate) struct Typed<T>(PhantomData<fn() -> T>);
+impl<T: Node> Typed<T> {
+    pub(crate) fn new() -> Self {
+        register::<T>();
+        Self(PhantomData)
+    }
+}
+impl<T> Clone for Typed<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<T> Copy for Typed<T> {}
+impl<T: Node, D: DB + Sync> Reader<D> for Typed<T> {
+    type Node = T;
+
+    fn schema(&self) -> Schema {
+        Schema::of::<T>()
+    }
+    fn load<'d>(self, db: &'d D, ids: &'d [NodeID]) -> BoxFuture<'d, Result<Vec<T>, DBError>> {
+        db.get_nodes::<T>(ids)
+    }
+    fn scan(self, db: &D, after: Option<NodeID>) -> NodeStream<'_, T> {
+        db.scan_nodes::<T>(after)
+    }
+}
+
+/// Reads the nodes of a registered entity as rows.
+#[derive(Clone, Copy)]
+pub(crate) struct Rows(NodeType);
+impl Rows {
+    pub(crate) fn new(name: &str) -> Result<Self, DBError> {
+        let node_type = node_type(&entity_name(name)).ok_or_else(|| DBError::QueryError {
+            error: format!("{} is not a registered node type", name),
+        })?;
+        Ok(Self(node_type))
+    }
+    fn row(&self, id: NodeID, bytes: &[u8]) -> Result<Row, DBError> {
+        let value = (self.0.decode)(bytes).map_err(|e| DBError::GetNodeError {
+            key: id,
+            error: e.to_string(),
+        })?;
+        Ok(Row { id, value })
+    }
+}
+impl<D: DB + Sync> Reader<D> for Rows {
+    type Node = Row;
+
+    fn schema(&self) -> Schema {
+        (self.0.schema)()
+    }
+    fn load<'d>(self, db: &'d D, ids: &'d [NodeID]) -> BoxFuture<'d, Result<Vec<Row>, DBError>> {
+        Box::pin(async move {
+            let entity = (self.0.schema)().entity;
+            let found = db.get_node_bytes(&entity, ids).await?;
+            found
+                .iter()
+                .map(|(id, bytes)| self.row(*id, bytes))
+                .collect()
+        })
+    }
+    fn scan(self, db: &D, after: Option<NodeID>) -> NodeStream<'_, Row> {
+        let entity = (self.0.schema)().entity;
+        db.scan_node_bytes(&entity, after)
+            .map(move |node| {
+                let (id, bytes) = node?;
+                self.row(id, &bytes)
+            })
+            .boxed()
+    }
+}
@@ -0,0 +1,76 @@
+use crate::{
+    cursor::Page,
+    db::{DBError, DB},
+    query::{QueryExecutor, QueryOperation},
+    reader::{Row, Rows},
+};
+use arkycore::types::Value;
+use std::collections::BTreeMap;
+
+/// Projection of a query to some properties, returned by
+/// `QueryExecutor::select`. Rows are read from the entities given to `from`
+/// by name, so one query can span entities that don't share a Rust type.
+pub struct Select<'q, 'a, D> {
+    query: &'q QueryExecutor<'a, D>,
+    props: Vec<String>,
+    entities: Vec<String>,
+}
+impl<'q, 'a, D: DB + Sync> Select<'q, 'a, D> {
+    pub(crate) fn new(query: &'q QueryExecutor<'a, D>, props: &[&str]) -> Self {
+        Self {
+            query,
+            props: props.iter().map(|prop| prop.to_string()).collect(),
+            entities: Vec::new(),
+        }
+    }
+    /// Reads the matching nodes of the entity `name` too. Without `from`,
+    /// the entities of the `by_entity_name` steps of the query are read.
+    pub fn from(&mut self, name: &str) -> &mut Self {
+        self.entities.push(name.to_string());
+        self
+    }
+    /// One map per matching node, keyed by the selected properties. The
+    /// sort, skip and limit of the query apply to the rows of all the
+    /// entities together, and properties a node doesn't have are
+    /// `Value::Null`.
+    pub async fn exec(&self) -> Result<Vec<Value>, DBError> {
+        let rows = self.query.read(&self.readers()?).await?;
+        Ok(rows.iter().map(|row| self.project(row)).collect())
+    }
+    /// Same as `exec`, a page at a time like `QueryExecutor::page`.
+    pub async fn page(&self) -> Result<Page<Value>, DBError> {
+        let page = self.query.read_page(&self.readers()?).await?;
+        Ok(Page {
+            nodes: page.nodes.iter().map(|row| self.project(row)).collect(),
+            next: page.next,
+        })
+    }
+
+    fn readers(&self) -> Result<Vec<Rows>, DBError> {
+        let mut entities: Vec<&str> = self.entities.iter().map(String::as_str).collect();
+        if entities.is_empty() {
+            entities = self
+                .query
+                .operations()
+                .iter()
+                .filter_map(|operation| match operation {
+                    QueryOperation::ByEntityName(name) => Some(name.as_str()),
+                    _ => None,
+                })
+                .collect();
+        }
+        if entities.is_empty() {
+            return Err(DBError::QueryError {
+                error: "select needs an entity to read from".to_string(),
+            });
+        }
+        entities.into_iter().map(Rows::new).collect()
+    }
+    fn project(&self, row: &Row) -> Value {
+        let values = self.props.iter().map(|prop| {
+            let value = row.value.get(prop).cloned().unwrap_or(Value::Null);
+            (prop.clone(), value)
+        });
+        Value::Map(values.collect::<BTreeMap<_, _>>())
+    }
+}
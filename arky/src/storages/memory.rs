@@ -0,0 +1,948 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::future::Future;
+use std::ops::Bound;
+use std::sync::{Arc, Mutex, RwLock};
+
+use async_trait::async_trait;
+use futures::{future, stream, StreamExt};
+
+use crate::{
+    core::types::{EdgesTree, EntitiesTree, GeoArea, NodeID, NodesTree, Value, VectorMetric},
+    db::{serialize_batch, DBError, EdgeStream, NodeStream, Transaction as DBTransaction, DB},
+    edge::EdgeItem,
+    entity::EntityItem,
+    index::{
+        bm25_scores, composite_prefix, field_prefix, geo_point, geo_ranges, index_entries,
+        index_id, index_key, index_prefix, vector_graph, IndexEntries, IndexRange,
+    },
+    node::Node,
+    storage::{Storage, StorageError},
+    vector::{VectorItem, VectorSource, VectorWrite, VectorWrites},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Key {
+    Node(NodeID),
+    Edge(NodeID, NodeID),
+    Entity(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct NodeEntry {
+    entity: String,
+    bytes: Vec<u8>,
+    indexes: IndexEntries,
+}
+
+/// Nodes and edges are kept serialized, like RocksDB does, so reads hand out
+/// fresh copies and payloads go through the same encoding checks.
+#[derive(Debug, Default)]
+struct Trees {
+    nodes: NodesTree<NodeEntry>,
+    edges: EdgesTree<BTreeMap<String, Vec<u8>>>,
+    entities: EntitiesTree<EntityItem>,
+    indexes: BTreeSet<Vec<u8>>,
+    /// Owner of each value claimed under a unique index.
+    unique: HashMap<Vec<u8>, NodeID>,
+    /// Vector graphs, laid out like RocksDB's `vectors` column family.
+    vectors: BTreeMap<Vec<u8>, Vec<u8>>,
+    versions: HashMap<Key, u64>,
+}
+impl Trees {
+    fn version(&self, key: &Key) -> u64 {
+        self.versions.get(key).copied().unwrap_or_default()
+    }
+
+    fn bump(&mut self, key: Key) {
+        *self.versions.entry(key).or_default() += 1;
+    }
+
+    /// Fails if an inserted node claims a unique value owned by a node the
+    /// changes leave untouched, or already claimed by another inserted node.
+    fn check_unique(&self, changes: &Changes) -> Result<(), DBError> {
+        let mut claimed = HashMap::new();
+        for (id, node) in &changes.nodes {
+            let Some(node) = node else {
+                continue;
+            };
+            for unique in &node.indexes.unique {
+                let owner = self.unique.get(&unique.key);
+                let taken =
+                    owner.is_some_and(|owner| owner != id && !changes.nodes.contains_key(owner));
+                if taken || claimed.insert(&unique.key, id).is_some() {
+                    return Err(unique.violation(&node.entity));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Links the vectors of the changed nodes into their graphs.
+    fn vector_writes(&self, changes: &Changes) -> Result<Vec<VectorWrite>, DBError> {
+        let mut writes = VectorWrites::new(&self.vectors);
+        for (id, node) in &changes.nodes {
+            let stored = self
+                .nodes
+                .get(id)
+                .map(|node| node.indexes.vectors.as_slice());
+            let current = node.as_ref().map(|node| node.indexes.vectors.as_slice());
+            writes
+                .update(*id, stored.unwrap_or_default(), current.unwrap_or_default())
+                .map_err(|error| DBError::InsertNodeError { key: *id, error })?;
+        }
+        Ok(writes.into_writes())
+    }
+
+    /// Applies every change at once, creating the entities of the inserted
+    /// nodes that don't exist yet. Nothing is applied if the changes break a
+    /// unique index.
+    fn apply(&mut self, changes: Changes) -> Result<(), DBError> {
+        self.check_unique(&changes)?;
+        for (key, vector) in self.vector_writes(&changes)? {
+            match vector {
+                Some(vector) => self.vectors.insert(key, vector),
+                None => self.vectors.remove(&key),
+            };
+        }
+        for (name, entity) in changes.entities {
+            self.bump(Key::Entity(name.clone()));
+            match entity {
+                Some(entity) => self.entities.insert(name, entity),
+                None => self.entities.remove(&name),
+            };
+        }
+        for id in changes.nodes.keys() {
+            if let Some(stored) = self.nodes.get(id) {
+                for key in &stored.indexes.keys {
+                    self.indexes.remove(key);
+                }
+                for entry in &stored.indexes.unique {
+                    self.unique.remove(&entry.key);
+                }
+            }
+        }
+        for (id, node) in changes.nodes {
+            self.bump(Key::Node(id));
+            match node {
+                Some(node) => {
+                    if !self.entities.contains_key(&node.entity) {
+                        self.bump(Key::Entity(node.entity.clone()));
+                        self.entities
+                            .insert(node.entity.clone(), EntityItem::new(node.entity.clone()));
+                    }
+                    self.indexes.extend(node.indexes.keys.iter().cloned());
+                    for entry in &node.indexes.unique {
+                        self.unique.insert(entry.key.clone(), id);
+                    }
+                    self.nodes.insert(id, node)
+                }
+                None => self.nodes.remove(&id),
+            };
+        }
+        for ((from, to), labels) in changes.edges {
+            self.bump(Key::Edge(from, to));
+            let edges = self.edges.entry((from, to)).or_default();
+            for (label, edge) in labels {
+                match edge {
+                    Some(edge) => edges.insert(label, edge),
+                    None => edges.remove(&label),
+                };
+            }
+            if edges.is_empty() {
+                self.edges.remove(&(from, to));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl VectorSource for BTreeMap<Vec<u8>, Vec<u8>> {
+    fn read(&self, key: &[u8]) -> Result<Option<Vec<u8>>, String> {
+        Ok(self.get(key).cloned())
+    }
+
+    fn scan(&self, prefix: &[u8]) -> Result<Vec<VectorItem>, String> {
+        Ok(self
+            .range(prefix.to_vec()..)
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect())
+    }
+}
+
+/// Pending writes, `None` standing for a removal.
+#[derive(Debug, Default)]
+struct Changes {
+    nodes: NodesTree<Option<NodeEntry>>,
+    edges: EdgesTree<BTreeMap<String, Option<Vec<u8>>>>,
+    entities: EntitiesTree<Option<EntityItem>>,
+}
+impl Changes {
+    fn insert_node<T: Node>(&mut self, node: &T, bytes: Vec<u8>) -> Result<(), DBError> {
+        let indexes = index_entries(node).map_err(|e| DBError::InsertNodeError {
+            key: node.key(),
+            error: e.to_string(),
+        })?;
+        let entry = NodeEntry {
+            entity: node.entity(),
+            bytes,
+            indexes,
+        };
+        self.nodes.insert(node.key(), Some(entry));
+        Ok(())
+    }
+
+    fn remove_node<T: Node>(&mut self, node: &T) {
+        self.nodes.insert(node.key(), None);
+    }
+
+    fn insert_edge(&mut self, edge: &EdgeItem, bytes: Vec<u8>) {
+        let edges = self.edges.entry((edge.from, edge.to)).or_default();
+        edges.insert(edge.label.clone(), Some(bytes));
+    }
+
+    fn remove_edge(&mut self, edge: &EdgeItem) {
+        let edges = self.edges.entry((edge.from, edge.to)).or_default();
+        edges.insert(edge.label.clone(), None);
+    }
+
+    fn insert_entity(&mut self, entity: &EntityItem) {
+        self.entities
+            .insert(entity.name.clone(), Some(entity.clone()));
+    }
+
+    fn remove_entity(&mut self, entity: &EntityItem) {
+        self.entities.insert(entity.name.clone(), None);
+    }
+}
+
+#[derive(Debug)]
+pub struct MemoryDatabase {
+    key: String,
+    trees: RwLock<Trees>,
+}
+
+impl MemoryDatabase {
+    fn _commit(&self, changes: Changes) -> Result<(), DBError> {
+        self.trees.write().unwrap().apply(changes)
+    }
+
+    fn _index_keys(&self, prefix: &[u8]) -> Vec<Vec<u8>> {
+        let trees = self.trees.read().unwrap();
+        trees
+            .indexes
+            .range(prefix.to_vec()..)
+            .take_while(|key| key.starts_with(prefix))
+            .cloned()
+            .collect()
+    }
+
+    fn _index_ids(&self, prefix: Vec<u8>) -> Vec<NodeID> {
+        let keys = self._index_keys(&prefix);
+        keys.iter().filter_map(|key| index_id(key)).collect()
+    }
+
+    fn _get_entity(trees: &Trees, name: &str) -> Result<EntityItem, DBError> {
+        trees
+            .entities
+            .get(name)
+            .cloned()
+            .ok_or_else(|| DBError::GetEntityError {
+                key: name.to_string(),
+                error: "Entity not found".to_string(),
+            })
+    }
+
+    fn _entity_node_ids(&self, entity: &str) -> Vec<NodeID> {
+        let trees = self.trees.read().unwrap();
+        let mut ids: Vec<NodeID> = trees
+            .nodes
+            .iter()
+            .filter(|(_, node)| node.entity == entity)
+            .map(|(id, _)| *id)
+            .collect();
+        ids.sort_by_key(|id| id.0);
+        ids
+    }
+
+    fn _get_node<T: Node>(trees: &Trees, id: NodeID) -> Result<T, DBError> {
+        let node = trees.nodes.get(&id).ok_or_else(|| DBError::GetNodeError {
+            key: id,
+            error: "Node not found".to_string(),
+        })?;
+        Self::_decode_node(id, &node.bytes)
+    }
+
+    fn _decode_node<T: Node>(id: NodeID, node_bytes: &[u8]) -> Result<T, DBError> {
+        T::from_bytes(node_bytes).map_err(|e| DBError::GetNodeError {
+            key: id,
+            error: e.to_string(),
+        })
+    }
+
+    fn _get_edge(id: String, edge_bytes: Option<&Vec<u8>>) -> Result<EdgeItem, DBError> {
+        let edge_bytes = edge_bytes.ok_or_else(|| DBError::GetEdgeError {
+            key: id.clone(),
+            error: "Edge not found".to_string(),
+        })?;
+        EdgeItem::from_bytes(edge_bytes).map_err(|e| DBError::GetEdgeError {
+            key: id,
+            error: e.to_string(),
+        })
+    }
+
+    /// Decodes the edges from `from` to `to`, ordered by label.
+    fn _get_edges(
+        from: NodeID,
+        to: NodeID,
+        edges: &BTreeMap<String, Vec<u8>>,
+    ) -> Result<Vec<EdgeItem>, DBError> {
+        edges
+            .iter()
+            .map(|(label, edge_bytes)| {
+                Self::_get_edge(EdgeItem::format_key(from, to, label), Some(edge_bytes))
+            })
+            .collect()
+    }
+
+    fn _first_edge(from: NodeID, to: NodeID, edges: Vec<EdgeItem>) -> Result<EdgeItem, DBError> {
+        edges
+            .into_iter()
+            .next()
+            .ok_or_else(|| DBError::GetEdgeError {
+                key: EdgeItem::format_prefix(from, to),
+                error: "Edge not found".to_string(),
+            })
+    }
+
+    /// Edges matching `label` whose endpoints pass `adjacent`, ordered by
+    /// label and then by the other endpoint.
+    fn _adjacent_edges(
+        &self,
+        label: Option<&str>,
+        adjacent: impl Fn(NodeID, NodeID) -> Option<NodeID>,
+    ) -> EdgeStream<'_> {
+        let trees = self.trees.read().unwrap();
+        let mut keys = Vec::new();
+        for ((from, to), edges) in &trees.edges {
+            let Some(other) = adjacent(*from, *to) else {
+                continue;
+            };
+            for edge_label in edges.keys() {
+                if label.is_none_or(|label| label == edge_label) {
+                    keys.push((edge_label.clone(), other.0, *from, *to));
+                }
+            }
+        }
+        keys.sort_by(|a, b| (&a.0, a.1).cmp(&(&b.0, b.1)));
+
+        let edges: Vec<_> = keys
+            .into_iter()
+            .map(|(label, _, from, to)| {
+                let edge_bytes = trees.edges[&(from, to)].get(&label);
+                Self::_get_edge(EdgeItem::format_key(from, to, &label), edge_bytes)
+            })
+            .collect();
+        stream::iter(edges).boxed()
+    }
+
+    fn _node_to_bytes_with_error<T: Node>(node: &T) -> Result<Vec<u8>, DBError> {
+        node.to_bytes().map_err(|e| DBError::InsertNodeError {
+            key: node.key(),
+            error: e.to_string(),
+        })
+    }
+
+    fn _edge_to_bytes_with_error(edge: &EdgeItem) -> Result<Vec<u8>, DBError> {
+        edge.to_bytes().map_err(|e| DBError::InsertEdgeError {
+            key: edge.key(),
+            error: e.to_string(),
+        })
+    }
+}
+
+#[async_trait]
+impl DB for MemoryDatabase {
+    type Config = MemoryConfig;
+    type Transaction<'a> = MemoryTransaction<'a>;
+
+    fn key(&self) -> String {
+        self.key.clone()
+    }
+
+    fn new(key: String, _config: &Self::Config) -> Result<Self, DBError>
+    where
+        Self: Sized,
+    {
+        Ok(MemoryDatabase {
+            key,
+            trees: RwLock::new(Trees::default()),
+        })
+    }
+
+    /**
+     * Entity methods
+     */
+    async fn get_entity(&self, name: &str) -> Result<EntityItem, DBError> {
+        Self::_get_entity(&self.trees.read().unwrap(), name)
+    }
+
+    async fn insert_entity(&self, entity: &EntityItem) -> Result<(), DBError> {
+        self.insert_entities(std::slice::from_ref(entity)).await
+    }
+
+    async fn insert_entities(&self, entities: &[EntityItem]) -> Result<(), DBError> {
+        let mut changes = Changes::default();
+        for entity in entities {
+            changes.insert_entity(entity);
+        }
+        self._commit(changes)
+    }
+
+    async fn remove_entity(&self, entity: &EntityItem) -> Result<(), DBError> {
+        self.remove_entities(std::slice::from_ref(entity)).await
+    }
+
+    async fn remove_entities(&self, entities: &[EntityItem]) -> Result<(), DBError> {
+        let mut changes = Changes::default();
+        for entity in entities {
+            changes.remove_entity(entity);
+        }
+        self._commit(changes)
+    }
+
+    async fn update_entity(&self, entity: &EntityItem) -> Result<(), DBError> {
+        self.insert_entity(entity).await
+    }
+
+    /**
+     * Node methods
+     */
+    async fn get_node<T: Node>(&self, id: NodeID) -> Result<T, DBError> {
+        Self::_get_node(&self.trees.read().unwrap(), id)
+    }
+
+    async fn get_nodes<T: Node>(&self, ids: &[NodeID]) -> Result<Vec<T>, DBError> {
+        let trees = self.trees.read().unwrap();
+        let entity = T::entity_name();
+
+        ids.iter()
+            .filter(|id| matches!(trees.nodes.get(id), Some(node) if node.entity == entity))
+            .map(|id| Self::_get_node(&trees, *id))
+            .collect()
+    }
+
+    async fn get_node_ids(&self, entity: &str) -> Result<Vec<NodeID>, DBError> {
+        Ok(self._entity_node_ids(entity))
+    }
+
+    fn scan_nodes<T: Node>(&self, after: Option<NodeID>) -> NodeStream<'_, T> {
+        self.scan_node_bytes(&T::entity_name(), after)
+            .map(|node| {
+                let (id, node_bytes) = node?;
+                Self::_decode_node(id, &node_bytes)
+            })
+            .boxed()
+    }
+
+    async fn get_node_bytes(
+        &self,
+        entity: &str,
+        ids: &[NodeID],
+    ) -> Result<Vec<(NodeID, Vec<u8>)>, DBError> {
+        let trees = self.trees.read().unwrap();
+        let found = ids.iter().filter_map(|id| match trees.nodes.get(id) {
+            Some(node) if node.entity == entity => Some((*id, node.bytes.clone())),
+            _ => None,
+        });
+        Ok(found.collect())
+    }
+
+    fn scan_node_bytes(
+        &self,
+        entity: &str,
+        after: Option<NodeID>,
+    ) -> NodeStream<'_, (NodeID, Vec<u8>)> {
+        let entity = entity.to_string();
+        let mut ids = self._entity_node_ids(&entity);
+        if let Some(after) = after {
+            ids.drain(..ids.partition_point(|id| id.0 <= after.0));
+        }
+        stream::iter(ids)
+            .filter_map(move |id| {
+                // Nodes removed since the scan started are skipped.
+                let trees = self.trees.read().unwrap();
+                let node = match trees.nodes.get(&id) {
+                    Some(node) if node.entity == entity => Some(Ok((id, node.bytes.clone()))),
+                    _ => None,
+                };
+                future::ready(node)
+            })
+            .boxed()
+    }
+
+    async fn get_node_ids_by_index(
+        &self,
+        entity: &str,
+        field: &str,
+        value: &Value,
+    ) -> Result<Vec<NodeID>, DBError> {
+        Ok(self._index_ids(index_prefix(entity, field, value)))
+    }
+
+    async fn get_node_ids_by_composite_index(
+        &self,
+        entity: &str,
+        fields: &[String],
+        values: &[Value],
+    ) -> Result<Vec<NodeID>, DBError> {
+        Ok(self._index_ids(composite_prefix(entity, fields, values)))
+    }
+
+    async fn search_node_ids(
+        &self,
+        entity: &str,
+        field: &str,
+        query: &str,
+    ) -> Result<Vec<(NodeID, f64)>, DBError> {
+        bm25_scores(entity, field, query, |prefix| Ok(self._index_keys(prefix)))
+    }
+
+    async fn nearest_node_ids(
+        &self,
+        entity: &str,
+        field: &str,
+        metric: VectorMetric,
+        query: &[f32],
+        k: usize,
+    ) -> Result<Vec<(NodeID, f32)>, DBError> {
+        let trees = self.trees.read().unwrap();
+        VectorWrites::new(&trees.vectors)
+            .nearest(&vector_graph(entity, field, metric), query, k)
+            .map_err(|error| DBError::QueryError { error })
+    }
+
+    async fn get_node_ids_by_index_range(
+        &self,
+        entity: &str,
+        field: &str,
+        start: Bound<&Value>,
+        end: Bound<&Value>,
+    ) -> Result<Vec<NodeID>, DBError> {
+        let trees = self.trees.read().unwrap();
+        let range = IndexRange::new(entity, field, start, end);
+        Ok(trees
+            .indexes
+            .range(range.seek_key()..)
+            .take_while(|key| !range.is_past(key))
+            .filter(|key| range.contains_key(key))
+            .filter_map(|key| index_id(key))
+            .collect())
+    }
+
+    fn scan_index_ids(
+        &self,
+        entity: &str,
+        field: &str,
+        after: Option<(&Value, NodeID)>,
+    ) -> NodeStream<'_, NodeID> {
+        let prefix = field_prefix(entity, field);
+        let start = match after {
+            Some((value, id)) => Bound::Excluded(index_key(entity, field, value, id)),
+            None => Bound::Included(prefix.clone()),
+        };
+        // One key at a time, so writes aren't blocked while the scan is read.
+        stream::unfold(start, move |start| {
+            let trees = self.trees.read().unwrap();
+            let key = trees
+                .indexes
+                .range((start, Bound::Unbounded))
+                .next()
+                .filter(|key| key.starts_with(&prefix))
+                .cloned();
+            future::ready(key.map(|key| (index_id(&key), Bound::Excluded(key))))
+        })
+        .filter_map(|id| future::ready(id.map(Ok)))
+        .boxed()
+    }
+
+    async fn get_node_ids_by_geo(
+        &self,
+        entity: &str,
+        field: &str,
+        area: &GeoArea,
+    ) -> Result<Vec<NodeID>, DBError> {
+        let trees = self.trees.read().unwrap();
+        Ok(geo_ranges(entity, field, area)
+            .into_iter()
+            .flat_map(|(start, end)| trees.indexes.range(start..end))
+            .filter(|key| geo_point(key).is_some_and(|point| area.contains(&point)))
+            .filter_map(|key| index_id(key))
+            .collect())
+    }
+
+    async fn insert_node<T: Node + Sync>(&self, node: &T) -> Result<(), DBError> {
+        self.insert_nodes(std::slice::from_ref(node))
+            .await
+            .map_err(|e| {
+                e.for_item(|error| DBError::InsertNodeError {
+                    key: node.key(),
+                    error,
+                })
+            })
+    }
+
+    async fn insert_nodes<T: Node>(&self, nodes: &[T]) -> Result<(), DBError> {
+        let nodes_serialized = serialize_batch(nodes, |node| node.key().to_string(), T::to_bytes)?;
+        let mut changes = Changes::default();
+        for (node, node_serialized) in nodes.iter().zip(nodes_serialized) {
+            changes.insert_node(node, node_serialized)?;
+        }
+        self._commit(changes)
+    }
+
+    async fn remove_node<T: Node>(&self, node: &T) -> Result<(), DBError> {
+        self.remove_nodes(std::slice::from_ref(node))
+            .await
+            .map_err(|e| {
+                e.for_item(|error| DBError::RemoveNodeError {
+                    key: node.key(),
+                    error,
+                })
+            })
+    }
+
+    async fn remove_nodes<T: Node>(&self, nodes: &[T]) -> Result<(), DBError> {
+        let mut changes = Changes::default();
+        for node in nodes {
+            changes.remove_node(node);
+        }
+        self._commit(changes)
+    }
+
+    async fn remove_nodes_with_edges<T: Node>(&self, nodes: &[T]) -> Result<(), DBError> {
+        let ids: HashSet<NodeID> = nodes.iter().map(Node::key).collect();
+        let mut trees = self.trees.write().unwrap();
+        let mut changes = Changes::default();
+        for node in nodes {
+            changes.remove_node(node);
+        }
+        for ((from, to), edges) in &trees.edges {
+            if ids.contains(from) || ids.contains(to) {
+                let removed = edges.keys().map(|label| (label.clone(), None));
+                changes.edges.insert((*from, *to), removed.collect());
+            }
+        }
+        trees.apply(changes)
+    }
+
+    async fn update_node<T: Node>(&self, node: &T) -> Result<(), DBError> {
+        self.insert_node(node).await
+    }
+
+    /**
+     * Edge methods
+     */
+    async fn get_edge(&self, from: NodeID, to: NodeID) -> Result<EdgeItem, DBError> {
+        let edges = self.get_edges(from, to).await?;
+        Self::_first_edge(from, to, edges)
+    }
+
+    async fn get_edges(&self, from: NodeID, to: NodeID) -> Result<Vec<EdgeItem>, DBError> {
+        let trees = self.trees.read().unwrap();
+        match trees.edges.get(&(from, to)) {
+            Some(edges) => Self::_get_edges(from, to, edges),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    async fn get_edge_by_label(
+        &self,
+        from: NodeID,
+        to: NodeID,
+        label: &str,
+    ) -> Result<EdgeItem, DBError> {
+        let trees = self.trees.read().unwrap();
+        let edge_bytes = trees
+            .edges
+            .get(&(from, to))
+            .and_then(|edges| edges.get(label));
+        Self::_get_edge(EdgeItem::format_key(from, to, label), edge_bytes)
+    }
+
+    async fn scan_edges(&self) -> Result<Vec<EdgeItem>, DBError> {
+        let trees = self.trees.read().unwrap();
+        let mut edges = Vec::new();
+        for ((from, to), labels) in &trees.edges {
+            edges.extend(Self::_get_edges(*from, *to, labels)?);
+        }
+        // In the order of their keys, as RocksDB scans them.
+        edges.sort_by_key(EdgeItem::key);
+        Ok(edges)
+    }
+
+    fn out_edges(&self, id: NodeID, label: Option<&str>) -> EdgeStream<'_> {
+        self._adjacent_edges(label, |from, to| (from == id).then_some(to))
+    }
+
+    fn in_edges(&self, id: NodeID, label: Option<&str>) -> EdgeStream<'_> {
+        self._adjacent_edges(label, |from, to| (to == id).then_some(from))
+    }
+
+    async fn insert_edge(&self, edge: &EdgeItem) -> Result<(), DBError> {
+        self.insert_edges(std::slice::from_ref(edge))
+            .await
+            .map_err(|e| {
+                e.for_item(|error| DBError::InsertEdgeError {
+                    key: edge.key(),
+                    error,
+                })
+            })
+    }
+
+    async fn insert_edges(&self, edges: &[EdgeItem]) -> Result<(), DBError> {
+        let edges_serialized = serialize_batch(edges, EdgeItem::key, EdgeItem::to_bytes)?;
+        let mut changes = Changes::default();
+        for (edge, edge_serialized) in edges.iter().zip(edges_serialized) {
+            changes.insert_edge(edge, edge_serialized);
+        }
+        self._commit(changes)
+    }
+
+    async fn remove_edge(&self, edge: &EdgeItem) -> Result<(), DBError> {
+        self.remove_edges(std::slice::from_ref(edge))
+            .await
+            .map_err(|e| {
+                e.for_item(|error| DBError::RemoveEdgeError {
+                    key: edge.key(),
+                    error,
+                })
+            })
+    }
+
+    async fn remove_edges(&self, edges: &[EdgeItem]) -> Result<(), DBError> {
+        let mut changes = Changes::default();
+        for edge in edges {
+            changes.remove_edge(edge);
+        }
+        self._commit(changes)
+    }
+
+    async fn update_edge(&self, edge: &EdgeItem) -> Result<(), DBError> {
+        self.insert_edge(edge).await
+    }
+
+    /**
+     * Transaction methods
+     */
+    async fn transaction<'a, F, Fut, R>(&'a self, cb: F) -> Result<R, DBError>
+    where
+        F: FnOnce(Self::Transaction<'a>) -> Fut + Send,
+        Fut: Future<Output = Result<R, DBError>> + Send,
+        R: Send,
+    {
+        let tx = MemoryTransaction {
+            db: self,
+            state: Arc::new(Mutex::new(Some(TransactionState::default()))),
+        };
+        let result = cb(tx.clone()).await;
+        let state = tx.close()?;
+        let value = result?;
+
+        let mut trees = self.trees.write().unwrap();
+        let conflict = state
+            .reads
+            .iter()
+            .any(|(key, version)| trees.version(key) != *version);
+        if conflict {
+            return Err(DBError::TransactionError {
+                error: "Transaction conflicts with a concurrent write".to_string(),
+            });
+        }
+        trees.apply(state.changes)?;
+
+        Ok(value)
+    }
+}
+
+#[derive(Debug, Default)]
+struct TransactionState {
+    changes: Changes,
+    reads: HashMap<Key, u64>,
+}
+
+/// In-memory transaction. Writes are staged until commit, and the versions
+/// of the keys it read are checked then so a concurrent write makes the
+/// commit fail, same as RocksDB's optimistic transactions.
+#[derive(Debug, Clone)]
+pub struct MemoryTransaction<'a> {
+    db: &'a MemoryDatabase,
+    state: Arc<Mutex<Option<TransactionState>>>,
+}
+impl<'a> MemoryTransaction<'a> {
+    fn close(&self) -> Result<TransactionState, DBError> {
+        self.state
+            .lock()
+            .unwrap()
+            .take()
+            .ok_or_else(Self::_closed_error)
+    }
+
+    fn _closed_error() -> DBError {
+        DBError::TransactionError {
+            error: "Transaction is already closed".to_string(),
+        }
+    }
+
+    fn _with<R>(&self, cb: impl FnOnce(&mut TransactionState) -> R) -> Result<R, DBError> {
+        match self.state.lock().unwrap().as_mut() {
+            Some(state) => Ok(cb(state)),
+            None => Err(Self::_closed_error()),
+        }
+    }
+
+    /// Reads `key` through the staged changes, recording the version it had
+    /// when first read.
+    fn _read<R>(&self, key: Key, cb: impl FnOnce(&Changes, &Trees) -> R) -> Result<R, DBError> {
+        self._with(|state| {
+            let trees = self.db.trees.read().unwrap();
+            state
+                .reads
+                .entry(key.clone())
+                .or_insert_with(|| trees.version(&key));
+            cb(&state.changes, &trees)
+        })
+    }
+
+    /// Edges from `from` to `to` as seen by this transaction.
+    fn _edges(&self, from: NodeID, to: NodeID) -> Result<BTreeMap<String, Vec<u8>>, DBError> {
+        self._read(Key::Edge(from, to), |changes, trees| {
+            let mut edges = trees.edges.get(&(from, to)).cloned().unwrap_or_default();
+            for (label, edge) in changes.edges.get(&(from, to)).into_iter().flatten() {
+                match edge {
+                    Some(edge) => edges.insert(label.clone(), edge.clone()),
+                    None => edges.remove(label),
+                };
+            }
+            edges
+        })
+    }
+}
+
+#[async_trait]
+impl<'a> DBTransaction for MemoryTransaction<'a> {
+    /**
+     * Entity methods
+     */
+    async fn get_entity(&self, name: &str) -> Result<EntityItem, DBError> {
+        let not_found = || DBError::GetEntityError {
+            key: name.to_string(),
+            error: "Entity not found".to_string(),
+        };
+        let entity = self._read(
+            Key::Entity(name.to_string()),
+            |changes, trees| match changes.entities.get(name) {
+                Some(entity) => entity.clone(),
+                None => trees.entities.get(name).cloned(),
+            },
+        )?;
+        entity.ok_or_else(not_found)
+    }
+
+    async fn insert_entity(&self, entity: &EntityItem) -> Result<(), DBError> {
+        self._with(|state| state.changes.insert_entity(entity))
+    }
+
+    async fn remove_entity(&self, entity: &EntityItem) -> Result<(), DBError> {
+        self._with(|state| state.changes.remove_entity(entity))
+    }
+
+    /**
+     * Node methods
+     */
+    async fn get_node<T: Node>(&self, id: NodeID) -> Result<T, DBError> {
+        let entry = self._read(Key::Node(id), |changes, trees| {
+            match changes.nodes.get(&id) {
+                Some(node) => node.clone(),
+                None => trees.nodes.get(&id).cloned(),
+            }
+        })?;
+        let node = entry.ok_or_else(|| DBError::GetNodeError {
+            key: id,
+            error: "Node not found".to_string(),
+        })?;
+        T::from_bytes(&node.bytes).map_err(|e| DBError::GetNodeError {
+            key: id,
+            error: e.to_string(),
+        })
+    }
+
+    async fn insert_node<T: Node>(&self, node: &T) -> Result<(), DBError> {
+        let node_serialized = MemoryDatabase::_node_to_bytes_with_error(node)?;
+        let entity_name = node.entity();
+        if self.get_entity(&entity_name).await.is_err() {
+            self.insert_entity(&EntityItem::new(entity_name)).await?;
+        }
+        self._with(|state| state.changes.insert_node(node, node_serialized))?
+    }
+
+    async fn remove_node<T: Node>(&self, node: &T) -> Result<(), DBError> {
+        self._with(|state| state.changes.remove_node(node))
+    }
+
+    /**
+     * Edge methods
+     */
+    async fn get_edge(&self, from: NodeID, to: NodeID) -> Result<EdgeItem, DBError> {
+        let edges = MemoryDatabase::_get_edges(from, to, &self._edges(from, to)?)?;
+        MemoryDatabase::_first_edge(from, to, edges)
+    }
+
+    async fn get_edge_by_label(
+        &self,
+        from: NodeID,
+        to: NodeID,
+        label: &str,
+    ) -> Result<EdgeItem, DBError> {
+        let edges = self._edges(from, to)?;
+        MemoryDatabase::_get_edge(EdgeItem::format_key(from, to, label), edges.get(label))
+    }
+
+    async fn insert_edge(&self, edge: &EdgeItem) -> Result<(), DBError> {
+        let edge_serialized = MemoryDatabase::_edge_to_bytes_with_error(edge)?;
+        self._with(|state| state.changes.insert_edge(edge, edge_serialized))
+    }
+
+    async fn remove_edge(&self, edge: &EdgeItem) -> Result<(), DBError> {
+        self._with(|state| state.changes.remove_edge(edge))
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryConfig {}
+
+#[derive(Debug)]
+pub struct MemoryStorage {
+    db: Result<MemoryDatabase, DBError>,
+    config: MemoryConfig,
+}
+impl Storage for MemoryStorage {
+    type Config = MemoryConfig;
+    type Database = MemoryDatabase;
+
+    fn set_config(&mut self, config: &Self::Config) {
+        self.config = config.clone();
+    }
+
+    fn new(config: Self::Config) -> Self {
+        let db = MemoryDatabase::new("memory".to_string(), &config);
+        Self { db, config }
+    }
+
+    fn use_db(&self) -> Result<&Self::Database, StorageError> {
+        match &self.db {
+            Ok(db) => Ok(db),
+            Err(e) => Err(StorageError::DbError(e.clone())),
+        }
+    }
+}
@@ -1,34 +1,57 @@
-use std::sync::Arc;
+use std::collections::{hash_map::Entry, HashMap, HashSet};
+use std::future::Future;
+use std::ops::Bound;
+use std::sync::{Arc, Mutex};
 
 use async_trait::async_trait;
+use futures::{future, stream, StreamExt};
 use rocksdb::{
-    BoundColumnFamily, ColumnFamilyDescriptor, DBWithThreadMode, MultiThreaded,
-    Options as RocksDBOptions,
+    BoundColumnFamily, ColumnFamilyDescriptor, Direction, IteratorMode, MultiThreaded,
+    OptimisticTransactionDB, Options as RocksDBOptions, WriteBatchWithTransaction,
 };
 
 use crate::{
-    core::types::NodeID,
-    db::{DBError, DB},
+    core::types::{GeoArea, NodeID, Value, VectorMetric},
+    db::{serialize_batch, DBError, EdgeStream, NodeStream, Transaction as DBTransaction, DB},
     edge::EdgeItem,
     entity::EntityItem,
+    index::{
+        bm25_scores, composite_prefix, decode_id, encode_id, field_prefix, geo_point, geo_ranges,
+        index_entries, index_id, index_key, index_prefix, vector_graph, IndexEntries, IndexRange,
+    },
     node::Node,
     storage::{Storage, StorageError},
+    vector::{VectorItem, VectorSource, VectorWrite, VectorWrites},
 };
 
+type Instance = OptimisticTransactionDB<MultiThreaded>;
+type WriteBatch = WriteBatchWithTransaction<true>;
+
 #[derive(Debug)]
 pub struct Database {
     key: String,
-    instance: DBWithThreadMode<MultiThreaded>,
+    instance: Instance,
+    /// Held from reading the index state of a node write until it's applied,
+    /// so two writes can't claim the same unique value or relink the same
+    /// vectors at once. Edge writes hold it too, so removing nodes with
+    /// their edges can't miss an edge linked meanwhile, and so do transaction
+    /// commits, which can't land between the checks and the writes of
+    /// another write. What a transaction read itself is checked on commit.
+    index_lock: Mutex<()>,
 }
 
 static NODES_CF: &str = "nodes";
 static EDGES_CF: &str = "edges";
 static ENTITIES_CF: &str = "entities";
+static ENTITY_NODES_CF: &str = "entity_nodes";
+static OUT_EDGES_CF: &str = "out_edges";
+static IN_EDGES_CF: &str = "in_edges";
+static INDEXES_CF: &str = "indexes";
+static UNIQUE_CF: &str = "unique";
+static VECTORS_CF: &str = "vectors";
 
 impl Database {
-    fn create_db_instance(
-        config: &RocksDBConfig,
-    ) -> Result<DBWithThreadMode<MultiThreaded>, rocksdb::Error> {
+    fn create_db_instance(config: &RocksDBConfig) -> Result<Instance, rocksdb::Error> {
         let mut dbs_opts = RocksDBOptions::default();
         dbs_opts.create_if_missing(config.create_if_missing);
         dbs_opts.set_error_if_exists(config.set_error_if_exists);
@@ -37,8 +60,24 @@ impl Database {
         let nodes = ColumnFamilyDescriptor::new(NODES_CF, dbs_opts.clone());
         let edges = ColumnFamilyDescriptor::new(EDGES_CF, dbs_opts.clone());
         let entities = ColumnFamilyDescriptor::new(ENTITIES_CF, dbs_opts.clone());
-        let cfs = vec![nodes, edges, entities];
-        DBWithThreadMode::open_cf_descriptors(&dbs_opts, &config.path, cfs)
+        let entity_nodes = ColumnFamilyDescriptor::new(ENTITY_NODES_CF, dbs_opts.clone());
+        let out_edges = ColumnFamilyDescriptor::new(OUT_EDGES_CF, dbs_opts.clone());
+        let in_edges = ColumnFamilyDescriptor::new(IN_EDGES_CF, dbs_opts.clone());
+        let indexes = ColumnFamilyDescriptor::new(INDEXES_CF, dbs_opts.clone());
+        let unique = ColumnFamilyDescriptor::new(UNIQUE_CF, dbs_opts.clone());
+        let vectors = ColumnFamilyDescriptor::new(VECTORS_CF, dbs_opts.clone());
+        let cfs = vec![
+            nodes,
+            edges,
+            entities,
+            entity_nodes,
+            out_edges,
+            in_edges,
+            indexes,
+            unique,
+            vectors,
+        ];
+        Instance::open_cf_descriptors(&dbs_opts, &config.path, cfs)
     }
 
     fn _get_entity(
@@ -47,7 +86,7 @@ impl Database {
         handle: &Arc<BoundColumnFamily<'_>>,
     ) -> Result<EntityItem, DBError> {
         self.instance
-            .get_cf(handle, name.to_string())
+            .get_cf(handle, name)
             .map_err(|e| DBError::GetEntityError {
                 key: name.to_string(),
                 error: e.to_string(),
@@ -78,7 +117,7 @@ impl Database {
     ) -> Result<(), DBError> {
         let entity_serialized = self._entity_to_bytes_with_error(entity)?;
         self.instance
-            .put_cf(handle, entity.name.to_string(), entity_serialized)
+            .put_cf(handle, &entity.name, entity_serialized)
             .map_err(|e| DBError::InsertEntityError {
                 key: entity.name.to_string(),
                 error: e.to_string(),
@@ -87,14 +126,27 @@ impl Database {
         Ok(())
     }
 
-    fn _insert_entity_if_needed<T: Node>(&self, node: &T) {
-        let entities = self.instance.cf_handle(ENTITIES_CF).unwrap();
-        let entity_name = node.entity();
-        let entity = self._get_entity(entity_name.as_str(), &entities);
-        if let Err(_) = entity {
-            let new_entity = EntityItem::new(entity_name);
-            self._insert_entity(&new_entity, &entities).unwrap();
+    fn _insert_entity_if_needed(
+        &self,
+        batch: &mut WriteBatch,
+        entity_name: &str,
+        handle: &Arc<BoundColumnFamily<'_>>,
+    ) -> Result<(), DBError> {
+        let entity = self._get_entity(entity_name, handle);
+        if entity.is_err() {
+            let new_entity = EntityItem::new(entity_name.to_string());
+            let entity_serialized = self._entity_to_bytes_with_error(&new_entity)?;
+            batch.put_cf(handle, &new_entity.name, entity_serialized);
         }
+        Ok(())
+    }
+
+    fn _write_batch(&self, batch: WriteBatch) -> Result<(), DBError> {
+        self.instance
+            .write(batch)
+            .map_err(|e| DBError::WriteBatchError {
+                error: e.to_string(),
+            })
     }
 
     fn _get_node<T: Node>(
@@ -102,6 +154,14 @@ impl Database {
         id: NodeID,
         handle: &Arc<BoundColumnFamily<'_>>,
     ) -> Result<T, DBError> {
+        Self::_decode_node(id, &self._get_node_bytes(id, handle)?)
+    }
+
+    fn _get_node_bytes(
+        &self,
+        id: NodeID,
+        handle: &Arc<BoundColumnFamily<'_>>,
+    ) -> Result<Vec<u8>, DBError> {
         self.instance
             .get_cf(handle, id.to_string())
             .map_err(|e| DBError::GetNodeError {
@@ -112,12 +172,13 @@ impl Database {
                 key: id,
                 error: "Node not found".to_string(),
             })
-            .and_then(|node_bytes| {
-                T::from_bytes(&node_bytes).map_err(|e| DBError::GetNodeError {
-                    key: id,
-                    error: e.to_string(),
-                })
-            })
+    }
+
+    fn _decode_node<T: Node>(id: NodeID, node_bytes: &[u8]) -> Result<T, DBError> {
+        T::from_bytes(node_bytes).map_err(|e| DBError::GetNodeError {
+            key: id,
+            error: e.to_string(),
+        })
     }
 
     fn _node_to_bytes_with_error<T: Node>(&self, node: &T) -> Result<Vec<u8>, DBError> {
@@ -127,29 +188,237 @@ impl Database {
         })
     }
 
-    fn _insert_node<T: Node>(
+    /// Index entries of the stored version of the node, if there is one.
+    fn _stored_index_entries<T: Node>(
         &self,
-        node: &T,
+        id: NodeID,
         handle: &Arc<BoundColumnFamily<'_>>,
+    ) -> Result<IndexEntries, DBError> {
+        let node = match self.instance.get_cf(handle, id.to_string()) {
+            Ok(Some(node_bytes)) => T::from_bytes(&node_bytes).ok(),
+            Ok(None) => None,
+            Err(e) => {
+                return Err(DBError::GetNodeError {
+                    key: id,
+                    error: e.to_string(),
+                })
+            }
+        };
+        match node {
+            Some(node) => index_entries(&node).map_err(|e| DBError::GetNodeError {
+                key: id,
+                error: e.to_string(),
+            }),
+            None => Ok(IndexEntries::default()),
+        }
+    }
+
+    /// Fails if a unique value of the nodes is owned by a node outside of
+    /// them, or claimed twice among them.
+    fn _check_unique<T: Node>(
+        &self,
+        nodes: &[T],
+        nodes_entries: &[IndexEntries],
     ) -> Result<(), DBError> {
-        let node_serialized = self._node_to_bytes_with_error(node)?;
-        self.instance
-            .put_cf(handle, node.key().to_string(), node_serialized)
-            .map_err(|e| DBError::InsertNodeError {
-                key: node.key(),
+        let handle = self.instance.cf_handle(UNIQUE_CF).unwrap();
+        let ids: HashSet<NodeID> = nodes.iter().map(|node| node.key()).collect();
+        let mut claimed = HashMap::new();
+
+        for (node, entries) in nodes.iter().zip(nodes_entries) {
+            for unique in &entries.unique {
+                let claimed_by = claimed.insert(&unique.key, node.key());
+                if claimed_by.is_some_and(|id| id != node.key()) {
+                    return Err(unique.violation(&node.entity()));
+                }
+
+                let owner = self
+                    .instance
+                    .get_cf(&handle, &unique.key)
+                    .map_err(|e| DBError::InsertNodeError {
+                        key: node.key(),
+                        error: e.to_string(),
+                    })?
+                    .and_then(|owner| decode_id(&owner));
+                if owner.is_some_and(|owner| owner != node.key() && !ids.contains(&owner)) {
+                    return Err(unique.violation(&node.entity()));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Puts the nodes along with their entity membership and index keys,
+    /// creating the entities that don't exist yet. The old index entries of
+    /// all the nodes are deleted before any new one is put, so nodes of the
+    /// batch can swap unique values.
+    fn _insert_nodes<T: Node>(
+        &self,
+        batch: &mut WriteBatch,
+        nodes: &[T],
+        nodes_serialized: Vec<Vec<u8>>,
+    ) -> Result<(), DBError> {
+        let handle = self.instance.cf_handle(NODES_CF).unwrap();
+        let entity_nodes = self.instance.cf_handle(ENTITY_NODES_CF).unwrap();
+        let entities = self.instance.cf_handle(ENTITIES_CF).unwrap();
+        let indexes = self.instance.cf_handle(INDEXES_CF).unwrap();
+        let unique = self.instance.cf_handle(UNIQUE_CF).unwrap();
+        let mut entity_names = HashSet::new();
+
+        let nodes_entries = nodes
+            .iter()
+            .map(|node| {
+                index_entries(node).map_err(|e| DBError::InsertNodeError {
+                    key: node.key(),
+                    error: e.to_string(),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        self._check_unique(nodes, &nodes_entries)?;
+
+        let mut vectors = VectorWrites::new(self);
+        for (node, entries) in nodes.iter().zip(&nodes_entries) {
+            let stored = self._stored_index_entries::<T>(node.key(), &handle)?;
+            vectors
+                .update(node.key(), &stored.vectors, &entries.vectors)
+                .map_err(|error| DBError::InsertNodeError {
+                    key: node.key(),
+                    error,
+                })?;
+            for key in stored.keys {
+                batch.delete_cf(&indexes, key);
+            }
+            for entry in stored.unique {
+                batch.delete_cf(&unique, entry.key);
+            }
+        }
+        self._write_vectors(batch, vectors.into_writes());
+        for ((node, node_serialized), entries) in
+            nodes.iter().zip(nodes_serialized).zip(nodes_entries)
+        {
+            for key in entries.keys {
+                batch.put_cf(&indexes, key, []);
+            }
+            for entry in entries.unique {
+                batch.put_cf(&unique, entry.key, encode_id(node.key()));
+            }
+            batch.put_cf(&handle, node.key().to_string(), node_serialized);
+            batch.put_cf(
+                &entity_nodes,
+                Self::_entity_node_key(&node.entity(), node.key()),
+                [],
+            );
+            entity_names.insert(node.entity());
+        }
+        for entity_name in entity_names {
+            self._insert_entity_if_needed(batch, &entity_name, &entities)?;
+        }
+
+        Ok(())
+    }
+
+    fn _remove_nodes<T: Node>(&self, batch: &mut WriteBatch, nodes: &[T]) -> Result<(), DBError> {
+        let handle = self.instance.cf_handle(NODES_CF).unwrap();
+        let entity_nodes = self.instance.cf_handle(ENTITY_NODES_CF).unwrap();
+        let indexes = self.instance.cf_handle(INDEXES_CF).unwrap();
+        let unique = self.instance.cf_handle(UNIQUE_CF).unwrap();
+        let mut vectors = VectorWrites::new(self);
+
+        for node in nodes {
+            let stored = self._stored_index_entries::<T>(node.key(), &handle)?;
+            vectors
+                .update(node.key(), &stored.vectors, &[])
+                .map_err(|error| DBError::RemoveNodeError {
+                    key: node.key(),
+                    error,
+                })?;
+            for key in stored.keys {
+                batch.delete_cf(&indexes, key);
+            }
+            for entry in stored.unique {
+                batch.delete_cf(&unique, entry.key);
+            }
+            batch.delete_cf(&handle, node.key().to_string());
+            batch.delete_cf(
+                &entity_nodes,
+                Self::_entity_node_key(&node.entity(), node.key()),
+            );
+        }
+        self._write_vectors(batch, vectors.into_writes());
+
+        Ok(())
+    }
+
+    fn _write_vectors(&self, batch: &mut WriteBatch, writes: Vec<VectorWrite>) {
+        let handle = self.instance.cf_handle(VECTORS_CF).unwrap();
+        for (key, value) in writes {
+            match value {
+                Some(value) => batch.put_cf(&handle, key, value),
+                None => batch.delete_cf(&handle, key),
+            }
+        }
+    }
+
+    fn _index_keys(&self, entity: &str, prefix: &[u8]) -> Result<Vec<Box<[u8]>>, DBError> {
+        let handle = self.instance.cf_handle(INDEXES_CF).unwrap();
+        let mut keys = Vec::new();
+
+        for item in self.instance.prefix_iterator_cf(&handle, prefix) {
+            let (key, _) = item.map_err(|e| DBError::GetEntityError {
+                key: entity.to_string(),
                 error: e.to_string(),
             })?;
+            if !key.starts_with(prefix) {
+                break;
+            }
+            keys.push(key);
+        }
 
-        Ok(())
+        Ok(keys)
+    }
+
+    fn _index_ids(&self, entity: &str, prefix: Vec<u8>) -> Result<Vec<NodeID>, DBError> {
+        let keys = self._index_keys(entity, &prefix)?;
+        Ok(keys.iter().filter_map(|key| index_id(key)).collect())
+    }
+
+    fn _entity_node_key(entity: &str, id: NodeID) -> String {
+        format!("{}:{}", entity, id)
+    }
+
+    /// Id of an `entity_nodes` key, `None` once the keys leave `prefix`.
+    fn _entity_node_id(entity: &str, prefix: &str, key: &[u8]) -> Option<Result<NodeID, DBError>> {
+        let id = key.strip_prefix(prefix.as_bytes())?;
+        let id = std::str::from_utf8(id)
+            .ok()
+            .and_then(|id| id.parse::<u64>().ok())
+            .ok_or_else(|| DBError::GetEntityError {
+                key: entity.to_string(),
+                error: "Invalid node key".to_string(),
+            });
+        Some(id.map(NodeID::from))
+    }
+
+    fn _has_entity_node(
+        &self,
+        entity: &str,
+        id: NodeID,
+        handle: &Arc<BoundColumnFamily<'_>>,
+    ) -> Result<bool, DBError> {
+        self.instance
+            .get_pinned_cf(handle, Self::_entity_node_key(entity, id))
+            .map(|value| value.is_some())
+            .map_err(|e| DBError::GetNodeError {
+                key: id,
+                error: e.to_string(),
+            })
     }
 
     fn _get_edge(
         &self,
-        from: NodeID,
-        to: NodeID,
+        id: &str,
         handle: &Arc<BoundColumnFamily<'_>>,
     ) -> Result<EdgeItem, DBError> {
-        let id = &EdgeItem::format_key(from, to);
         self.instance
             .get_cf(handle, id)
             .map_err(|e| DBError::GetEdgeError {
@@ -168,6 +437,42 @@ impl Database {
             })
     }
 
+    /// Decodes the edges yielded by a prefix iterator, stopping at the first
+    /// key outside of `prefix`.
+    fn _collect_edges(
+        prefix: &str,
+        iter: impl Iterator<Item = Result<(Box<[u8]>, Box<[u8]>), rocksdb::Error>>,
+    ) -> Result<Vec<EdgeItem>, DBError> {
+        let mut edges = Vec::new();
+
+        for item in iter {
+            let (key, edge_bytes) = item.map_err(|e| DBError::GetEdgeError {
+                key: prefix.to_string(),
+                error: e.to_string(),
+            })?;
+            if !key.starts_with(prefix.as_bytes()) {
+                break;
+            }
+            let edge = EdgeItem::from_bytes(&edge_bytes).map_err(|e| DBError::GetEdgeError {
+                key: String::from_utf8_lossy(&key).to_string(),
+                error: e.to_string(),
+            })?;
+            edges.push(edge);
+        }
+
+        Ok(edges)
+    }
+
+    fn _first_edge(prefix: &str, edges: Vec<EdgeItem>) -> Result<EdgeItem, DBError> {
+        edges
+            .into_iter()
+            .next()
+            .ok_or_else(|| DBError::GetEdgeError {
+                key: prefix.to_string(),
+                error: "Edge not found".to_string(),
+            })
+    }
+
     fn _edge_to_bytes_with_error(&self, edge: &EdgeItem) -> Result<Vec<u8>, DBError> {
         edge.to_bytes().map_err(|e| DBError::InsertEdgeError {
             key: edge.key(),
@@ -175,26 +480,142 @@ impl Database {
         })
     }
 
-    fn _insert_edge(
+    /// Adjacency keys of the edge, `{node}:{label}\0{other}`. The label is
+    /// terminated by a NUL byte so a label prefix scan can't reach labels
+    /// that merely start with it, like `a:b` when asking for `a`.
+    fn _adjacency_keys(edge: &EdgeItem) -> [(&'static str, String); 2] {
+        [
+            (
+                OUT_EDGES_CF,
+                format!("{}:{}\0{}", edge.from, edge.label, edge.to),
+            ),
+            (
+                IN_EDGES_CF,
+                format!("{}:{}\0{}", edge.to, edge.label, edge.from),
+            ),
+        ]
+    }
+
+    fn _adjacency_prefix(id: NodeID, label: Option<&str>) -> String {
+        match label {
+            Some(label) => format!("{}:{}\0", id, label),
+            None => format!("{}:", id),
+        }
+    }
+
+    fn _insert_edges(
         &self,
-        edge: &EdgeItem,
-        handle: &Arc<BoundColumnFamily<'_>>,
-    ) -> Result<(), DBError> {
-        let edge_serialized = self._edge_to_bytes_with_error(edge)?;
-        self.instance
-            .put_cf(handle, edge.key(), edge_serialized)
-            .map_err(|e| DBError::InsertEdgeError {
-                key: edge.key(),
+        batch: &mut WriteBatch,
+        edges: &[EdgeItem],
+        edges_serialized: Vec<Vec<u8>>,
+    ) {
+        let handle = self.instance.cf_handle(EDGES_CF).unwrap();
+
+        for (edge, edge_serialized) in edges.iter().zip(edges_serialized) {
+            batch.put_cf(&handle, edge.key(), edge_serialized);
+            for (cf, key) in Self::_adjacency_keys(edge) {
+                let adjacency = self.instance.cf_handle(cf).unwrap();
+                batch.put_cf(&adjacency, key, edge.key());
+            }
+        }
+    }
+
+    fn _remove_edges(&self, batch: &mut WriteBatch, edges: &[EdgeItem]) {
+        let handle = self.instance.cf_handle(EDGES_CF).unwrap();
+
+        for edge in edges {
+            batch.delete_cf(&handle, edge.key());
+            for (cf, key) in Self::_adjacency_keys(edge) {
+                let adjacency = self.instance.cf_handle(cf).unwrap();
+                batch.delete_cf(&adjacency, key);
+            }
+        }
+    }
+
+    /// Keys of the edges listed under `prefix` in the `cf` adjacency column
+    /// family.
+    fn _adjacent_keys(&self, cf: &str, prefix: &str) -> Result<Vec<String>, DBError> {
+        let handle = self.instance.cf_handle(cf).unwrap();
+        let mut keys = Vec::new();
+
+        for item in self.instance.prefix_iterator_cf(&handle, prefix) {
+            let (key, edge_key) = item.map_err(|e| DBError::GetEdgeError {
+                key: prefix.to_string(),
                 error: e.to_string(),
             })?;
+            if !key.starts_with(prefix.as_bytes()) {
+                break;
+            }
+            keys.push(String::from_utf8_lossy(&edge_key).to_string());
+        }
 
-        Ok(())
+        Ok(keys)
+    }
+
+    /// Edges going out of or coming into any of `nodes`, each once.
+    fn _attached_edges<T: Node>(&self, nodes: &[T]) -> Result<Vec<EdgeItem>, DBError> {
+        let handle = self.instance.cf_handle(EDGES_CF).unwrap();
+        let mut edges = HashMap::new();
+
+        for node in nodes {
+            let prefix = Self::_adjacency_prefix(node.key(), None);
+            for cf in [OUT_EDGES_CF, IN_EDGES_CF] {
+                for key in self._adjacent_keys(cf, &prefix)? {
+                    if let Entry::Vacant(entry) = edges.entry(key) {
+                        let edge = self._get_edge(entry.key(), &handle)?;
+                        entry.insert(edge);
+                    }
+                }
+            }
+        }
+
+        Ok(edges.into_values().collect())
+    }
+
+    /// Streams the edges listed under `prefix` in the `cf` adjacency column
+    /// family. Only the edge keys are read upfront, each edge is loaded as
+    /// the stream is polled.
+    fn _adjacent_edges(&self, cf: &str, prefix: String) -> EdgeStream<'_> {
+        let keys = match self._adjacent_keys(cf, &prefix) {
+            Ok(keys) => keys,
+            Err(error) => return stream::once(future::ready(Err(error))).boxed(),
+        };
+
+        stream::iter(keys)
+            .map(move |key| {
+                let handle = self.instance.cf_handle(EDGES_CF).unwrap();
+                self._get_edge(&key, &handle)
+            })
+            .boxed()
+    }
+}
+
+impl VectorSource for Database {
+    fn read(&self, key: &[u8]) -> Result<Option<Vec<u8>>, String> {
+        let handle = self.instance.cf_handle(VECTORS_CF).unwrap();
+        self.instance
+            .get_cf(&handle, key)
+            .map_err(|e| e.to_string())
+    }
+
+    fn scan(&self, prefix: &[u8]) -> Result<Vec<VectorItem>, String> {
+        let handle = self.instance.cf_handle(VECTORS_CF).unwrap();
+        let mut items = Vec::new();
+        for item in self.instance.prefix_iterator_cf(&handle, prefix) {
+            let (key, value) = item.map_err(|e| e.to_string())?;
+            if !key.starts_with(prefix) {
+                break;
+            }
+            items.push((key.to_vec(), value.to_vec()));
+        }
+        Ok(items)
     }
 }
 
 #[async_trait]
 impl DB for Database {
     type Config = RocksDBConfig;
+    type Transaction<'a> = Transaction<'a>;
 
     fn key(&self) -> String {
         self.key.clone()
@@ -204,8 +625,12 @@ impl DB for Database {
     where
         Self: Sized,
     {
-        Database::create_db_instance(&config)
-            .map(|instance| Database { key, instance })
+        Database::create_db_instance(config)
+            .map(|instance| Database {
+                key,
+                instance,
+                index_lock: Mutex::new(()),
+            })
             .map_err(|e| DBError::ConnectError {
                 error: e.to_string(),
             })
@@ -226,20 +651,21 @@ impl DB for Database {
 
     async fn insert_entities(&self, entities: &[EntityItem]) -> Result<(), DBError> {
         let handle = self.instance.cf_handle(ENTITIES_CF).unwrap();
-        let mut batch = rocksdb::WriteBatch::default();
+        let entities_serialized =
+            serialize_batch(entities, |entity| entity.name.clone(), EntityItem::to_bytes)?;
+        let mut batch = WriteBatch::default();
 
-        for entity in entities {
-            let entity_serialized = self._entity_to_bytes_with_error(entity)?;
-            batch.put_cf(&handle, entity.name.to_string(), entity_serialized);
+        for (entity, entity_serialized) in entities.iter().zip(entities_serialized) {
+            batch.put_cf(&handle, &entity.name, entity_serialized);
         }
 
-        Ok(())
+        self._write_batch(batch)
     }
 
     async fn remove_entity(&self, entity: &EntityItem) -> Result<(), DBError> {
         let handle = self.instance.cf_handle(ENTITIES_CF).unwrap();
         self.instance
-            .delete_cf(&handle, entity.name.to_string())
+            .delete_cf(&handle, &entity.name)
             .map_err(|e| DBError::RemoveEntityError {
                 key: entity.name.to_string(),
                 error: e.to_string(),
@@ -250,11 +676,11 @@ impl DB for Database {
 
     async fn remove_entities(&self, entities: &[EntityItem]) -> Result<(), DBError> {
         let handle = self.instance.cf_handle(ENTITIES_CF).unwrap();
-        let mut batch = rocksdb::WriteBatch::default();
+        let mut batch = WriteBatch::default();
         for entity in entities {
-            batch.delete_cf(&handle, entity.name.to_string());
+            batch.delete_cf(&handle, &entity.name);
         }
-        Ok(())
+        self._write_batch(batch)
     }
 
     async fn update_entity(&self, entity: &EntityItem) -> Result<(), DBError> {
@@ -270,47 +696,269 @@ impl DB for Database {
         self._get_node(id, &nodes)
     }
 
-    async fn insert_node<T: Node + Sync>(&self, node: &T) -> Result<(), DBError> {
+    async fn get_nodes<T: Node>(&self, ids: &[NodeID]) -> Result<Vec<T>, DBError> {
+        let found = self.get_node_bytes(&T::entity_name(), ids).await?;
+        found
+            .iter()
+            .map(|(id, node_bytes)| Self::_decode_node(*id, node_bytes))
+            .collect()
+    }
+
+    async fn get_node_bytes(
+        &self,
+        entity: &str,
+        ids: &[NodeID],
+    ) -> Result<Vec<(NodeID, Vec<u8>)>, DBError> {
         let nodes = self.instance.cf_handle(NODES_CF).unwrap();
-        self._insert_node(node, &nodes)?;
-        self._insert_entity_if_needed(node);
-        Ok(())
+        let entity_nodes = self.instance.cf_handle(ENTITY_NODES_CF).unwrap();
+        let mut found = Vec::with_capacity(ids.len());
+
+        for id in ids {
+            if self._has_entity_node(entity, *id, &entity_nodes)? {
+                found.push((*id, self._get_node_bytes(*id, &nodes)?));
+            }
+        }
+
+        Ok(found)
     }
 
-    async fn insert_nodes<T: Node>(&self, nodes: &[T]) -> Result<(), DBError> {
-        let handle = self.instance.cf_handle(NODES_CF).unwrap();
-        let mut batch = rocksdb::WriteBatch::default();
+    async fn get_node_ids(&self, entity: &str) -> Result<Vec<NodeID>, DBError> {
+        let handle = self.instance.cf_handle(ENTITY_NODES_CF).unwrap();
+        let prefix = format!("{}:", entity);
+        let mut ids = Vec::new();
 
-        for node in nodes {
-            let node_serialized = self._node_to_bytes_with_error(node)?;
-            batch.put_cf(&handle, node.key().to_string(), node_serialized);
+        for item in self.instance.prefix_iterator_cf(&handle, prefix.as_bytes()) {
+            let (key, _) = item.map_err(|e| DBError::GetEntityError {
+                key: entity.to_string(),
+                error: e.to_string(),
+            })?;
+            let Some(id) = Self::_entity_node_id(entity, &prefix, &key) else {
+                break;
+            };
+            ids.push(id?);
         }
 
-        Ok(())
+        Ok(ids)
     }
 
-    async fn remove_node<T: Node>(&self, node: &T) -> Result<(), DBError> {
-        let nodes = self.instance.cf_handle(NODES_CF).unwrap();
-        let res = self.instance.delete_cf(&nodes, node.key().to_string());
+    fn scan_nodes<T: Node>(&self, after: Option<NodeID>) -> NodeStream<'_, T> {
+        self.scan_node_bytes(&T::entity_name(), after)
+            .map(|node| {
+                let (id, node_bytes) = node?;
+                Self::_decode_node(id, &node_bytes)
+            })
+            .boxed()
+    }
+
+    fn scan_node_bytes(
+        &self,
+        entity: &str,
+        after: Option<NodeID>,
+    ) -> NodeStream<'_, (NodeID, Vec<u8>)> {
+        let entity = entity.to_string();
+        let prefix = format!("{}:", entity);
+        let start = match after {
+            Some(id) => Self::_entity_node_key(&entity, id),
+            None => prefix.clone(),
+        };
+        let handle = self.instance.cf_handle(ENTITY_NODES_CF).unwrap();
+        let mode = IteratorMode::From(start.as_bytes(), Direction::Forward);
+        let ids = self
+            .instance
+            .iterator_cf(&handle, mode)
+            .filter(move |item| !matches!(item, Ok((key, _)) if **key == *start.as_bytes()))
+            .map_while(move |item| match item {
+                Ok((key, _)) => Self::_entity_node_id(&entity, &prefix, &key),
+                Err(e) => Some(Err(DBError::GetEntityError {
+                    key: entity.clone(),
+                    error: e.to_string(),
+                })),
+            });
 
-        match res {
-            Ok(_) => Ok(()),
-            Err(e) => Err(DBError::RemoveNodeError {
-                key: node.key(),
+        stream::iter(ids)
+            .map(move |id| {
+                let id = id?;
+                let handle = self.instance.cf_handle(NODES_CF).unwrap();
+                Ok((id, self._get_node_bytes(id, &handle)?))
+            })
+            .boxed()
+    }
+
+    async fn get_node_ids_by_index(
+        &self,
+        entity: &str,
+        field: &str,
+        value: &Value,
+    ) -> Result<Vec<NodeID>, DBError> {
+        self._index_ids(entity, index_prefix(entity, field, value))
+    }
+
+    async fn get_node_ids_by_composite_index(
+        &self,
+        entity: &str,
+        fields: &[String],
+        values: &[Value],
+    ) -> Result<Vec<NodeID>, DBError> {
+        self._index_ids(entity, composite_prefix(entity, fields, values))
+    }
+
+    async fn search_node_ids(
+        &self,
+        entity: &str,
+        field: &str,
+        query: &str,
+    ) -> Result<Vec<(NodeID, f64)>, DBError> {
+        bm25_scores(entity, field, query, |prefix| {
+            let keys = self._index_keys(entity, prefix)?;
+            Ok(keys.into_iter().map(Vec::from).collect())
+        })
+    }
+
+    async fn nearest_node_ids(
+        &self,
+        entity: &str,
+        field: &str,
+        metric: VectorMetric,
+        query: &[f32],
+        k: usize,
+    ) -> Result<Vec<(NodeID, f32)>, DBError> {
+        VectorWrites::new(self)
+            .nearest(&vector_graph(entity, field, metric), query, k)
+            .map_err(|error| DBError::QueryError { error })
+    }
+
+    async fn get_node_ids_by_index_range(
+        &self,
+        entity: &str,
+        field: &str,
+        start: Bound<&Value>,
+        end: Bound<&Value>,
+    ) -> Result<Vec<NodeID>, DBError> {
+        let handle = self.instance.cf_handle(INDEXES_CF).unwrap();
+        let range = IndexRange::new(entity, field, start, end);
+        let seek_key = range.seek_key();
+        let mode = IteratorMode::From(&seek_key, Direction::Forward);
+        let mut ids = Vec::new();
+
+        for item in self.instance.iterator_cf(&handle, mode) {
+            let (key, _) = item.map_err(|e| DBError::GetEntityError {
+                key: entity.to_string(),
                 error: e.to_string(),
-            }),
+            })?;
+            if range.is_past(&key) {
+                break;
+            }
+            if range.contains_key(&key) {
+                ids.extend(index_id(&key));
+            }
         }
+
+        Ok(ids)
     }
 
-    async fn remove_nodes<T: Node>(&self, nodes: &[T]) -> Result<(), DBError> {
-        let handle = self.instance.cf_handle(NODES_CF).unwrap();
-        let mut batch = rocksdb::WriteBatch::default();
+    fn scan_index_ids(
+        &self,
+        entity: &str,
+        field: &str,
+        after: Option<(&Value, NodeID)>,
+    ) -> NodeStream<'_, NodeID> {
+        let entity = entity.to_string();
+        let prefix = field_prefix(&entity, field);
+        let start = match after {
+            Some((value, id)) => index_key(&entity, field, value, id),
+            None => prefix.clone(),
+        };
+        let handle = self.instance.cf_handle(INDEXES_CF).unwrap();
+        let mode = IteratorMode::From(&start, Direction::Forward);
+        let ids = self
+            .instance
+            .iterator_cf(&handle, mode)
+            .filter(move |item| !matches!(item, Ok((key, _)) if **key == *start))
+            .map_while(move |item| match item {
+                Ok((key, _)) => index_id(&key).filter(|_| key.starts_with(&prefix)).map(Ok),
+                Err(e) => Some(Err(DBError::GetEntityError {
+                    key: entity.clone(),
+                    error: e.to_string(),
+                })),
+            });
 
-        for node in nodes {
-            batch.delete_cf(&handle, node.key().to_string());
+        stream::iter(ids).boxed()
+    }
+
+    async fn get_node_ids_by_geo(
+        &self,
+        entity: &str,
+        field: &str,
+        area: &GeoArea,
+    ) -> Result<Vec<NodeID>, DBError> {
+        let handle = self.instance.cf_handle(INDEXES_CF).unwrap();
+        let mut ids = Vec::new();
+
+        for (start, end) in geo_ranges(entity, field, area) {
+            let mode = IteratorMode::From(&start, Direction::Forward);
+            for item in self.instance.iterator_cf(&handle, mode) {
+                let (key, _) = item.map_err(|e| DBError::GetEntityError {
+                    key: entity.to_string(),
+                    error: e.to_string(),
+                })?;
+                if *key >= *end {
+                    break;
+                }
+                if geo_point(&key).is_some_and(|point| area.contains(&point)) {
+                    ids.extend(index_id(&key));
+                }
+            }
         }
 
-        Ok(())
+        Ok(ids)
+    }
+
+    async fn insert_node<T: Node + Sync>(&self, node: &T) -> Result<(), DBError> {
+        self.insert_nodes(std::slice::from_ref(node))
+            .await
+            .map_err(|e| {
+                e.for_item(|error| DBError::InsertNodeError {
+                    key: node.key(),
+                    error,
+                })
+            })
+    }
+
+    async fn insert_nodes<T: Node>(&self, nodes: &[T]) -> Result<(), DBError> {
+        let nodes_serialized = serialize_batch(nodes, |node| node.key().to_string(), T::to_bytes)?;
+        let _guard = self.index_lock.lock().unwrap();
+        let mut batch = WriteBatch::default();
+        self._insert_nodes(&mut batch, nodes, nodes_serialized)?;
+        self._write_batch(batch)
+    }
+
+    async fn remove_node<T: Node>(&self, node: &T) -> Result<(), DBError> {
+        self.remove_nodes(std::slice::from_ref(node))
+            .await
+            .map_err(|e| {
+                e.for_item(|error| DBError::RemoveNodeError {
+                    key: node.key(),
+                    error,
+                })
+            })
+    }
+
+    async fn remove_nodes<T: Node>(&self, nodes: &[T]) -> Result<(), DBError> {
+        let _guard = self.index_lock.lock().unwrap();
+        let mut batch = WriteBatch::default();
+        self._remove_nodes(&mut batch, nodes)?;
+        self._write_batch(batch)
+    }
+
+    async fn remove_nodes_with_edges<T: Node>(&self, nodes: &[T]) -> Result<(), DBError> {
+        // Edges are read under the lock, so none can be linked to the nodes
+        // between reading them and removing the nodes.
+        let _guard = self.index_lock.lock().unwrap();
+        let edges = self._attached_edges(nodes)?;
+        let mut batch = WriteBatch::default();
+        self._remove_nodes(&mut batch, nodes)?;
+        self._remove_edges(&mut batch, &edges);
+        self._write_batch(batch)
     }
 
     async fn update_node<T: Node>(&self, node: &T) -> Result<(), DBError> {
@@ -321,57 +969,437 @@ impl DB for Database {
      * Edge methods
      */
     async fn get_edge(&self, from: NodeID, to: NodeID) -> Result<EdgeItem, DBError> {
+        let edges = self.get_edges(from, to).await?;
+        Self::_first_edge(&EdgeItem::format_prefix(from, to), edges)
+    }
+
+    async fn get_edges(&self, from: NodeID, to: NodeID) -> Result<Vec<EdgeItem>, DBError> {
         let handle = self.instance.cf_handle(EDGES_CF).unwrap();
-        self._get_edge(from, to, &handle)
+        let prefix = EdgeItem::format_prefix(from, to);
+        Self::_collect_edges(&prefix, self.instance.prefix_iterator_cf(&handle, &prefix))
     }
 
-    async fn insert_edge(&self, edge: &EdgeItem) -> Result<(), DBError> {
+    async fn get_edge_by_label(
+        &self,
+        from: NodeID,
+        to: NodeID,
+        label: &str,
+    ) -> Result<EdgeItem, DBError> {
         let handle = self.instance.cf_handle(EDGES_CF).unwrap();
-        self._insert_edge(edge, &handle)?;
-        Ok(())
+        self._get_edge(&EdgeItem::format_key(from, to, label), &handle)
     }
 
-    async fn insert_edges(&self, edges: &[EdgeItem]) -> Result<(), DBError> {
+    async fn scan_edges(&self) -> Result<Vec<EdgeItem>, DBError> {
         let handle = self.instance.cf_handle(EDGES_CF).unwrap();
-        let mut batch = rocksdb::WriteBatch::default();
+        let mut edges = Vec::new();
 
-        for edge in edges {
-            let edge_serialized = self._edge_to_bytes_with_error(edge)?;
-            let id = EdgeItem::format_key(edge.from, edge.to);
-            batch.put_cf(&handle, id, edge_serialized);
+        for item in self.instance.iterator_cf(&handle, IteratorMode::Start) {
+            let (key, edge_bytes) = item.map_err(|e| DBError::GetEdgeError {
+                key: EDGES_CF.to_string(),
+                error: e.to_string(),
+            })?;
+            let edge = EdgeItem::from_bytes(&edge_bytes).map_err(|e| DBError::GetEdgeError {
+                key: String::from_utf8_lossy(&key).to_string(),
+                error: e.to_string(),
+            })?;
+            edges.push(edge);
         }
 
-        Ok(())
+        Ok(edges)
     }
 
-    async fn remove_edge(&self, edge: &EdgeItem) -> Result<(), DBError> {
-        let handle = self.instance.cf_handle(EDGES_CF).unwrap();
-        let id = EdgeItem::format_key(edge.from, edge.to);
-        self.instance
-            .delete_cf(&handle, id)
-            .map_err(|e| DBError::RemoveEdgeError {
-                key: format!("{}-{}", edge.from, edge.to),
-                error: e.to_string(),
-            })?;
+    fn out_edges(&self, id: NodeID, label: Option<&str>) -> EdgeStream<'_> {
+        self._adjacent_edges(OUT_EDGES_CF, Self::_adjacency_prefix(id, label))
+    }
 
-        Ok(())
+    fn in_edges(&self, id: NodeID, label: Option<&str>) -> EdgeStream<'_> {
+        self._adjacent_edges(IN_EDGES_CF, Self::_adjacency_prefix(id, label))
     }
 
-    async fn remove_edges(&self, edges: &[EdgeItem]) -> Result<(), DBError> {
-        let handle = self.instance.cf_handle(EDGES_CF).unwrap();
-        let mut batch = rocksdb::WriteBatch::default();
+    async fn insert_edge(&self, edge: &EdgeItem) -> Result<(), DBError> {
+        self.insert_edges(std::slice::from_ref(edge))
+            .await
+            .map_err(|e| {
+                e.for_item(|error| DBError::InsertEdgeError {
+                    key: edge.key(),
+                    error,
+                })
+            })
+    }
 
-        for edge in edges {
-            let id = EdgeItem::format_key(edge.from, edge.to);
-            batch.delete_cf(&handle, id);
-        }
+    async fn insert_edges(&self, edges: &[EdgeItem]) -> Result<(), DBError> {
+        let edges_serialized = serialize_batch(edges, EdgeItem::key, EdgeItem::to_bytes)?;
+        let _guard = self.index_lock.lock().unwrap();
+        let mut batch = WriteBatch::default();
+        self._insert_edges(&mut batch, edges, edges_serialized);
+        self._write_batch(batch)
+    }
 
-        Ok(())
+    async fn remove_edge(&self, edge: &EdgeItem) -> Result<(), DBError> {
+        self.remove_edges(std::slice::from_ref(edge))
+            .await
+            .map_err(|e| {
+                e.for_item(|error| DBError::RemoveEdgeError {
+                    key: edge.key(),
+                    error,
+                })
+            })
+    }
+
+    async fn remove_edges(&self, edges: &[EdgeItem]) -> Result<(), DBError> {
+        let _guard = self.index_lock.lock().unwrap();
+        let mut batch = WriteBatch::default();
+        self._remove_edges(&mut batch, edges);
+        self._write_batch(batch)
     }
 
     async fn update_edge(&self, edge: &EdgeItem) -> Result<(), DBError> {
         self.insert_edge(edge).await
     }
+
+    /**
+     * Transaction methods
+     */
+    async fn transaction<'a, F, Fut, R>(&'a self, cb: F) -> Result<R, DBError>
+    where
+        F: FnOnce(Self::Transaction<'a>) -> Fut + Send,
+        Fut: Future<Output = Result<R, DBError>> + Send,
+        R: Send,
+    {
+        let tx = Transaction {
+            db: self,
+            inner: Arc::new(Mutex::new(Some(self.instance.transaction()))),
+        };
+        let result = cb(tx.clone()).await;
+        let inner = tx.close()?;
+
+        let to_error = |e: rocksdb::Error| DBError::TransactionError {
+            error: e.to_string(),
+        };
+        match result {
+            Ok(value) => {
+                let _guard = self.index_lock.lock().unwrap();
+                inner.commit().map(|_| value).map_err(to_error)
+            }
+            Err(e) => {
+                inner.rollback().map_err(to_error)?;
+                Err(e)
+            }
+        }
+    }
+}
+
+/// RocksDB transaction over the nodes, edges and entities, with their
+/// indexes, unique values, vector graphs and adjacency keys. Reads go through
+/// `get_for_update` so the commit fails if any key read here was written by
+/// someone else in the meantime. Prefix scans only track the keys they
+/// found, not the ones added under the prefix since.
+#[derive(Clone)]
+pub struct Transaction<'a> {
+    db: &'a Database,
+    inner: Arc<Mutex<Option<rocksdb::Transaction<'a, Instance>>>>,
+}
+impl<'a> Transaction<'a> {
+    fn close(&self) -> Result<rocksdb::Transaction<'a, Instance>, DBError> {
+        self.inner
+            .lock()
+            .unwrap()
+            .take()
+            .ok_or_else(Self::_closed_error)
+    }
+
+    fn _closed_error() -> DBError {
+        DBError::TransactionError {
+            error: "Transaction is already closed".to_string(),
+        }
+    }
+
+    fn _with<R>(
+        &self,
+        cb: impl FnOnce(&rocksdb::Transaction<'a, Instance>) -> Result<R, rocksdb::Error>,
+    ) -> Result<Option<R>, rocksdb::Error> {
+        match self.inner.lock().unwrap().as_ref() {
+            Some(tx) => cb(tx).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn _get(&self, cf: &str, key: impl AsRef<[u8]>) -> Result<Option<Vec<u8>>, String> {
+        let handle = self.db.instance.cf_handle(cf).unwrap();
+        self._with(|tx| tx.get_for_update_cf(&handle, key, true))
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| Self::_closed_error().to_string())
+    }
+
+    /// Keys and values under `prefix`, each read through `get_for_update`.
+    fn _scan(&self, cf: &str, prefix: &[u8]) -> Result<Vec<VectorItem>, String> {
+        let handle = self.db.instance.cf_handle(cf).unwrap();
+        let keys = self
+            ._with(|tx| {
+                let mut keys = Vec::new();
+                for item in tx.prefix_iterator_cf(&handle, prefix) {
+                    let (key, _) = item?;
+                    if !key.starts_with(prefix) {
+                        break;
+                    }
+                    keys.push(key.to_vec());
+                }
+                Ok(keys)
+            })
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| Self::_closed_error().to_string())?;
+
+        let mut items = Vec::with_capacity(keys.len());
+        for key in keys {
+            if let Some(value) = self._get(cf, &key)? {
+                items.push((key, value));
+            }
+        }
+        Ok(items)
+    }
+
+    fn _put(&self, cf: &str, key: impl AsRef<[u8]>, value: &[u8]) -> Result<(), String> {
+        let handle = self.db.instance.cf_handle(cf).unwrap();
+        self._with(|tx| tx.put_cf(&handle, key, value))
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| Self::_closed_error().to_string())
+    }
+
+    /// Index entries of the version of the node this transaction sees.
+    fn _stored_index_entries<T: Node>(&self, id: NodeID) -> Result<IndexEntries, DBError> {
+        let to_error = |error: String| DBError::GetNodeError { key: id, error };
+        let node = self
+            ._get(NODES_CF, id.to_string())
+            .map_err(to_error)?
+            .and_then(|node_bytes| T::from_bytes(&node_bytes).ok());
+        match node {
+            Some(node) => index_entries(&node).map_err(|e| to_error(e.to_string())),
+            None => Ok(IndexEntries::default()),
+        }
+    }
+
+    fn _delete(&self, cf: &str, key: impl AsRef<[u8]>) -> Result<(), String> {
+        let handle = self.db.instance.cf_handle(cf).unwrap();
+        self._with(|tx| tx.delete_cf(&handle, key))
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| Self::_closed_error().to_string())
+    }
+
+    fn _write_vectors(&self, writes: Vec<VectorWrite>) -> Result<(), String> {
+        writes.iter().try_for_each(|(key, value)| match value {
+            Some(value) => self._put(VECTORS_CF, key, value),
+            None => self._delete(VECTORS_CF, key),
+        })
+    }
+}
+
+impl<'a> VectorSource for Transaction<'a> {
+    fn read(&self, key: &[u8]) -> Result<Option<Vec<u8>>, String> {
+        self._get(VECTORS_CF, key)
+    }
+
+    fn scan(&self, prefix: &[u8]) -> Result<Vec<VectorItem>, String> {
+        self._scan(VECTORS_CF, prefix)
+    }
+}
+
+#[async_trait]
+impl<'a> DBTransaction for Transaction<'a> {
+    /**
+     * Entity methods
+     */
+    async fn get_entity(&self, name: &str) -> Result<EntityItem, DBError> {
+        let to_error = |error: String| DBError::GetEntityError {
+            key: name.to_string(),
+            error,
+        };
+        let entity_bytes = self
+            ._get(ENTITIES_CF, name)
+            .map_err(to_error)?
+            .ok_or_else(|| to_error("Entity not found".to_string()))?;
+        EntityItem::from_bytes(&entity_bytes).map_err(|e| to_error(e.to_string()))
+    }
+
+    async fn insert_entity(&self, entity: &EntityItem) -> Result<(), DBError> {
+        let entity_serialized = self.db._entity_to_bytes_with_error(entity)?;
+        self._put(ENTITIES_CF, &entity.name, &entity_serialized)
+            .map_err(|error| DBError::InsertEntityError {
+                key: entity.name.to_string(),
+                error,
+            })
+    }
+
+    async fn remove_entity(&self, entity: &EntityItem) -> Result<(), DBError> {
+        self._delete(ENTITIES_CF, &entity.name)
+            .map_err(|error| DBError::RemoveEntityError {
+                key: entity.name.to_string(),
+                error,
+            })
+    }
+
+    /**
+     * Node methods
+     */
+    async fn get_node<T: Node>(&self, id: NodeID) -> Result<T, DBError> {
+        let to_error = |error: String| DBError::GetNodeError { key: id, error };
+        let node_bytes = self
+            ._get(NODES_CF, id.to_string())
+            .map_err(to_error)?
+            .ok_or_else(|| to_error("Node not found".to_string()))?;
+        T::from_bytes(&node_bytes).map_err(|e| to_error(e.to_string()))
+    }
+
+    async fn insert_node<T: Node>(&self, node: &T) -> Result<(), DBError> {
+        let node_serialized = self.db._node_to_bytes_with_error(node)?;
+        let entity_name = node.entity();
+        if self.get_entity(&entity_name).await.is_err() {
+            self.insert_entity(&EntityItem::new(entity_name.clone()))
+                .await?;
+        }
+
+        let to_error = |error: String| DBError::InsertNodeError {
+            key: node.key(),
+            error,
+        };
+        let stored = self._stored_index_entries::<T>(node.key())?;
+        let entries = index_entries(node).map_err(|e| to_error(e.to_string()))?;
+        for unique in &entries.unique {
+            let owner = self
+                ._get(UNIQUE_CF, &unique.key)
+                .map_err(to_error)?
+                .and_then(|owner| decode_id(&owner));
+            if owner.is_some_and(|owner| owner != node.key()) {
+                return Err(unique.violation(&entity_name));
+            }
+        }
+
+        let mut vectors = VectorWrites::new(self);
+        vectors
+            .update(node.key(), &stored.vectors, &entries.vectors)
+            .map_err(to_error)?;
+        let vectors = vectors.into_writes();
+
+        let entity_node_key = Database::_entity_node_key(&entity_name, node.key());
+        self._write_vectors(vectors)
+            .and_then(|_| {
+                stored
+                    .keys
+                    .iter()
+                    .try_for_each(|key| self._delete(INDEXES_CF, key))
+            })
+            .and_then(|_| {
+                stored
+                    .unique
+                    .iter()
+                    .try_for_each(|entry| self._delete(UNIQUE_CF, &entry.key))
+            })
+            .and_then(|_| {
+                entries
+                    .keys
+                    .iter()
+                    .try_for_each(|key| self._put(INDEXES_CF, key, &[]))
+            })
+            .and_then(|_| {
+                entries
+                    .unique
+                    .iter()
+                    .try_for_each(|entry| self._put(UNIQUE_CF, &entry.key, &encode_id(node.key())))
+            })
+            .and_then(|_| self._put(NODES_CF, node.key().to_string(), &node_serialized))
+            .and_then(|_| self._put(ENTITY_NODES_CF, &entity_node_key, &[]))
+            .map_err(to_error)
+    }
+
+    async fn remove_node<T: Node>(&self, node: &T) -> Result<(), DBError> {
+        let to_error = |error: String| DBError::RemoveNodeError {
+            key: node.key(),
+            error,
+        };
+        let stored = self._stored_index_entries::<T>(node.key())?;
+        let mut vectors = VectorWrites::new(self);
+        vectors
+            .update(node.key(), &stored.vectors, &[])
+            .map_err(to_error)?;
+        let vectors = vectors.into_writes();
+
+        let entity_node_key = Database::_entity_node_key(&node.entity(), node.key());
+        self._write_vectors(vectors)
+            .and_then(|_| {
+                stored
+                    .keys
+                    .iter()
+                    .try_for_each(|key| self._delete(INDEXES_CF, key))
+            })
+            .and_then(|_| {
+                stored
+                    .unique
+                    .iter()
+                    .try_for_each(|entry| self._delete(UNIQUE_CF, &entry.key))
+            })
+            .and_then(|_| self._delete(NODES_CF, node.key().to_string()))
+            .and_then(|_| self._delete(ENTITY_NODES_CF, &entity_node_key))
+            .map_err(to_error)
+    }
+
+    /**
+     * Edge methods
+     */
+    async fn get_edge(&self, from: NodeID, to: NodeID) -> Result<EdgeItem, DBError> {
+        let prefix = EdgeItem::format_prefix(from, to);
+        let items =
+            self._scan(EDGES_CF, prefix.as_bytes())
+                .map_err(|error| DBError::GetEdgeError {
+                    key: prefix.clone(),
+                    error,
+                })?;
+        let items = items
+            .into_iter()
+            .map(|(key, value)| Ok((key.into_boxed_slice(), value.into_boxed_slice())));
+        let edges = Database::_collect_edges(&prefix, items)?;
+        Database::_first_edge(&prefix, edges)
+    }
+
+    async fn get_edge_by_label(
+        &self,
+        from: NodeID,
+        to: NodeID,
+        label: &str,
+    ) -> Result<EdgeItem, DBError> {
+        let id = EdgeItem::format_key(from, to, label);
+        let to_error = |error: String| DBError::GetEdgeError {
+            key: id.to_string(),
+            error,
+        };
+        let edge_bytes = self
+            ._get(EDGES_CF, &id)
+            .map_err(to_error)?
+            .ok_or_else(|| to_error("Edge not found".to_string()))?;
+        EdgeItem::from_bytes(&edge_bytes).map_err(|e| to_error(e.to_string()))
+    }
+
+    async fn insert_edge(&self, edge: &EdgeItem) -> Result<(), DBError> {
+        let edge_serialized = self.db._edge_to_bytes_with_error(edge)?;
+        let edge_key = edge.key();
+        Database::_adjacency_keys(edge)
+            .iter()
+            .try_for_each(|(cf, key)| self._put(cf, key, edge_key.as_bytes()))
+            .and_then(|_| self._put(EDGES_CF, &edge_key, &edge_serialized))
+            .map_err(|error| DBError::InsertEdgeError {
+                key: edge.key(),
+                error,
+            })
+    }
+
+    async fn remove_edge(&self, edge: &EdgeItem) -> Result<(), DBError> {
+        Database::_adjacency_keys(edge)
+            .iter()
+            .try_for_each(|(cf, key)| self._delete(cf, key))
+            .and_then(|_| self._delete(EDGES_CF, edge.key()))
+            .map_err(|error| DBError::RemoveEdgeError {
+                key: edge.key(),
+                error,
+            })
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -0,0 +1,216 @@
+use crate::{
+    db::{DBError, DB},
+    edge::EdgeItem,
+    node::Node,
+};
+use arkycore::types::{NodeID, Value};
+use futures::future::BoxFuture;
+use futures::TryStreamExt;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+
+type CheckFn<D> =
+    dyn for<'d> Fn(&'d D, NodeID) -> BoxFuture<'d, Result<bool, DBError>> + Send + Sync;
+
+enum Step<D> {
+    Out(String),
+    In(String),
+    Both(String),
+    Filter {
+        entity: String,
+        check: Arc<CheckFn<D>>,
+    },
+    Dedup,
+    Limit(usize),
+}
+impl<D> fmt::Debug for Step<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Out(label) => write!(f, "Out({:?})", label),
+            Self::In(label) => write!(f, "In({:?})", label),
+            Self::Both(label) => write!(f, "Both({:?})", label),
+            Self::Filter { entity, .. } => write!(f, "Filter({:?})", entity),
+            Self::Dedup => write!(f, "Dedup"),
+            Self::Limit(limit) => write!(f, "Limit({})", limit),
+        }
+    }
+}
+
+/// Walk of the graph from a start node, one step after the other. Nothing
+/// is read until a terminal method runs, and nodes are then walked depth
+/// first, so a full `limit` stops the walk early.
+pub struct Traversal<'a, D> {
+    db: &'a D,
+    start: NodeID,
+    steps: Vec<Step<D>>,
+}
+impl<'a, D: DB + Sync> Traversal<'a, D> {
+    pub fn new(db: &'a D, start: NodeID) -> Self {
+        Self {
+            db,
+            start,
+            steps: Vec::new(),
+        }
+    }
+    fn push(&mut self, step: Step<D>) -> &mut Self {
+        self.steps.push(step);
+        self
+    }
+    /// Moves to the targets of the outgoing edges with `label`.
+    pub fn out(&mut self, label: &str) -> &mut Self {
+        self.push(Step::Out(label.to_string()))
+    }
+    /// Moves to the sources of the incoming edges with `label`.
+    pub fn in_(&mut self, label: &str) -> &mut Self {
+        self.push(Step::In(label.to_string()))
+    }
+    /// Moves to the nodes linked by edges with `label` either way.
+    pub fn both(&mut self, label: &str) -> &mut Self {
+        self.push(Step::Both(label.to_string()))
+    }
+    /// Keeps the nodes of type `T` whose `prop` equals `value`.
+    pub fn has<T: Node>(&mut self, prop: &str, value: impl Into<Value>) -> &mut Self {
+        let (prop, value) = (prop.to_string(), value.into());
+        self.filter::<T>(move |node| match node.to_value() {
+            Ok(node_value) => node_value.get(&prop).unwrap_or(&Value::Null) == &value,
+            Err(_) => false,
+        })
+    }
+    /// Keeps the nodes of type `T` passing `cb`.
+    pub fn filter<T: Node>(
+        &mut self,
+        cb: impl Fn(&T) -> bool + Send + Sync + 'static,
+    ) -> &mut Self {
+        let cb = Arc::new(cb);
+        self.push(Step::Filter {
+            entity: T::entity_name(),
+            check: checker::<D, _>(move |db, id| {
+                let cb = cb.clone();
+                Box::pin(async move { Ok(cb(&db.get_node::<T>(id).await?)) })
+            }),
+        })
+    }
+    /// Drops the nodes already seen at this step.
+    pub fn dedup(&mut self) -> &mut Self {
+        self.push(Step::Dedup)
+    }
+    /// Lets the first `limit` nodes reaching this step through.
+    pub fn limit(&mut self, limit: usize) -> &mut Self {
+        self.push(Step::Limit(limit))
+    }
+
+    /// Ids of the nodes reached by the last step, in walk order.
+    pub async fn ids(&self) -> Result<Vec<NodeID>, DBError> {
+        let mut ids = Vec::new();
+        let mut seen: HashMap<usize, HashSet<NodeID>> = HashMap::new();
+        let mut passed: HashMap<usize, usize> = HashMap::new();
+        let mut entities: HashMap<String, HashSet<NodeID>> = HashMap::new();
+        // Nodes waiting at a step at or before a full limit can't reach the
+        // end anymore.
+        let mut blocked: Option<usize> = None;
+        let mut stack = vec![(0, self.start)];
+
+        while let Some((index, id)) = stack.pop() {
+            if blocked.is_some_and(|blocked| index <= blocked) {
+                continue;
+            }
+            let Some(step) = self.steps.get(index) else {
+                ids.push(id);
+                continue;
+            };
+
+            let next = match step {
+                Step::Out(label) => {
+                    let edges: Vec<EdgeItem> =
+                        self.db.out_edges(id, Some(label)).try_collect().await?;
+                    edges.into_iter().map(|edge| edge.to).collect()
+                }
+                Step::In(label) => {
+                    let edges: Vec<EdgeItem> =
+                        self.db.in_edges(id, Some(label)).try_collect().await?;
+                    edges.into_iter().map(|edge| edge.from).collect()
+                }
+                Step::Both(label) => {
+                    let out: Vec<EdgeItem> =
+                        self.db.out_edges(id, Some(label)).try_collect().await?;
+                    let in_: Vec<EdgeItem> =
+                        self.db.in_edges(id, Some(label)).try_collect().await?;
+                    let targets = out.into_iter().map(|edge| edge.to);
+                    targets
+                        .chain(in_.into_iter().map(|edge| edge.from))
+                        .collect()
+                }
+                Step::Filter { entity, check } => {
+                    if !entities.contains_key(entity) {
+                        let ids = self.db.get_node_ids(entity).await?;
+                        entities.insert(entity.clone(), ids.into_iter().collect());
+                    }
+                    if entities[entity].contains(&id) && check(self.db, id).await? {
+                        vec![id]
+                    } else {
+                        Vec::new()
+                    }
+                }
+                Step::Dedup => {
+                    if seen.entry(index).or_default().insert(id) {
+                        vec![id]
+                    } else {
+                        Vec::new()
+                    }
+                }
+                Step::Limit(limit) => {
+                    let passed = passed.entry(index).or_default();
+                    *passed += 1;
+                    if *passed >= *limit {
+                        blocked = blocked.max(Some(index));
+                    }
+                    if *passed <= *limit {
+                        vec![id]
+                    } else {
+                        Vec::new()
+                    }
+                }
+            };
+            stack.extend(next.into_iter().rev().map(|id| (index + 1, id)));
+        }
+
+        Ok(ids)
+    }
+    /// Nodes reached by the last step, in walk order. Nodes of other types
+    /// than `T` are skipped.
+    pub async fn exec<T: Node>(&self) -> Result<Vec<T>, DBError> {
+        let entity_ids: HashSet<NodeID> = self
+            .db
+            .get_node_ids(&T::entity_name())
+            .await?
+            .into_iter()
+            .collect();
+        let ids: Vec<NodeID> = self
+            .ids()
+            .await?
+            .into_iter()
+            .filter(|id| entity_ids.contains(id))
+            .collect();
+        self.db.get_nodes::<T>(&ids).await
+    }
+    pub async fn count(&self) -> Result<usize, DBError> {
+        Ok(self.ids().await?.len())
+    }
+}
+impl<D> fmt::Debug for Traversal<'_, D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Traversal")
+            .field("start", &self.start)
+            .field("steps", &self.steps)
+            .finish()
+    }
+}
+
+/// Pins down the higher-ranked signature of a filter closure.
+fn checker<D, F>(cb: F) -> Arc<CheckFn<D>>
+where
+    F: for<'d> Fn(&'d D, NodeID) -> BoxFuture<'d, Result<bool, DBError>> + Send + Sync + 'static,
+{
+    Arc::new(cb)
+}
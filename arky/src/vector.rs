@@ -0,0 +1,441 @@
+use crate::index::{decode_id, encode_id};
+use arkycore::types::{NodeID, VectorMetric};
+use serde::{Deserialize, Serialize};
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+/// Neighbors kept per node on the upper layers, twice as many on layer 0.
+const MAX_NEIGHBORS: usize = 16;
+/// Candidates considered when linking a new node.
+const EF_CONSTRUCTION: usize = 100;
+/// Candidates considered when searching, at least `k`.
+const EF_SEARCH: usize = 64;
+const MAX_LEVEL: usize = 16;
+
+const ENTRY_TAG: u8 = 0;
+const NODE_TAG: u8 = 1;
+
+/// HNSW graph of the vectors of an indexed field. Its keys are the graph
+/// prefix followed by `00` for the entry point, or by `01{id}` for each node.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct VectorGraph {
+    pub prefix: Vec<u8>,
+    pub metric: VectorMetric,
+}
+
+/// Vector a node has in a graph.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct VectorEntry {
+    pub graph: VectorGraph,
+    pub vector: Vec<f32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct GraphNode {
+    vector: Vec<f32>,
+    /// Neighbor ids of each layer the node is on, from layer 0 up.
+    neighbors: Vec<Vec<u64>>,
+}
+
+/// Graph key to put, or to delete when `None`.
+pub(crate) type VectorWrite = (Vec<u8>, Option<Vec<u8>>);
+/// Graph key with its stored value.
+pub(crate) type VectorItem = (Vec<u8>, Vec<u8>);
+
+/// Read access to the stored graphs.
+pub(crate) trait VectorSource {
+    fn read(&self, key: &[u8]) -> Result<Option<Vec<u8>>, String>;
+    fn scan(&self, prefix: &[u8]) -> Result<Vec<VectorItem>, String>;
+}
+
+/// Graph changes staged over a source, so they're written along with the
+/// rest of a node write once all of its vectors are linked.
+pub(crate) struct VectorWrites<'s, S: VectorSource> {
+    source: &'s S,
+    staged: HashMap<Vec<u8>, Option<Vec<u8>>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Scored(f32, u64);
+impl Eq for Scored {}
+impl Ord for Scored {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0).then(self.1.cmp(&other.1))
+    }
+}
+impl PartialOrd for Scored {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<'s, S: VectorSource> VectorWrites<'s, S> {
+    pub fn new(source: &'s S) -> Self {
+        Self {
+            source,
+            staged: HashMap::new(),
+        }
+    }
+
+    pub fn into_writes(self) -> Vec<VectorWrite> {
+        self.staged.into_iter().collect()
+    }
+
+    fn read(&self, key: &[u8]) -> Result<Option<Vec<u8>>, String> {
+        match self.staged.get(key) {
+            Some(value) => Ok(value.clone()),
+            None => self.source.read(key),
+        }
+    }
+
+    fn entry(&self, graph: &VectorGraph) -> Result<Option<u64>, String> {
+        let entry = self.read(&entry_key(graph))?;
+        Ok(entry.and_then(|bytes| decode_id(&bytes)).map(|id| id.0))
+    }
+
+    fn set_entry(&mut self, graph: &VectorGraph, entry: Option<u64>) {
+        let value = entry.map(|id| encode_id(NodeID(id)).to_vec());
+        self.staged.insert(entry_key(graph), value);
+    }
+
+    fn node(&self, graph: &VectorGraph, id: u64) -> Result<Option<GraphNode>, String> {
+        match self.read(&node_key(graph, id))? {
+            Some(bytes) => bincode::deserialize(&bytes)
+                .map(Some)
+                .map_err(|e| e.to_string()),
+            None => Ok(None),
+        }
+    }
+
+    fn put_node(&mut self, graph: &VectorGraph, id: u64, node: &GraphNode) -> Result<(), String> {
+        let bytes = bincode::serialize(node).map_err(|e| e.to_string())?;
+        self.staged.insert(node_key(graph, id), Some(bytes));
+        Ok(())
+    }
+
+    fn distance(&self, graph: &VectorGraph, query: &[f32], id: u64) -> Result<f32, String> {
+        let node = self.node(graph, id)?;
+        Ok(node.map_or(f32::INFINITY, |node| {
+            graph.metric.distance(query, &node.vector)
+        }))
+    }
+
+    /// Closest nodes of `layer` to `query` found from `entries`, at most `ef`
+    /// of them, closest first.
+    fn search_layer(
+        &self,
+        graph: &VectorGraph,
+        query: &[f32],
+        entries: &[Scored],
+        ef: usize,
+        layer: usize,
+    ) -> Result<Vec<Scored>, String> {
+        let mut visited: HashSet<u64> = entries.iter().map(|entry| entry.1).collect();
+        let mut candidates: BinaryHeap<Reverse<Scored>> =
+            entries.iter().copied().map(Reverse).collect();
+        let mut found: BinaryHeap<Scored> = entries.iter().copied().collect();
+
+        while let Some(Reverse(candidate)) = candidates.pop() {
+            let furthest = found.peek().map_or(f32::INFINITY, |found| found.0);
+            if candidate.0 > furthest && found.len() >= ef {
+                break;
+            }
+            let Some(node) = self.node(graph, candidate.1)? else {
+                continue;
+            };
+            for &neighbor in node.neighbors.get(layer).into_iter().flatten() {
+                if !visited.insert(neighbor) {
+                    continue;
+                }
+                let distance = self.distance(graph, query, neighbor)?;
+                let furthest = found.peek().map_or(f32::INFINITY, |found| found.0);
+                if found.len() < ef || distance < furthest {
+                    candidates.push(Reverse(Scored(distance, neighbor)));
+                    found.push(Scored(distance, neighbor));
+                    if found.len() > ef {
+                        found.pop();
+                    }
+                }
+            }
+        }
+
+        Ok(found.into_sorted_vec())
+    }
+
+    /// Closest node to `query` found greedily on the layers above `layer`.
+    fn descend(
+        &self,
+        graph: &VectorGraph,
+        query: &[f32],
+        entry: u64,
+        layer: usize,
+    ) -> Result<Vec<Scored>, String> {
+        let mut closest = vec![Scored(self.distance(graph, query, entry)?, entry)];
+        for upper in (layer + 1..=self.level(graph, entry)?).rev() {
+            closest = self.search_layer(graph, query, &closest, 1, upper)?;
+        }
+        Ok(closest)
+    }
+
+    fn level(&self, graph: &VectorGraph, id: u64) -> Result<usize, String> {
+        let node = self.node(graph, id)?;
+        Ok(node.map_or(0, |node| node.neighbors.len().saturating_sub(1)))
+    }
+
+    fn check_dimension(
+        &self,
+        graph: &VectorGraph,
+        entry: u64,
+        vector: &[f32],
+    ) -> Result<(), String> {
+        let expected = self.node(graph, entry)?.map(|node| node.vector.len());
+        match expected {
+            Some(expected) if expected != vector.len() => Err(format!(
+                "Vector has {} dimensions, the index has {}",
+                vector.len(),
+                expected
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Moves the node from the graphs of its `stored` vectors to the ones of
+    /// its `current` vectors, leaving the unchanged ones alone.
+    pub fn update(
+        &mut self,
+        id: NodeID,
+        stored: &[VectorEntry],
+        current: &[VectorEntry],
+    ) -> Result<(), String> {
+        for entry in stored.iter().filter(|entry| !current.contains(entry)) {
+            self.remove(&entry.graph, id)?;
+        }
+        for entry in current.iter().filter(|entry| !stored.contains(entry)) {
+            self.insert(&entry.graph, id, &entry.vector)?;
+        }
+        Ok(())
+    }
+
+    pub fn insert(
+        &mut self,
+        graph: &VectorGraph,
+        id: NodeID,
+        vector: &[f32],
+    ) -> Result<(), String> {
+        if vector.is_empty() {
+            return Err("Vector is empty".to_string());
+        }
+        self.remove(graph, id)?;
+        let id = id.0;
+        let level = random_level(id);
+        let mut node = GraphNode {
+            vector: vector.to_vec(),
+            neighbors: vec![Vec::new(); level + 1],
+        };
+
+        let Some(entry) = self.entry(graph)? else {
+            self.put_node(graph, id, &node)?;
+            self.set_entry(graph, Some(id));
+            return Ok(());
+        };
+        self.check_dimension(graph, entry, vector)?;
+        let entry_level = self.level(graph, entry)?;
+
+        let mut closest = self.descend(graph, vector, entry, level)?;
+        let mut links = Vec::new();
+        for layer in (0..=level.min(entry_level)).rev() {
+            closest = self.search_layer(graph, vector, &closest, EF_CONSTRUCTION, layer)?;
+            let candidates = closest.iter().map(|scored| scored.1).collect();
+            let neighbors =
+                self.select_neighbors(graph, vector, candidates, max_neighbors(layer))?;
+            node.neighbors[layer] = neighbors.clone();
+            links.push((layer, neighbors));
+        }
+        self.put_node(graph, id, &node)?;
+
+        for (layer, neighbors) in links {
+            for neighbor in neighbors {
+                let Some(mut other) = self.node(graph, neighbor)? else {
+                    continue;
+                };
+                let Some(others) = other.neighbors.get_mut(layer) else {
+                    continue;
+                };
+                others.push(id);
+                let candidates = std::mem::take(others);
+                other.neighbors[layer] =
+                    self.select_neighbors(graph, &other.vector, candidates, max_neighbors(layer))?;
+                self.put_node(graph, neighbor, &other)?;
+            }
+        }
+        if level > entry_level {
+            self.set_entry(graph, Some(id));
+        }
+        Ok(())
+    }
+
+    /// Unlinks the node from the graph, reconnecting each of its neighbors to
+    /// the closest nodes among their other neighbors and its own. The node
+    /// with the most layers becomes the entry point if it was the node.
+    pub fn remove(&mut self, graph: &VectorGraph, id: NodeID) -> Result<(), String> {
+        let id = id.0;
+        let Some(node) = self.node(graph, id)? else {
+            return Ok(());
+        };
+
+        for (layer, neighbors) in node.neighbors.iter().enumerate() {
+            for &neighbor in neighbors {
+                let Some(mut other) = self.node(graph, neighbor)? else {
+                    continue;
+                };
+                let Some(others) = other.neighbors.get(layer) else {
+                    continue;
+                };
+                if !others.contains(&id) {
+                    continue;
+                }
+                let candidates: Vec<u64> = others
+                    .iter()
+                    .chain(neighbors)
+                    .copied()
+                    .filter(|candidate| *candidate != id && *candidate != neighbor)
+                    .collect::<HashSet<_>>()
+                    .into_iter()
+                    .collect();
+                other.neighbors[layer] =
+                    self.select_neighbors(graph, &other.vector, candidates, max_neighbors(layer))?;
+                self.put_node(graph, neighbor, &other)?;
+            }
+        }
+        self.staged.insert(node_key(graph, id), None);
+
+        if self.entry(graph)? == Some(id) {
+            let entry = self
+                .nodes(graph)?
+                .into_iter()
+                .max_by_key(|(id, node)| (node.neighbors.len(), Reverse(*id)))
+                .map(|(id, _)| id);
+            self.set_entry(graph, entry);
+        }
+        Ok(())
+    }
+
+    /// The `k` closest nodes to `query`, closest first.
+    pub fn nearest(
+        &self,
+        graph: &VectorGraph,
+        query: &[f32],
+        k: usize,
+    ) -> Result<Vec<(NodeID, f32)>, String> {
+        let Some(entry) = self.entry(graph)? else {
+            return Ok(Vec::new());
+        };
+        self.check_dimension(graph, entry, query)?;
+        let closest = self.descend(graph, query, entry, 0)?;
+        let found = self.search_layer(graph, query, &closest, EF_SEARCH.max(k), 0)?;
+        Ok(found
+            .into_iter()
+            .take(k)
+            .map(|scored| (NodeID(scored.1), scored.0))
+            .collect())
+    }
+
+    /// Up to `count` neighbors for a node at `vector`, closest first. A
+    /// candidate is skipped when it's closer to an already selected neighbor
+    /// than to the node, which keeps links towards every direction so
+    /// outlying nodes stay reachable.
+    fn select_neighbors(
+        &self,
+        graph: &VectorGraph,
+        vector: &[f32],
+        candidates: Vec<u64>,
+        count: usize,
+    ) -> Result<Vec<u64>, String> {
+        let mut scored = Vec::with_capacity(candidates.len());
+        for candidate in candidates {
+            if let Some(node) = self.node(graph, candidate)? {
+                let distance = graph.metric.distance(vector, &node.vector);
+                scored.push((Scored(distance, candidate), node.vector));
+            }
+        }
+        scored.sort_by_key(|(scored, _)| *scored);
+        scored.dedup_by_key(|(scored, _)| scored.1);
+
+        let mut selected: Vec<(Scored, Vec<f32>)> = Vec::with_capacity(count);
+        for (candidate, candidate_vector) in scored {
+            if selected.len() >= count {
+                break;
+            }
+            let diverse = selected.iter().all(|(_, selected_vector)| {
+                graph.metric.distance(&candidate_vector, selected_vector) > candidate.0
+            });
+            if diverse {
+                selected.push((candidate, candidate_vector));
+            }
+        }
+        Ok(selected.into_iter().map(|(scored, _)| scored.1).collect())
+    }
+
+    /// Every node of the graph, staged changes included.
+    fn nodes(&self, graph: &VectorGraph) -> Result<Vec<(u64, GraphNode)>, String> {
+        let mut prefix = graph.prefix.clone();
+        prefix.push(NODE_TAG);
+        let mut keys: HashSet<Vec<u8>> = self
+            .source
+            .scan(&prefix)?
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        keys.extend(
+            self.staged
+                .keys()
+                .filter(|key| key.starts_with(&prefix))
+                .cloned(),
+        );
+
+        let mut nodes = Vec::new();
+        for key in keys {
+            let Some(id) = decode_id(&key[prefix.len()..]) else {
+                continue;
+            };
+            if let Some(node) = self.node(graph, id.0)? {
+                nodes.push((id.0, node));
+            }
+        }
+        nodes.sort_by_key(|(id, _)| *id);
+        Ok(nodes)
+    }
+}
+
+fn entry_key(graph: &VectorGraph) -> Vec<u8> {
+    let mut key = graph.prefix.clone();
+    key.push(ENTRY_TAG);
+    key
+}
+
+fn node_key(graph: &VectorGraph, id: u64) -> Vec<u8> {
+    let mut key = graph.prefix.clone();
+    key.push(NODE_TAG);
+    key.extend_from_slice(&encode_id(NodeID(id)));
+    key
+}
+
+fn max_neighbors(layer: usize) -> usize {
+    if layer == 0 {
+        MAX_NEIGHBORS * 2
+    } else {
+        MAX_NEIGHBORS
+    }
+}
+
+/// Layer of a node, drawn from its id so it doesn't change when the node is
+/// linked again. Ids are sequential, so they're mixed first.
+fn random_level(id: u64) -> usize {
+    let mut x = id.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^= x >> 31;
+    let uniform = ((x >> 11) as f64 + 1.0) / (1u64 << 53) as f64;
+    let level = -uniform.ln() / (MAX_NEIGHBORS as f64).ln();
+    (level as usize).min(MAX_LEVEL)
+}
@@ -0,0 +1,35 @@
+//! Fixtures shared by the storage tests.
+#![allow(dead_code)]
+
+use arky::node::prelude::*;
+
+#[schema(Node)]
+pub struct User {
+    pub id: NodeID,
+    #[index(fulltext)]
+    pub name: String,
+    #[index(range)]
+    pub age: u32,
+}
+
+#[schema(Node)]
+pub struct Account {
+    pub id: NodeID,
+    #[index(unique)]
+    pub email: String,
+}
+
+pub fn create_user(name: &str, age: u32) -> User {
+    User::new(User {
+        id: NodeID::new(),
+        name: name.to_string(),
+        age,
+    })
+}
+
+pub fn create_account(email: &str) -> Account {
+    Account::new(Account {
+        id: NodeID::new(),
+        email: email.to_string(),
+    })
+}
@@ -0,0 +1,426 @@
+use arky::cypher::parse;
+use arky::db::DBError;
+use arky::edge::prelude::*;
+use arky::inst::prelude::*;
+use arky::node::{prelude::*, Value};
+use arky::query::QueryOperation;
+use std::ops::Bound;
+use tempdir::TempDir;
+
+#[schema(Node)]
+struct User {
+    pub id: NodeID,
+    pub name: String,
+    #[index(range)]
+    pub age: u32,
+}
+
+#[schema(Node)]
+struct Car {
+    pub id: NodeID,
+    pub name: String,
+    pub model: String,
+}
+
+fn create_storage() -> RocksDB {
+    let dir = TempDir::new("arky").unwrap();
+    let db_path = dir.path().join("test_db").to_str().unwrap().to_string();
+    RocksDB::new(RocksDBConfig {
+        path: db_path,
+        set_error_if_exists: false,
+        ..Default::default()
+    })
+}
+
+fn column(rows: &[Value], name: &str) -> Vec<Value> {
+    rows.iter()
+        .map(|row| row.get(name).cloned().unwrap_or(Value::Null))
+        .collect()
+}
+
+fn sorted(mut values: Vec<Value>) -> Vec<Value> {
+    values.sort();
+    values
+}
+
+/// alice (34) owns the red Mustang, bob (17) the blue Civic, and carol (25)
+/// the green Mustang and the blue Civic.
+async fn create_garage<D: DB>(db: &D) -> (Vec<User>, Vec<Car>) {
+    let users: Vec<_> = [("alice", 34), ("bob", 17), ("carol", 25)]
+        .into_iter()
+        .map(|(name, age)| {
+            User::new(User {
+                id: NodeID::new(),
+                name: name.to_string(),
+                age,
+            })
+        })
+        .collect();
+    let cars: Vec<_> = [("red", "Mustang"), ("blue", "Civic"), ("green", "Mustang")]
+        .into_iter()
+        .map(|(name, model)| {
+            Car::new(Car {
+                id: NodeID::new(),
+                name: name.to_string(),
+                model: model.to_string(),
+            })
+        })
+        .collect();
+    db.insert_nodes(&users).await.unwrap();
+    db.insert_nodes(&cars).await.unwrap();
+    let mut owns = Edge::new("user_owns");
+    for (user, car) in [(0, 0), (1, 1), (2, 2), (2, 1)] {
+        owns.link(&users[user], &cars[car], Data::None);
+        db.insert_edge(owns.item.as_ref().unwrap()).await.unwrap();
+    }
+
+    (users, cars)
+}
+
+#[tokio::test]
+async fn cypher_match_patterns() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+
+    create_garage(db).await;
+
+    let mut cypher = db.cypher();
+    cypher.register::<User>().register::<Car>();
+
+    let text = "MATCH (u:User)-[:user_owns]->(c:Car) WHERE u.age > 18 RETURN c.name";
+    let rows = cypher.run(text).await.unwrap();
+    assert_eq!(
+        sorted(column(&rows, "c.name")),
+        vec!["blue".into(), "green".into(), "red".into()]
+    );
+    assert_eq!(
+        cypher.operations(&parse(text).unwrap()),
+        vec![QueryOperation::ByIndexRange(
+            "age".to_string(),
+            Bound::Excluded(Value::Int(18)),
+            Bound::Unbounded
+        )]
+    );
+
+    let rows = cypher
+        .run(
+            "match (c:Car {model: 'Mustang'})<-[:user_owns]-(u) \
+             return u.name as owner, c.name order by u.age desc",
+        )
+        .await
+        .unwrap();
+    assert_eq!(column(&rows, "owner"), vec!["alice".into(), "carol".into()]);
+    assert_eq!(column(&rows, "c.name"), vec!["red".into(), "green".into()]);
+
+    let rows = cypher
+        .run(
+            "MATCH (a:User)-->(c)<--(b:User) \
+             WHERE NOT a.name = b.name AND (c.model = 'Civic' OR a.age <= 20) \
+             RETURN a.name, b.name ORDER BY a.name SKIP 1 LIMIT 5",
+        )
+        .await
+        .unwrap();
+    assert_eq!(column(&rows, "a.name"), vec!["carol".into()]);
+    assert_eq!(column(&rows, "b.name"), vec!["bob".into()]);
+
+    let rows = cypher
+        .run("MATCH (u:User {name: \"bob\"}) RETURN u")
+        .await
+        .unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].get("u").unwrap().get("age"), Some(&Value::UInt(17)));
+    let rows = cypher
+        .run("MATCH (u:User) WHERE u.name > 20 OR u.age < -1 RETURN u.name")
+        .await
+        .unwrap();
+    assert!(rows.is_empty());
+
+    for text in [
+        "MATCH (u:User RETURN u",
+        "MATCH (u:User)<-[:user_owns]->(c) RETURN c",
+        "MATCH (u:User) RETURN u LIMIT -1",
+        "MATCH (u:User) WHERE u.name = 'bob RETURN u",
+    ] {
+        assert!(matches!(
+            cypher.run(text).await,
+            Err(DBError::QueryError { .. })
+        ));
+    }
+    assert!(cypher.run("MATCH (u) RETURN u").await.is_err());
+    assert!(cypher.run("MATCH (p:Plane) RETURN p").await.is_err());
+    assert!(cypher.run("MATCH (u:User) RETURN x.name").await.is_err());
+}
+
+#[test]
+fn cypher_parse_errors() {
+    let error = |text: &str| match parse(text) {
+        Err(DBError::QueryError { error }) => error,
+        other => panic!("{} parsed as {:?}", text, other),
+    };
+
+    assert_eq!(
+        error("MATCH (u:User) WHERE u.age ! 3 RETURN u"),
+        "syntax error at 27: unexpected `!`"
+    );
+    assert_eq!(
+        error("MATCH (u:User) RETURN u; DELETE u"),
+        "syntax error at 23: unexpected `;`"
+    );
+    assert_eq!(
+        error("MATCH (u:User {name: 'bob}) RETURN u"),
+        "syntax error at 21: unterminated string"
+    );
+    assert_eq!(
+        error("MATCH (u:User) WHERE u.name = \"bob\\\" RETURN u"),
+        "syntax error at 30: unterminated string"
+    );
+    assert_eq!(
+        error("CREATE (u:User) RETURN u"),
+        "syntax error at 0: expected MATCH"
+    );
+    assert_eq!(
+        error("MATCH (u:User) DELETE u"),
+        "syntax error at 15: expected RETURN"
+    );
+    assert_eq!(
+        error("MATCH (u:User) RETURN u UNION MATCH (c:Car) RETURN c"),
+        "syntax error at 24: unexpected token"
+    );
+    assert_eq!(
+        error("MATCH (u:User) RETURN u ORDER u.name"),
+        "syntax error at 30: expected BY"
+    );
+    assert_eq!(
+        error("MATCH (u:User) WHERE u.age RETURN u"),
+        "syntax error at 27: expected a comparison"
+    );
+    assert_eq!(
+        error("MATCH (u:User) RETURN u LIMIT 1.5"),
+        "syntax error at 30: expected a positive integer"
+    );
+    assert_eq!(error("MATCH (u:User"), "syntax error at 13: expected `)`");
+}
+
+#[tokio::test]
+async fn cypher_where_operators() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+    create_garage(db).await;
+    let mut cypher = db.cypher();
+    cypher.register::<User>().register::<Car>();
+
+    let names = |filter: &'static str| {
+        let cypher = &cypher;
+        async move {
+            let text = format!("MATCH (u:User) WHERE {} RETURN u.name", filter);
+            let rows = cypher.run(&text).await.unwrap();
+            sorted(column(&rows, "u.name"))
+        }
+    };
+    let expected =
+        |names: &[&str]| -> Vec<Value> { names.iter().map(|&name| name.into()).collect() };
+
+    assert_eq!(names("u.age = 25").await, expected(&["carol"]));
+    assert_eq!(names("u.age <> 25").await, expected(&["alice", "bob"]));
+    assert_eq!(names("u.age < 25").await, expected(&["bob"]));
+    assert_eq!(names("u.age <= 25").await, expected(&["bob", "carol"]));
+    assert_eq!(names("u.age > 25").await, expected(&["alice"]));
+    assert_eq!(names("u.age >= 25").await, expected(&["alice", "carol"]));
+    assert_eq!(names("25 > u.age").await, expected(&["bob"]));
+    assert_eq!(names("u.name = 'bob'").await, expected(&["bob"]));
+    assert_eq!(
+        names("u.name <> 'bob'").await,
+        expected(&["alice", "carol"])
+    );
+    assert_eq!(names("u.name >= 'bob'").await, expected(&["bob", "carol"]));
+    assert_eq!(
+        names("u.age > 18 AND u.age < 30").await,
+        expected(&["carol"])
+    );
+    assert_eq!(
+        names("u.age < 18 OR u.name = 'alice'").await,
+        expected(&["alice", "bob"])
+    );
+    assert_eq!(names("NOT u.age > 18").await, expected(&["bob"]));
+    assert_eq!(
+        names("NOT (u.age > 30 OR u.age < 18)").await,
+        expected(&["carol"])
+    );
+    assert!(names("u.age = null").await.is_empty());
+    assert!(names("u.missing <> 1").await.is_empty());
+    assert!(names("u.name < 30").await.is_empty());
+
+    let operations = |filter: &str| {
+        let text = format!("MATCH (u:User) WHERE {} RETURN u", filter);
+        cypher.operations(&parse(&text).unwrap())
+    };
+    let range = |low, high| vec![QueryOperation::ByIndexRange("age".to_string(), low, high)];
+    let (included, excluded) = (
+        Bound::Included(Value::Int(25)),
+        Bound::Excluded(Value::Int(25)),
+    );
+    assert_eq!(
+        operations("u.age = 25"),
+        vec![QueryOperation::ByIndex("age".to_string(), Value::Int(25))]
+    );
+    assert!(operations("u.age <> 25").is_empty());
+    assert_eq!(
+        operations("u.age < 25"),
+        range(Bound::Unbounded, excluded.clone())
+    );
+    assert_eq!(
+        operations("u.age <= 25"),
+        range(Bound::Unbounded, included.clone())
+    );
+    assert_eq!(
+        operations("u.age > 25"),
+        range(excluded.clone(), Bound::Unbounded)
+    );
+    assert_eq!(operations("u.age >= 25"), range(included, Bound::Unbounded));
+    assert_eq!(operations("25 < u.age"), range(excluded, Bound::Unbounded));
+    assert!(operations("u.age > 18 OR u.age < 30").is_empty());
+    assert!(operations("NOT u.age > 18").is_empty());
+}
+
+#[tokio::test]
+async fn cypher_return_projection() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+    let (users, _) = create_garage(db).await;
+    let mut cypher = db.cypher();
+    cypher.register::<User>().register::<Car>();
+
+    let rows = cypher
+        .run("MATCH (u:User {name: 'carol'}) RETURN u.name, u.age AS years, u, u.missing")
+        .await
+        .unwrap();
+    assert_eq!(rows.len(), 1);
+    let Value::Map(columns) = &rows[0] else {
+        panic!("{:?} is not a map", rows[0]);
+    };
+    let names: Vec<&str> = columns.keys().map(String::as_str).collect();
+    assert_eq!(names, ["u", "u.missing", "u.name", "years"]);
+    assert_eq!(columns["u.name"], "carol".into());
+    assert_eq!(columns["years"], Value::UInt(25));
+    assert_eq!(columns["u.missing"], Value::Null);
+    assert_eq!(columns["u"], users[2].to_value().unwrap());
+
+    let rows = cypher
+        .run("MATCH (u:User)-[:user_owns]->(c) RETURN u.name AS owner, c.model AS model ORDER BY u.age, c.name")
+        .await
+        .unwrap();
+    assert_eq!(
+        column(&rows, "owner"),
+        vec!["bob".into(), "carol".into(), "carol".into(), "alice".into()]
+    );
+    assert_eq!(
+        column(&rows, "model"),
+        vec![
+            "Civic".into(),
+            "Civic".into(),
+            "Mustang".into(),
+            "Mustang".into()
+        ]
+    );
+    assert!(rows.iter().all(|row| row.get("u.name").is_none()));
+
+    let rows = cypher
+        .run("MATCH (u:User) RETURN u.name AS name ORDER BY u.age DESC LIMIT 2")
+        .await
+        .unwrap();
+    assert_eq!(column(&rows, "name"), vec!["alice".into(), "carol".into()]);
+    assert!(matches!(
+        cypher.run("MATCH (u:User) RETURN u.name AS").await,
+        Err(DBError::QueryError { .. })
+    ));
+}
+
+#[tokio::test]
+async fn cypher_match_edge_patterns() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+    let (users, cars) = create_garage(db).await;
+    let mut drives = Edge::new("drives");
+    drives.link(&users[1], &cars[0], Data::None);
+    db.insert_edge(drives.item.as_ref().unwrap()).await.unwrap();
+    let mut knows = Edge::new("knows");
+    knows.link(&users[0], &users[1], Data::None);
+    db.insert_edge(knows.item.as_ref().unwrap()).await.unwrap();
+    let mut cypher = db.cypher();
+    cypher.register::<User>().register::<Car>();
+
+    let pairs = |text: &'static str| {
+        let cypher = &cypher;
+        async move {
+            let rows = cypher.run(text).await.unwrap();
+            let pairs = rows.iter().map(|row| match (row.get("a"), row.get("b")) {
+                (Some(Value::String(a)), Some(Value::String(b))) => format!("{}-{}", a, b),
+                _ => panic!("{:?} misses a column", row),
+            });
+            let mut pairs: Vec<String> = pairs.collect();
+            pairs.sort();
+            pairs
+        }
+    };
+
+    assert_eq!(
+        pairs("MATCH (u:User)-[:user_owns]->(c:Car) RETURN u.name AS a, c.name AS b").await,
+        ["alice-red", "bob-blue", "carol-blue", "carol-green"]
+    );
+    assert_eq!(
+        pairs("MATCH (u:User)-[:drives]->(c:Car) RETURN u.name AS a, c.name AS b").await,
+        ["bob-red"]
+    );
+    assert_eq!(
+        pairs("MATCH (c:Car)<-[:drives]-(u) RETURN c.name AS a, u.name AS b").await,
+        ["red-bob"]
+    );
+    assert_eq!(
+        pairs("MATCH (u:User)-->(c:Car) RETURN u.name AS a, c.name AS b").await,
+        [
+            "alice-red",
+            "bob-blue",
+            "bob-red",
+            "carol-blue",
+            "carol-green"
+        ]
+    );
+    assert_eq!(
+        pairs("MATCH (u:User)-[:knows]->(v:User) RETURN u.name AS a, v.name AS b").await,
+        ["alice-bob"]
+    );
+    assert_eq!(
+        pairs("MATCH (u:User)-[:knows]-(v) RETURN u.name AS a, v.name AS b").await,
+        ["alice-bob", "bob-alice"]
+    );
+    assert_eq!(
+        pairs("MATCH (u:User)-[]->(c:Car {model: 'Civic'}) RETURN u.name AS a, c.name AS b").await,
+        ["bob-blue", "carol-blue"]
+    );
+    assert!(
+        pairs("MATCH (u:User)-[:knows]->(c:Car) RETURN u.name AS a, c.name AS b")
+            .await
+            .is_empty()
+    );
+    assert!(
+        pairs("MATCH (u:User)-[:user_own]->(c) RETURN u.name AS a, c.name AS b")
+            .await
+            .is_empty()
+    );
+    assert_eq!(
+        pairs(
+            "MATCH (u:User)-[:user_owns]->(c:Car)<-[:user_owns]-(u) \
+             RETURN u.name AS a, c.name AS b"
+        )
+        .await,
+        ["alice-red", "bob-blue", "carol-blue", "carol-green"]
+    );
+    assert_eq!(
+        pairs(
+            "MATCH (u:User)-[:user_owns]->(c)<-[:drives]-(d:User) \
+             RETURN u.name AS a, d.name AS b"
+        )
+        .await,
+        ["alice-bob"]
+    );
+}
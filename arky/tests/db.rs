@@ -1,19 +1,23 @@
-use arky::edge::prelude::*;
+use arky::db::Transaction;
+use arky::edge::{prelude::*, EdgeItem};
 use arky::entity::EntityItem;
 use arky::inst::prelude::*;
-use arky::node::prelude::*;
+use arky::node::{prelude::*, Value};
+use common::{create_account, Account, User};
+use futures::executor::block_on;
+use futures::TryStreamExt;
 use tempdir::TempDir;
 
+mod common;
+
 #[schema(EdgeData)]
 struct Owns {
     pub since: u32,
 }
 
-#[schema(Node)]
-struct User {
-    pub id: NodeID,
-    pub name: String,
-    pub age: u32,
+#[schema(EdgeData)]
+struct Mileage {
+    pub km: u32,
 }
 
 #[schema(Node)]
@@ -77,3 +81,564 @@ async fn insert_entity_on_db() {
     let db_entity = db.get_entity("User").await.unwrap();
     assert_eq!(db_entity.name, "User");
 }
+
+#[tokio::test]
+async fn edge_without_data_round_trips() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+
+    let user = User::new(User {
+        id: NodeID::new(),
+        name: "John".to_string(),
+        age: 20,
+    });
+    let friend = User::new(User {
+        id: NodeID::new(),
+        name: "Jane".to_string(),
+        age: 20,
+    });
+    let mut knows = Edge::new("knows");
+    knows.link(&user, &friend, Data::None);
+    let item = knows.item.as_ref().unwrap();
+    db.insert_edge(item).await.unwrap();
+
+    let found = db.get_edge(user.id, friend.id).await.unwrap();
+    assert_eq!(found.data, Data::None);
+    assert_eq!(&found, item);
+}
+
+#[tokio::test]
+async fn edge_data_round_trip() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+
+    let mut car_users_edge = Edge::new("car_owned_by");
+    let user = User::new(User {
+        id: NodeID::new(),
+        name: "John".to_string(),
+        age: 20,
+    });
+    let car = Car::new(Car {
+        id: NodeID::new(),
+        name: "Ford".to_string(),
+        model: "Mustang".to_string(),
+        owner: EdgeRef::new(&car_users_edge),
+    });
+
+    car_users_edge.link(&car, &user, Owns::new(Owns { since: 2019 }));
+    let edge = car_users_edge.item.as_ref().unwrap();
+    db.insert_edge(edge).await.unwrap();
+
+    let found_edge = db.get_edge(car.id, user.id).await.unwrap();
+    assert_eq!(&found_edge, edge);
+    assert_eq!(Owns::get(&found_edge.data).unwrap().since, 2019);
+}
+
+#[tokio::test]
+async fn edge_data_decodes_after_register() {
+    #[derive(serde::Serialize)]
+    enum StoredData {
+        #[allow(dead_code)]
+        None,
+        Some(String, Vec<u8>),
+    }
+    #[derive(serde::Serialize)]
+    struct StoredEdge {
+        label: String,
+        from: NodeID,
+        to: NodeID,
+        data: StoredData,
+    }
+
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+
+    // Written by a process that registered `Mileage`, which this one hasn't.
+    let (from, to) = (NodeID::new(), NodeID::new());
+    let bytes = bincode::serialize(&StoredEdge {
+        label: "drove".to_string(),
+        from,
+        to,
+        data: StoredData::Some(
+            concat!(module_path!(), "::Mileage").to_string(),
+            bincode::serialize(&1200u32).unwrap(),
+        ),
+    })
+    .unwrap();
+    db.insert_edge(&EdgeItem::from_bytes(&bytes).unwrap())
+        .await
+        .unwrap();
+
+    let mileage = Mileage { km: 1200 };
+    let found = db.get_edge(from, to).await.unwrap();
+    assert_ne!(found.data, Data::new(mileage.clone()));
+    assert!(found.data.get_owned::<Mileage>().is_err());
+
+    Mileage::register();
+    assert_eq!(found.data.get_owned::<Mileage>().unwrap(), mileage);
+    let found = db.get_edge(from, to).await.unwrap();
+    assert_eq!(found.data, Data::new(mileage));
+}
+
+mod trips {
+    use arky::node::prelude::*;
+
+    #[schema(EdgeData)]
+    pub struct Mileage {
+        pub miles: String,
+    }
+}
+
+#[tokio::test]
+async fn edge_data_of_same_name_decodes_by_module() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+
+    trips::Mileage::register();
+    let (from, to) = (NodeID::new(), NodeID::new());
+    let edge = EdgeItem {
+        label: "drove".to_string(),
+        from,
+        to,
+        data: Mileage::new(Mileage { km: 1200 }),
+    };
+    let trip = EdgeItem {
+        label: "drove".to_string(),
+        from: to,
+        to: from,
+        data: Data::new(trips::Mileage {
+            miles: "750".to_string(),
+        }),
+    };
+    db.insert_edges(&[edge, trip]).await.unwrap();
+
+    let found = db.get_edge(from, to).await.unwrap();
+    assert_eq!(Mileage::get(&found.data).unwrap().km, 1200);
+    let found = db.get_edge(to, from).await.unwrap();
+    let trip: trips::Mileage = found.data.get_owned().unwrap();
+    assert_eq!(trip.miles, "750");
+}
+
+#[test]
+#[should_panic(expected = "already registered")]
+fn edge_data_name_taken_by_another_type() {
+    Owns::register();
+    arkycore::data::register::<trips::Mileage>(concat!(module_path!(), "::Owns"));
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Unregistered(u32);
+
+#[tokio::test]
+async fn batch_insert_and_remove() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+
+    let users = ["John", "Jane"].map(|name| {
+        User::new(User {
+            id: NodeID::new(),
+            name: name.to_string(),
+            age: 20,
+        })
+    });
+    db.insert_nodes(&users).await.unwrap();
+    for user in &users {
+        assert_eq!(&db.get_node::<User>(user.id).await.unwrap(), user);
+    }
+    assert!(db.get_entity(&users[0].entity()).await.is_ok());
+
+    let mut edge = Edge::new("friend_of");
+    edge.link(&users[0], &users[1], Data::None);
+    let edges = [edge.item.unwrap()];
+    db.insert_edges(&edges).await.unwrap();
+    assert_eq!(
+        db.get_edge(users[0].id, users[1].id).await.unwrap(),
+        edges[0]
+    );
+
+    let entities = [EntityItem::new("Car".to_string())];
+    db.insert_entities(&entities).await.unwrap();
+    assert!(db.get_entity("Car").await.is_ok());
+
+    db.remove_edges(&edges).await.unwrap();
+    db.remove_nodes(&users).await.unwrap();
+    db.remove_entities(&entities).await.unwrap();
+    assert!(db.get_edge(users[0].id, users[1].id).await.is_err());
+    assert!(db.get_node::<User>(users[0].id).await.is_err());
+    assert!(db
+        .get_node_ids(&users[0].entity())
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(db.get_entity("Car").await.is_err());
+}
+
+#[tokio::test]
+async fn batch_reports_failed_items() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+
+    let users: Vec<User> = (0..3)
+        .map(|age| {
+            User::new(User {
+                id: NodeID::new(),
+                name: "John".to_string(),
+                age,
+            })
+        })
+        .collect();
+    let edges: Vec<EdgeItem> = [Data::None, Data::new(Unregistered(1)), Data::None]
+        .into_iter()
+        .enumerate()
+        .map(|(i, data)| {
+            let mut edge = Edge::new("knows");
+            edge.link(&users[i], &users[(i + 1) % 3], data);
+            edge.item.unwrap()
+        })
+        .collect();
+
+    match db.insert_edges(&edges).await {
+        Err(DBError::BatchError { items }) => {
+            assert_eq!(items.len(), 1);
+            assert_eq!(items[0].index, 1);
+            assert_eq!(items[0].key, edges[1].key());
+        }
+        res => panic!("expected a batch error, got {:?}", res),
+    }
+    match db.insert_edge(&edges[1]).await {
+        Err(DBError::InsertEdgeError { key, .. }) => assert_eq!(key, edges[1].key()),
+        res => panic!("expected an insert edge error, got {:?}", res),
+    }
+    assert!(db.scan_edges().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn transaction_commits_all_writes() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+
+    let user = User::new(User {
+        id: NodeID::new(),
+        name: "John".to_string(),
+        age: 20,
+    });
+    let mut edge = Edge::new("car_owned_by");
+    let car = Car::new(Car {
+        id: NodeID::new(),
+        name: "Ford".to_string(),
+        model: "Mustang".to_string(),
+        owner: EdgeRef::new(&edge),
+    });
+    edge.link(&car, &user, Data::None);
+    let edge = edge.item.unwrap();
+
+    let (tx_user, tx_car, tx_edge) = (user.clone(), car.clone(), edge.clone());
+    let found = db
+        .transaction(|tx| async move {
+            tx.insert_node(&tx_user).await?;
+            tx.insert_node(&tx_car).await?;
+            tx.insert_edge(&tx_edge).await?;
+            tx.get_node::<User>(tx_user.id).await
+        })
+        .await
+        .unwrap();
+    assert_eq!(found, user);
+
+    assert_eq!(db.get_node::<User>(user.id).await.unwrap(), user);
+    assert_eq!(db.get_node::<Car>(car.id).await.unwrap(), car);
+    assert_eq!(db.get_edge(car.id, user.id).await.unwrap(), edge);
+    assert!(db.get_entity(&car.entity()).await.is_ok());
+}
+
+#[tokio::test]
+async fn transaction_rolls_back_on_error() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+
+    let user = User::new(User {
+        id: NodeID::new(),
+        name: "John".to_string(),
+        age: 20,
+    });
+
+    let tx_user = user.clone();
+    let res = db
+        .transaction(|tx| async move {
+            tx.insert_node(&tx_user).await?;
+            tx.get_edge(tx_user.id, NodeID::new()).await
+        })
+        .await;
+    assert!(matches!(res, Err(DBError::GetEdgeError { .. })));
+    assert!(db.get_node::<User>(user.id).await.is_err());
+    assert!(db.get_entity(&user.entity()).await.is_err());
+}
+
+#[tokio::test]
+async fn transaction_fails_on_conflicting_write() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+
+    let mut user = User::new(User {
+        id: NodeID::new(),
+        name: "John".to_string(),
+        age: 20,
+    });
+    db.insert_node(&user).await.unwrap();
+
+    let id = user.id;
+    let res = db
+        .transaction(|tx| async move {
+            let mut found = tx.get_node::<User>(id).await?;
+            user.age = 30;
+            db.update_node(&user).await?;
+            found.age += 1;
+            tx.insert_node(&found).await
+        })
+        .await;
+    assert!(matches!(res, Err(DBError::TransactionError { .. })));
+    assert_eq!(db.get_node::<User>(id).await.unwrap().age, 30);
+}
+
+#[tokio::test]
+async fn transaction_fails_on_edge_removed_after_read() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+
+    let user = User::new(User {
+        id: NodeID::new(),
+        name: "John".to_string(),
+        age: 20,
+    });
+    let mut edge = Edge::new("car_owned_by");
+    let car = Car::new(Car {
+        id: NodeID::new(),
+        name: "Ford".to_string(),
+        model: "Mustang".to_string(),
+        owner: EdgeRef::new(&edge),
+    });
+    edge.link(&car, &user, Data::None);
+    let edge = edge.item.unwrap();
+    db.insert_edge(&edge).await.unwrap();
+
+    let res = db
+        .transaction(|tx| async move {
+            let found = tx.get_edge(car.id, user.id).await?;
+            db.remove_edge(&found).await?;
+            tx.insert_node(&car).await
+        })
+        .await;
+    assert!(matches!(res, Err(DBError::TransactionError { .. })));
+    assert!(db.get_edge(edge.from, edge.to).await.is_err());
+}
+
+#[tokio::test]
+async fn multiple_edges_between_nodes() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+
+    let john = User::new(User {
+        id: NodeID::new(),
+        name: "John".to_string(),
+        age: 20,
+    });
+    let jane = User::new(User {
+        id: NodeID::new(),
+        name: "Jane".to_string(),
+        age: 20,
+    });
+
+    let edges: Vec<EdgeItem> = ["follows", "blocks"]
+        .into_iter()
+        .map(|label| {
+            let mut edge = Edge::new(label);
+            edge.link(&john, &jane, Data::None);
+            edge.item.unwrap()
+        })
+        .collect();
+    db.insert_edges(&edges).await.unwrap();
+
+    let found = db.get_edges(john.id, jane.id).await.unwrap();
+    assert_eq!(found, vec![edges[1].clone(), edges[0].clone()]);
+    let follows = db.get_edge_by_label(john.id, jane.id, "follows");
+    assert_eq!(follows.await.unwrap(), edges[0]);
+    assert!(db.get_edges(jane.id, john.id).await.unwrap().is_empty());
+
+    db.remove_edge(&edges[1]).await.unwrap();
+    assert!(db
+        .get_edge_by_label(john.id, jane.id, "blocks")
+        .await
+        .is_err());
+    assert_eq!(db.get_edge(john.id, jane.id).await.unwrap(), edges[0]);
+}
+
+#[tokio::test]
+async fn out_and_in_edges() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+
+    let users: Vec<User> = ["John", "Jane", "Mary"]
+        .into_iter()
+        .map(|name| {
+            User::new(User {
+                id: NodeID::new(),
+                name: name.to_string(),
+                age: 20,
+            })
+        })
+        .collect();
+    let link = |label: &str, from: &User, to: &User| {
+        let mut edge = Edge::new(label);
+        edge.link(from, to, Data::None);
+        edge.item.unwrap()
+    };
+    let follows_jane = link("follows", &users[0], &users[1]);
+    let follows_mary = link("follows", &users[0], &users[2]);
+    let blocks_mary = link("blocks", &users[0], &users[2]);
+    db.insert_edges(&[follows_jane.clone(), follows_mary.clone()])
+        .await
+        .unwrap();
+    let tx_edge = blocks_mary.clone();
+    db.transaction(|tx| async move { tx.insert_edge(&tx_edge).await })
+        .await
+        .unwrap();
+
+    let out: Vec<EdgeItem> = db.out_edges(users[0].id, None).try_collect().await.unwrap();
+    assert_eq!(out.len(), 3);
+    let out: Vec<EdgeItem> = db
+        .out_edges(users[0].id, Some("follows"))
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(out.len(), 2);
+    assert!(out.contains(&follows_jane) && out.contains(&follows_mary));
+
+    let into: Vec<EdgeItem> = db.in_edges(users[2].id, None).try_collect().await.unwrap();
+    assert_eq!(into, vec![blocks_mary.clone(), follows_mary.clone()]);
+    let into: Vec<EdgeItem> = db.in_edges(users[0].id, None).try_collect().await.unwrap();
+    assert!(into.is_empty());
+
+    db.remove_edge(&follows_mary).await.unwrap();
+    let into: Vec<EdgeItem> = db.in_edges(users[2].id, None).try_collect().await.unwrap();
+    assert_eq!(into, vec![blocks_mary]);
+}
+
+#[tokio::test]
+async fn adjacency_labels_sharing_a_prefix() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+
+    let users: Vec<User> = ["John", "Jane"]
+        .into_iter()
+        .map(|name| {
+            User::new(User {
+                id: NodeID::new(),
+                name: name.to_string(),
+                age: 20,
+            })
+        })
+        .collect();
+    let edges: Vec<EdgeItem> = ["a", "a:b", "ab"]
+        .into_iter()
+        .map(|label| {
+            let mut edge = Edge::new(label);
+            edge.link(&users[0], &users[1], Data::None);
+            edge.item.unwrap()
+        })
+        .collect();
+    db.insert_edges(&edges).await.unwrap();
+
+    for (i, label) in ["a", "a:b", "ab"].into_iter().enumerate() {
+        let out: Vec<EdgeItem> = db
+            .out_edges(users[0].id, Some(label))
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(out, vec![edges[i].clone()]);
+        let into: Vec<EdgeItem> = db
+            .in_edges(users[1].id, Some(label))
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(into, vec![edges[i].clone()]);
+    }
+    let out: Vec<EdgeItem> = db.out_edges(users[0].id, None).try_collect().await.unwrap();
+    assert_eq!(out, edges);
+}
+
+#[tokio::test]
+async fn unique_index_rejects_duplicates() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+
+    let mut john = create_account("john@example.com");
+    let jane = create_account("jane@example.com");
+    db.insert_nodes(&[john.clone(), jane.clone()])
+        .await
+        .unwrap();
+    db.insert_node(&john).await.unwrap();
+
+    let duplicate = create_account("john@example.com");
+    let res = db.insert_node(&duplicate).await;
+    assert!(matches!(
+        res,
+        Err(DBError::UniqueViolation { entity, field, value })
+            if entity == duplicate.entity()
+                && field == "email"
+                && value == Value::from("john@example.com")
+    ));
+    assert!(db.get_node::<Account>(duplicate.id).await.is_err());
+
+    let mut taken = jane.clone();
+    taken.email = john.email.clone();
+    let res = db.update_node(&taken).await;
+    assert!(matches!(res, Err(DBError::UniqueViolation { .. })));
+    assert_eq!(db.get_node::<Account>(jane.id).await.unwrap(), jane);
+
+    let accounts = [
+        create_account("a@example.com"),
+        create_account("a@example.com"),
+    ];
+    let res = db.insert_nodes(&accounts).await;
+    assert!(matches!(res, Err(DBError::UniqueViolation { .. })));
+
+    john.email = "john@example.org".to_string();
+    db.update_node(&john).await.unwrap();
+    db.insert_node(&duplicate).await.unwrap();
+
+    let tx_jane = taken.clone();
+    let res = db
+        .transaction(|tx| async move { tx.insert_node(&tx_jane).await })
+        .await;
+    assert!(matches!(res, Err(DBError::UniqueViolation { .. })));
+
+    db.remove_node(&duplicate).await.unwrap();
+    db.transaction(|tx| async move { tx.insert_node(&taken).await })
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn unique_index_holds_against_concurrent_transactions() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+
+    for round in 0..200 {
+        let email = format!("user{}@example.com", round);
+        let (plain, tx_account) = (create_account(&email), create_account(&email));
+        let (plain_id, tx_id) = (plain.id, tx_account.id);
+        std::thread::scope(|scope| {
+            scope.spawn(|| block_on(db.insert_node(&plain)));
+            scope.spawn(|| {
+                block_on(db.transaction(|tx| async move { tx.insert_node(&tx_account).await }))
+            });
+        });
+
+        let mut owners = 0;
+        for id in [plain_id, tx_id] {
+            if db.get_node::<Account>(id).await.is_ok() {
+                owners += 1;
+            }
+        }
+        assert_eq!(owners, 1, "{} is owned by {} accounts", email, owners);
+    }
+}
@@ -0,0 +1,425 @@
+use arky::db::Transaction;
+use arky::edge::{prelude::*, EdgeItem};
+use arky::inst::prelude::*;
+use arky::node::{prelude::*, GeoPoint, Value};
+use common::{create_account, create_user, Account, User};
+use futures::TryStreamExt;
+
+mod common;
+
+#[schema(Node)]
+struct Song {
+    pub id: NodeID,
+    pub title: String,
+    #[index(vector)]
+    pub embedding: Vec<f32>,
+}
+
+#[schema(Node)]
+struct Station {
+    pub id: NodeID,
+    pub name: String,
+    #[index(geo)]
+    pub location: GeoPoint,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Unregistered(u32);
+
+#[tokio::test]
+async fn memory_nodes_and_edges() {
+    let storage = MemoryStorage::new(MemoryConfig::default());
+    let db = ArkyDB::init(&storage);
+
+    let john = create_user("John", 20);
+    let jane = create_user("Jane", 30);
+    db.insert_nodes(&[john.clone(), jane.clone()])
+        .await
+        .unwrap();
+    assert_eq!(db.get_node::<User>(john.id).await.unwrap(), john);
+    assert!(db.get_entity(&john.entity()).await.is_ok());
+
+    let mut edge = Edge::new("friend_of");
+    edge.link(&john, &jane, Data::None);
+    let edge = edge.item.unwrap();
+    db.insert_edge(&edge).await.unwrap();
+    assert_eq!(db.get_edge(john.id, jane.id).await.unwrap(), edge);
+    assert_eq!(db.scan_edges().await.unwrap(), vec![edge.clone()]);
+
+    let mut blocks = Edge::new("blocks");
+    blocks.link(&john, &jane, Data::None);
+    let blocks = blocks.item.unwrap();
+    db.insert_edge(&blocks).await.unwrap();
+    let found = db.get_edges(john.id, jane.id).await.unwrap();
+    assert_eq!(found, vec![blocks.clone(), edge.clone()]);
+    let found = db.get_edge_by_label(john.id, jane.id, "blocks").await;
+    assert_eq!(found.unwrap(), blocks);
+    let out: Vec<EdgeItem> = db.out_edges(john.id, None).try_collect().await.unwrap();
+    assert_eq!(out, vec![blocks.clone(), edge.clone()]);
+    let into: Vec<EdgeItem> = db
+        .in_edges(jane.id, Some("friend_of"))
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(into, vec![edge.clone()]);
+    db.remove_edge(&blocks).await.unwrap();
+
+    db.remove_edge(&edge).await.unwrap();
+    db.remove_node(&john).await.unwrap();
+    assert!(db.get_edge(john.id, jane.id).await.is_err());
+    assert!(db.get_node::<User>(john.id).await.is_err());
+    assert_eq!(
+        db.get_node_ids(&jane.entity()).await.unwrap(),
+        vec![jane.id]
+    );
+}
+
+#[tokio::test]
+async fn memory_scans_edges_in_key_order() {
+    let storage = MemoryStorage::new(MemoryConfig::default());
+    let db = ArkyDB::init(&storage);
+
+    let users: Vec<User> = [9, 10, 100]
+        .into_iter()
+        .map(|id| {
+            User::new(User {
+                id: NodeID(id),
+                name: id.to_string(),
+                age: 20,
+            })
+        })
+        .collect();
+    let edges: Vec<EdgeItem> = [(0, 1), (1, 0), (2, 0)]
+        .into_iter()
+        .map(|(from, to)| {
+            let mut edge = Edge::new("knows");
+            edge.link(&users[from], &users[to], Data::None);
+            edge.item.unwrap()
+        })
+        .collect();
+    db.insert_edges(&edges).await.unwrap();
+
+    // Keys compare as strings: `100:` before `10:` before `9:`.
+    let expected = vec![edges[2].clone(), edges[1].clone(), edges[0].clone()];
+    assert_eq!(db.scan_edges().await.unwrap(), expected);
+}
+
+#[tokio::test]
+async fn memory_batch_reports_failed_items() {
+    let storage = MemoryStorage::new(MemoryConfig::default());
+    let db = ArkyDB::init(&storage);
+
+    let john = create_user("John", 20);
+    let jane = create_user("Jane", 30);
+    let edges: Vec<EdgeItem> = [Data::None, Data::new(Unregistered(1))]
+        .into_iter()
+        .map(|data| {
+            let mut edge = Edge::new("knows");
+            edge.link(&john, &jane, data);
+            edge.item.unwrap()
+        })
+        .collect();
+
+    let res = db.insert_edges(&edges).await;
+    assert!(matches!(res, Err(DBError::BatchError { items }) if items[0].index == 1));
+    let res = db.insert_edge(&edges[1]).await;
+    assert!(matches!(res, Err(DBError::InsertEdgeError { key, .. }) if key == edges[1].key()));
+    assert!(db.scan_edges().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn memory_transactions() {
+    let storage = MemoryStorage::new(MemoryConfig::default());
+    let db = ArkyDB::init(&storage);
+
+    let john = create_user("John", 20);
+    let tx_john = john.clone();
+    let res = db
+        .transaction(|tx| async move {
+            tx.insert_node(&tx_john).await?;
+            assert_eq!(tx.get_node::<User>(tx_john.id).await?, tx_john);
+            tx.get_edge(tx_john.id, NodeID::new()).await
+        })
+        .await;
+    assert!(matches!(res, Err(DBError::GetEdgeError { .. })));
+    assert!(db.get_node::<User>(john.id).await.is_err());
+
+    let tx_john = john.clone();
+    db.transaction(|tx| async move { tx.insert_node(&tx_john).await })
+        .await
+        .unwrap();
+    assert_eq!(db.get_node::<User>(john.id).await.unwrap(), john);
+
+    let mut other = john.clone();
+    let res = db
+        .transaction(|tx| async move {
+            let mut found = tx.get_node::<User>(other.id).await?;
+            other.age = 30;
+            db.update_node(&other).await?;
+            found.age += 1;
+            tx.insert_node(&found).await
+        })
+        .await;
+    assert!(matches!(res, Err(DBError::TransactionError { .. })));
+    assert_eq!(db.get_node::<User>(john.id).await.unwrap().age, 30);
+}
+
+#[tokio::test]
+async fn memory_query() {
+    let storage = MemoryStorage::new(MemoryConfig::default());
+    let db = ArkyDB::init(&storage);
+
+    for (name, age) in [("John", 40), ("Jane", 18), ("Mary", 16)] {
+        db.insert_node(&create_user(name, age)).await.unwrap();
+    }
+
+    let query = db.query().by_index("name", "Jane").build().unwrap();
+    let users = query.exec::<User>().await.unwrap();
+    assert_eq!(users.len(), 1);
+    assert_eq!(users[0].age, 18);
+
+    let mut jane = users[0].clone();
+    jane.name = "Janet".to_string();
+    db.update_node(&jane).await.unwrap();
+    assert!(query.exec::<User>().await.unwrap().is_empty());
+    jane.name = "Jane".to_string();
+    let tx_jane = jane.clone();
+    db.transaction(|tx| async move { tx.insert_node(&tx_jane).await })
+        .await
+        .unwrap();
+    assert_eq!(query.exec::<User>().await.unwrap(), vec![jane]);
+
+    let mut query = db.query().build().unwrap();
+    let users = query
+        .sort_by_prop("age")
+        .skip(1)
+        .exec::<User>()
+        .await
+        .unwrap();
+    let names: Vec<_> = users.iter().map(|user| user.name.as_str()).collect();
+    assert_eq!(names, ["Jane", "John"]);
+
+    let query = db.query().search("name", "jane mary").build().unwrap();
+    assert_eq!(query.count::<User>().await.unwrap(), 2);
+
+    let mut query = db.query().by_index_range("age", ..18).build().unwrap();
+    let users = query.sort_by_prop("age").exec::<User>().await.unwrap();
+    assert_eq!(users.len(), 1);
+    assert_eq!(users[0].name, "Mary");
+    let mut query = db.query().build().unwrap();
+    let users = query
+        .sort_by_prop("age")
+        .limit(1)
+        .exec::<User>()
+        .await
+        .unwrap();
+    assert_eq!(users[0].name, "Mary");
+}
+
+#[tokio::test]
+async fn memory_unique_index() {
+    let storage = MemoryStorage::new(MemoryConfig::default());
+    let db = ArkyDB::init(&storage);
+
+    let mut john = create_account("john@example.com");
+    let jane = create_account("jane@example.com");
+    db.insert_nodes(&[john.clone(), jane.clone()])
+        .await
+        .unwrap();
+    db.insert_node(&john).await.unwrap();
+
+    let duplicate = create_account("john@example.com");
+    let res = db.insert_node(&duplicate).await;
+    assert!(matches!(
+        res,
+        Err(DBError::UniqueViolation { entity, field, value })
+            if entity == duplicate.entity()
+                && field == "email"
+                && value == Value::from("john@example.com")
+    ));
+    assert!(db.get_node::<Account>(duplicate.id).await.is_err());
+
+    let mut taken = jane.clone();
+    taken.email = john.email.clone();
+    let res = db.update_node(&taken).await;
+    assert!(matches!(res, Err(DBError::UniqueViolation { .. })));
+    assert_eq!(db.get_node::<Account>(jane.id).await.unwrap(), jane);
+
+    let accounts = [
+        create_account("a@example.com"),
+        create_account("a@example.com"),
+    ];
+    let res = db.insert_nodes(&accounts).await;
+    assert!(matches!(res, Err(DBError::UniqueViolation { .. })));
+
+    john.email = "john@example.org".to_string();
+    db.update_node(&john).await.unwrap();
+    db.insert_node(&duplicate).await.unwrap();
+
+    let tx_jane = taken.clone();
+    let res = db
+        .transaction(|tx| async move { tx.insert_node(&tx_jane).await })
+        .await;
+    assert!(matches!(res, Err(DBError::UniqueViolation { .. })));
+
+    db.remove_node(&duplicate).await.unwrap();
+    db.transaction(|tx| async move { tx.insert_node(&taken).await })
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn memory_nearest() {
+    let storage = MemoryStorage::new(MemoryConfig::default());
+    let db = ArkyDB::init(&storage);
+
+    let songs: Vec<_> = [
+        ("calm", [1.0, 0.1]),
+        ("loud", [0.1, 1.0]),
+        ("mixed", [1.0, 1.0]),
+    ]
+    .into_iter()
+    .map(|(title, embedding)| {
+        Song::new(Song {
+            id: NodeID::new(),
+            title: title.to_string(),
+            embedding: embedding.to_vec(),
+        })
+    })
+    .collect();
+    db.insert_nodes(&songs).await.unwrap();
+
+    let query = db
+        .query()
+        .nearest("embedding", &[2.0, 0.0], 2)
+        .build()
+        .unwrap();
+    let found = query.exec::<Song>().await.unwrap();
+    assert_eq!(found, vec![songs[0].clone(), songs[2].clone()]);
+
+    db.remove_node(&songs[0]).await.unwrap();
+    let found = query.exec::<Song>().await.unwrap();
+    assert_eq!(found, vec![songs[2].clone(), songs[1].clone()]);
+
+    let query = db.query().nearest("title", &[2.0, 0.0], 2).build().unwrap();
+    assert!(matches!(
+        query.exec::<Song>().await,
+        Err(DBError::QueryError { .. })
+    ));
+}
+
+#[tokio::test]
+async fn memory_within() {
+    let storage = MemoryStorage::new(MemoryConfig::default());
+    let db = ArkyDB::init(&storage);
+
+    let stations: Vec<_> = [
+        ("shibuya", 35.6580, 139.7016),
+        ("shinjuku", 35.6896, 139.7006),
+        ("yokohama", 35.4658, 139.6223),
+    ]
+    .into_iter()
+    .map(|(name, lat, lon)| {
+        Station::new(Station {
+            id: NodeID::new(),
+            name: name.to_string(),
+            location: GeoPoint::new(lat, lon),
+        })
+    })
+    .collect();
+    db.insert_nodes(&stations).await.unwrap();
+
+    let query = db
+        .query()
+        .within_radius("location", GeoPoint::new(35.6580, 139.7016), 5_000.0)
+        .build()
+        .unwrap();
+    let mut found = query.exec::<Station>().await.unwrap();
+    found.sort_by(|a, b| a.name.cmp(&b.name));
+    assert_eq!(found, vec![stations[0].clone(), stations[1].clone()]);
+
+    db.remove_node(&stations[1]).await.unwrap();
+    let query = db
+        .query()
+        .within_bbox(
+            "location",
+            GeoPoint::new(35.0, 139.0),
+            GeoPoint::new(36.0, 140.0),
+        )
+        .build()
+        .unwrap();
+    let mut found = query.exec::<Station>().await.unwrap();
+    found.sort_by(|a, b| a.name.cmp(&b.name));
+    assert_eq!(found, vec![stations[0].clone(), stations[2].clone()]);
+}
+
+#[tokio::test]
+async fn memory_exec_stream() {
+    let storage = MemoryStorage::new(MemoryConfig::default());
+    let db = ArkyDB::init(&storage);
+
+    for (name, age) in [("John", 40), ("Jane", 18), ("Mary", 16)] {
+        db.insert_node(&create_user(name, age)).await.unwrap();
+    }
+
+    let query = db.query().build().unwrap();
+    let users: Vec<User> = query.exec_stream::<User>().try_collect().await.unwrap();
+    let mut names: Vec<_> = users.iter().map(|user| user.name.as_str()).collect();
+    names.sort();
+    assert_eq!(names, ["Jane", "John", "Mary"]);
+}
+
+#[tokio::test]
+async fn memory_pages() {
+    let storage = MemoryStorage::new(MemoryConfig::default());
+    let db = ArkyDB::init(&storage);
+
+    let users: Vec<_> = (0..5).map(|age| create_user("John", age)).collect();
+    db.insert_nodes(&users).await.unwrap();
+
+    let mut query = db.query().build().unwrap();
+    let first = query.limit(3).page::<User>().await.unwrap();
+    assert_eq!(first.nodes, users[..3]);
+    let cursor = first.next.unwrap();
+    let second = query.after(&cursor).page::<User>().await.unwrap();
+    assert_eq!(second.nodes, users[3..]);
+    assert!(second.next.is_none());
+
+    let mut query = db.query().build().unwrap();
+    query.sort_by_prop("age").limit(2);
+    let mut found = Vec::new();
+    loop {
+        let page = query.page::<User>().await.unwrap();
+        found.extend(page.nodes);
+        match page.next {
+            Some(cursor) => query.after(&cursor),
+            None => break,
+        };
+    }
+    assert_eq!(found, users);
+}
+
+#[tokio::test]
+async fn memory_delete_by_query() {
+    let storage = MemoryStorage::new(MemoryConfig::default());
+    let db = ArkyDB::init(&storage);
+
+    let users: Vec<_> = [("john", 40), ("jane", 18)]
+        .into_iter()
+        .map(|(name, age)| create_user(name, age))
+        .collect();
+    db.insert_nodes(&users).await.unwrap();
+    let mut knows = Edge::new("knows");
+    knows.link(&users[0], &users[1], Data::None);
+    db.insert_edge(knows.item.as_ref().unwrap()).await.unwrap();
+
+    let query = db.query().by_index_range("age", 30..).build().unwrap();
+    let updated = query.update(|user: &mut User| user.age += 1).await.unwrap();
+    assert_eq!(updated, 1);
+    assert_eq!(db.get_node::<User>(users[0].id).await.unwrap().age, 41);
+
+    assert_eq!(query.delete::<User>().await.unwrap(), 1);
+    assert!(db.get_node::<User>(users[0].id).await.is_err());
+    let edges: Vec<EdgeItem> = db.in_edges(users[1].id, None).try_collect().await.unwrap();
+    assert!(edges.is_empty());
+}
@@ -0,0 +1,1575 @@
+use arky::aggregate::Aggregate;
+use arky::cursor::Cursor;
+use arky::db::{DBError, Transaction};
+use arky::edge::{prelude::*, EdgeItem};
+use arky::inst::prelude::*;
+use arky::node::{prelude::*, GeoArea, GeoPoint, Value, VectorMetric};
+use arky::path::PathOptions;
+use arky::plan::{Access, Plan};
+use arky::query::{Predicate, QueryExecutor, QueryOperation};
+use futures::{StreamExt, TryStreamExt};
+use std::ops::Bound;
+use tempdir::TempDir;
+
+#[schema(Node)]
+struct User {
+    pub id: NodeID,
+    #[index]
+    pub name: String,
+    #[index(range)]
+    pub age: u32,
+}
+
+#[schema(Node)]
+struct Car {
+    pub id: NodeID,
+    pub name: String,
+    pub model: String,
+}
+
+#[schema(Node)]
+struct Reading {
+    pub id: NodeID,
+    #[index(range)]
+    pub value: f64,
+}
+
+#[schema(Node)]
+struct Task {
+    pub id: NodeID,
+    #[index(range)]
+    pub due: Option<u32>,
+}
+
+#[schema(Node)]
+#[index(brand, category, price)]
+struct Product {
+    pub id: NodeID,
+    pub brand: String,
+    pub category: String,
+    pub price: u32,
+}
+
+#[schema(Node)]
+struct Article {
+    pub id: NodeID,
+    #[index(fulltext)]
+    pub title: String,
+    pub body: String,
+}
+
+#[schema(Node)]
+struct Place {
+    pub id: NodeID,
+    pub name: String,
+    #[index(vector(l2))]
+    pub position: Vec<f32>,
+}
+
+fn create_place(x: f32, y: f32) -> Place {
+    Place::new(Place {
+        id: NodeID::new(),
+        name: format!("{},{}", x, y),
+        position: vec![x, y],
+    })
+}
+
+#[schema(Node)]
+struct Depot {
+    pub id: NodeID,
+    pub name: String,
+    #[index(geo)]
+    pub location: GeoPoint,
+}
+
+#[schema(Node)]
+struct Store {
+    pub id: NodeID,
+    pub name: String,
+    #[index(geo)]
+    pub location: (f64, f64),
+    pub entrance: (f64, f64),
+}
+
+#[schema(Node)]
+struct City {
+    pub id: NodeID,
+    pub name: String,
+}
+
+#[schema(EdgeData)]
+struct Road {
+    pub km: u32,
+}
+
+fn create_depot(name: &str, lat: f64, lon: f64) -> Depot {
+    Depot::new(Depot {
+        id: NodeID::new(),
+        name: name.to_string(),
+        location: GeoPoint::new(lat, lon),
+    })
+}
+
+fn create_storage() -> RocksDB {
+    let dir = TempDir::new("arky").unwrap();
+    let db_path = dir.path().join("test_db").to_str().unwrap().to_string();
+    RocksDB::new(RocksDBConfig {
+        path: db_path,
+        set_error_if_exists: false,
+        ..Default::default()
+    })
+}
+
+fn accesses(plan: &Plan) -> Vec<(Access, usize)> {
+    plan.steps
+        .iter()
+        .map(|step| (step.access.clone(), step.estimate))
+        .collect()
+}
+
+fn create_user(name: &str, age: u32) -> User {
+    User::new(User {
+        id: NodeID::new(),
+        name: name.to_string(),
+        age,
+    })
+}
+
+#[tokio::test]
+async fn query_by_id() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+
+    let john = create_user("John", 20);
+    let jane = create_user("Jane", 30);
+    db.insert_node(&john).await.unwrap();
+    db.insert_node(&jane).await.unwrap();
+
+    let query = db.query().by_id(&jane.id).build().unwrap();
+    let users = query.exec::<User>().await.unwrap();
+    assert_eq!(users, vec![jane.clone()]);
+
+    let cars = db.exec::<Car>(&query).await.unwrap();
+    assert!(cars.is_empty());
+}
+
+#[tokio::test]
+async fn query_by_index_and_prop() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+
+    let john = create_user("John", 20);
+    let jane = create_user("Jane", 20);
+    let old_john = create_user("John", 60);
+    for user in [&john, &jane, &old_john] {
+        db.insert_node(user).await.unwrap();
+    }
+
+    let query = db
+        .query()
+        .by_index("name", "John")
+        .filter_by_prop("age", 20)
+        .build()
+        .unwrap();
+    let users = query.exec::<User>().await.unwrap();
+    assert_eq!(users, vec![john]);
+}
+
+#[tokio::test]
+async fn query_by_edge_label() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+
+    let john = create_user("John", 20);
+    let car = Car::new(Car {
+        id: NodeID::new(),
+        name: "Ford".to_string(),
+        model: "Mustang".to_string(),
+    });
+    db.insert_node(&john).await.unwrap();
+    db.insert_node(&car).await.unwrap();
+
+    let mut user_cars_edge = Edge::new("user_owns");
+    user_cars_edge.link(&john, &car, Data::None);
+    db.insert_edge(user_cars_edge.item.as_ref().unwrap())
+        .await
+        .unwrap();
+
+    let query = db.query().by_edge_label("user_owns").build().unwrap();
+    assert_eq!(query.exec::<Car>().await.unwrap(), vec![car.clone()]);
+    assert_eq!(query.exec::<User>().await.unwrap(), vec![john.clone()]);
+
+    let query = db.query().by_edge_from(&john.id).build().unwrap();
+    assert_eq!(query.exec::<Car>().await.unwrap(), vec![car]);
+}
+
+#[tokio::test]
+async fn query_with_sort_and_pagination() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+
+    for (name, age) in [("John", 40), ("Jane", 18), ("Peter", 30), ("Mary", 16)] {
+        db.insert_node(&create_user(name, age)).await.unwrap();
+    }
+
+    let mut query = db.query().build().unwrap();
+    assert_eq!(query.count::<User>().await.unwrap(), 4);
+
+    let users = query
+        .sort_by_prop("age")
+        .skip(2)
+        .limit(1)
+        .exec::<User>()
+        .await
+        .unwrap();
+    assert_eq!(users.len(), 1);
+    assert_eq!(users[0].name, "Peter");
+}
+
+#[tokio::test]
+async fn query_by_index_follows_updates() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+
+    let mut john = create_user("John", 20);
+    let jane = create_user("Jane", 30);
+    db.insert_nodes(&[john.clone(), jane.clone()])
+        .await
+        .unwrap();
+
+    let entity = User::entity_name();
+    let name = Value::from("John");
+    let ids = db.get_node_ids_by_index(&entity, "name", &name).await;
+    assert_eq!(ids.unwrap(), vec![john.id]);
+
+    john.name = "Johnny".to_string();
+    db.update_node(&john).await.unwrap();
+    let ids = db.get_node_ids_by_index(&entity, "name", &name).await;
+    assert!(ids.unwrap().is_empty());
+    let query = db.query().by_index("name", "Johnny").build().unwrap();
+    assert_eq!(query.exec::<User>().await.unwrap(), vec![john.clone()]);
+
+    let tx_jane = jane.clone();
+    db.transaction(|tx| async move { tx.remove_node(&tx_jane).await })
+        .await
+        .unwrap();
+    let query = db.query().by_index("name", "Jane").build().unwrap();
+    assert!(query.exec::<User>().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn query_by_index_range() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+
+    for (name, age) in [("John", 40), ("Jane", 18), ("Mary", 16), ("Paul", 65)] {
+        db.insert_node(&create_user(name, age)).await.unwrap();
+    }
+
+    let mut query = db.query().by_index_range("age", 18..65).build().unwrap();
+    let users = query.sort_by_prop("age").exec::<User>().await.unwrap();
+    let names: Vec<_> = users.iter().map(|user| user.name.as_str()).collect();
+    assert_eq!(names, ["Jane", "John"]);
+
+    let query = db.query().by_index_range("age", 40..).build().unwrap();
+    assert_eq!(query.count::<User>().await.unwrap(), 2);
+    let query = db.query().by_index_range("age", ..=18).build().unwrap();
+    assert_eq!(query.count::<User>().await.unwrap(), 2);
+
+    let mut query = db.query().build().unwrap();
+    let users = query
+        .sort_by_prop("age")
+        .skip(2)
+        .limit(2)
+        .exec::<User>()
+        .await
+        .unwrap();
+    let names: Vec<_> = users.iter().map(|user| user.name.as_str()).collect();
+    assert_eq!(names, ["John", "Paul"]);
+}
+
+#[tokio::test]
+async fn index_range_orders_numbers() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+
+    let values = [256.0, -2.5, 1e10, 0.5, -1.0, 255.0, 0.0, 3.0];
+    let readings: Vec<_> = values
+        .iter()
+        .map(|value| {
+            Reading::new(Reading {
+                id: NodeID::new(),
+                value: *value,
+            })
+        })
+        .collect();
+    db.insert_nodes(&readings).await.unwrap();
+
+    let mut query = db.query().build().unwrap();
+    let found = query.sort_by_prop("value").exec::<Reading>().await.unwrap();
+    let found: Vec<_> = found.iter().map(|reading| reading.value).collect();
+    assert_eq!(found, [-2.5, -1.0, 0.0, 0.5, 3.0, 255.0, 256.0, 1e10]);
+
+    let entity = Reading::entity_name();
+    let (start, end) = (Value::Int(-1), Value::UInt(1));
+    let ids = db
+        .get_node_ids_by_index_range(
+            &entity,
+            "value",
+            Bound::Included(&start),
+            Bound::Excluded(&end),
+        )
+        .await
+        .unwrap();
+    let found = db.get_nodes::<Reading>(&ids).await.unwrap();
+    let found: Vec<_> = found.iter().map(|reading| reading.value).collect();
+    assert_eq!(found, [-1.0, 0.0, 0.5]);
+
+    let mut query = db
+        .query()
+        .by_index_range("value", 256.0..f64::NAN)
+        .build()
+        .unwrap();
+    let found = query.sort_by_prop("value").exec::<Reading>().await.unwrap();
+    let found: Vec<_> = found.iter().map(|reading| reading.value).collect();
+    assert_eq!(found, [256.0, 1e10]);
+}
+
+#[tokio::test]
+async fn query_by_composite_index() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+
+    let products: Vec<_> = [
+        ("Acme", "Shoes", 80),
+        ("Acme", "Shoes", 60),
+        ("Acme", "Hats", 20),
+        ("Bolt", "Shoes", 60),
+    ]
+    .into_iter()
+    .map(|(brand, category, price)| {
+        Product::new(Product {
+            id: NodeID::new(),
+            brand: brand.to_string(),
+            category: category.to_string(),
+            price,
+        })
+    })
+    .collect();
+    db.insert_nodes(&products).await.unwrap();
+
+    let fields = [
+        "brand".to_string(),
+        "category".to_string(),
+        "price".to_string(),
+    ];
+    let values = ["Acme".into(), "Shoes".into()];
+    let entity = Product::entity_name();
+    let ids = db
+        .get_node_ids_by_composite_index(&entity, &fields, &values)
+        .await
+        .unwrap();
+    assert_eq!(ids.len(), 2);
+    let found = db.get_nodes::<Product>(&ids).await.unwrap();
+    let prices: Vec<_> = found.iter().map(|product| product.price).collect();
+    assert_eq!(prices, [60, 80]);
+
+    let query = db
+        .query()
+        .by_composite_index(&["brand", "category"], &["Acme".into(), "Shoes".into()])
+        .build()
+        .unwrap();
+    assert_eq!(query.count::<Product>().await.unwrap(), 2);
+
+    let query = db
+        .query()
+        .by_composite_index(&["category", "price"], &["Shoes".into(), 60.into()])
+        .build()
+        .unwrap();
+    let found = query.exec::<Product>().await.unwrap();
+    let brands: Vec<_> = found.iter().map(|product| product.brand.as_str()).collect();
+    assert_eq!(brands.len(), 2);
+    assert!(brands.contains(&"Acme") && brands.contains(&"Bolt"));
+
+    let mut hats = products[2].clone();
+    hats.category = "Shoes".to_string();
+    db.update_node(&hats).await.unwrap();
+    let query = db
+        .query()
+        .by_composite_index(&["brand", "category"], &["Acme".into(), "Hats".into()])
+        .build()
+        .unwrap();
+    assert!(query.exec::<Product>().await.unwrap().is_empty());
+
+    let res = db
+        .query()
+        .by_composite_index(&["brand", "category"], &["Acme".into()])
+        .build();
+    assert!(matches!(res, Err(DBError::QueryError { .. })));
+}
+
+#[tokio::test]
+async fn query_search_ranks_by_bm25() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+
+    let articles: Vec<_> = [
+        "Graph databases in Rust",
+        "Cooking with cast iron",
+        "Rust, rust and more Rust",
+        "A short history of graph theory",
+    ]
+    .into_iter()
+    .map(|title| {
+        Article::new(Article {
+            id: NodeID::new(),
+            title: title.to_string(),
+            body: title.to_lowercase(),
+        })
+    })
+    .collect();
+    db.insert_nodes(&articles).await.unwrap();
+
+    let query = db.query().search("title", "rust GRAPH").build().unwrap();
+    let found = query.exec::<Article>().await.unwrap();
+    let titles: Vec<_> = found.iter().map(|article| article.title.as_str()).collect();
+    assert_eq!(
+        titles,
+        [
+            "Graph databases in Rust",
+            "Rust, rust and more Rust",
+            "A short history of graph theory",
+        ]
+    );
+
+    let mut cooking = articles[1].clone();
+    cooking.title = "Cooking Rust out of cast iron".to_string();
+    db.update_node(&cooking).await.unwrap();
+    let query = db.query().search("title", "cooking").build().unwrap();
+    assert_eq!(query.exec::<Article>().await.unwrap(), vec![cooking]);
+    let query = db.query().search("title", "rust").build().unwrap();
+    assert_eq!(query.count::<Article>().await.unwrap(), 3);
+
+    let query = db.query().search("body", "theory").build().unwrap();
+    let found = query.exec::<Article>().await.unwrap();
+    assert_eq!(found, vec![articles[3].clone()]);
+    let query = db.query().search("title", "").build().unwrap();
+    assert!(query.exec::<Article>().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn query_nearest_by_vector_index() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+
+    let places: Vec<_> = (0..15)
+        .flat_map(|x| (0..15).map(move |y| create_place(x as f32, y as f32)))
+        .collect();
+    db.insert_nodes(&places).await.unwrap();
+
+    let query = db
+        .query()
+        .nearest("position", &[3.1, 4.2], 4)
+        .build()
+        .unwrap();
+    let found = query.exec::<Place>().await.unwrap();
+    let names: Vec<_> = found.iter().map(|place| place.name.as_str()).collect();
+    assert_eq!(names, ["3,4", "3,5", "4,4", "2,4"]);
+
+    let closest = found[0].clone();
+    db.remove_node(&closest).await.unwrap();
+    let mut moved = found[1].clone();
+    moved.position = vec![14.0, 14.5];
+    db.update_node(&moved).await.unwrap();
+    let query = db
+        .query()
+        .nearest("position", &[3.1, 4.2], 2)
+        .build()
+        .unwrap();
+    let found = query.exec::<Place>().await.unwrap();
+    let names: Vec<_> = found.iter().map(|place| place.name.as_str()).collect();
+    assert_eq!(names, ["4,4", "2,4"]);
+
+    let entity = Place::entity_name();
+    let metric = VectorMetric::L2;
+    let found = db
+        .nearest_node_ids(&entity, "position", metric, &[14.0, 14.4], 1)
+        .await
+        .unwrap();
+    assert_eq!(found[0].0, moved.id);
+    assert!(db
+        .nearest_node_ids(&entity, "position", metric, &[1.0], 1)
+        .await
+        .is_err());
+
+    let far = create_place(100.0, 100.0);
+    let tx_far = far.clone();
+    db.transaction(|tx| async move { tx.insert_node(&tx_far).await })
+        .await
+        .unwrap();
+    let query = db
+        .query()
+        .nearest("position", &[90.0, 90.0], 1)
+        .build()
+        .unwrap();
+    assert_eq!(query.exec::<Place>().await.unwrap(), vec![far.clone()]);
+
+    let mut edge = Edge::new("near");
+    for place in [&places[0], &places[224], &far] {
+        edge.link(&closest, place, Data::None);
+        db.insert_edge(edge.item.as_ref().unwrap()).await.unwrap();
+    }
+    let query = db
+        .query()
+        .by_edge_from(&closest.id)
+        .nearest("position", &[13.0, 13.0], 2)
+        .build()
+        .unwrap();
+    let found = query.exec::<Place>().await.unwrap();
+    assert_eq!(found, vec![places[224].clone(), places[0].clone()]);
+}
+
+#[tokio::test]
+async fn query_within_geo_index() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+
+    let depots = [
+        create_depot("center", 48.8566, 2.3522),
+        create_depot("east", 48.8566, 2.4500),
+        create_depot("versailles", 48.8049, 2.1204),
+        create_depot("lyon", 45.7640, 4.8357),
+        create_depot("fiji", -17.7134, 178.0650),
+        create_depot("samoa", -13.7590, -172.1046),
+    ];
+    db.insert_nodes(&depots).await.unwrap();
+
+    let paris = GeoPoint::new(48.8566, 2.3522);
+    let names = |found: Vec<Depot>| {
+        let mut names: Vec<_> = found.into_iter().map(|depot| depot.name).collect();
+        names.sort();
+        names
+    };
+    let query = db
+        .query()
+        .within_radius("location", paris, 5_000.0)
+        .build()
+        .unwrap();
+    assert_eq!(names(query.exec().await.unwrap()), ["center"]);
+    let query = db
+        .query()
+        .within_radius("location", paris, 20_000.0)
+        .build()
+        .unwrap();
+    assert_eq!(
+        names(query.exec().await.unwrap()),
+        ["center", "east", "versailles"]
+    );
+
+    let query = db
+        .query()
+        .within_bbox(
+            "location",
+            GeoPoint::new(45.0, 2.2),
+            GeoPoint::new(49.0, 5.0),
+        )
+        .build()
+        .unwrap();
+    assert_eq!(
+        names(query.exec().await.unwrap()),
+        ["center", "east", "lyon"]
+    );
+    let query = db
+        .query()
+        .within_bbox(
+            "location",
+            GeoPoint::new(-20.0, 170.0),
+            GeoPoint::new(-10.0, -170.0),
+        )
+        .build()
+        .unwrap();
+    assert_eq!(names(query.exec().await.unwrap()), ["fiji", "samoa"]);
+    let fiji = GeoPoint::new(-17.7134, 178.0650);
+    let query = db
+        .query()
+        .within_radius("location", fiji, 1_200_000.0)
+        .build()
+        .unwrap();
+    assert_eq!(names(query.exec().await.unwrap()), ["fiji", "samoa"]);
+
+    let mut moved = depots[1].clone();
+    moved.location = GeoPoint::new(45.7500, 4.8500);
+    db.update_node(&moved).await.unwrap();
+    db.remove_node(&depots[2]).await.unwrap();
+    let query = db
+        .query()
+        .within_radius("location", paris, 20_000.0)
+        .build()
+        .unwrap();
+    assert_eq!(names(query.exec().await.unwrap()), ["center"]);
+    let found = db
+        .get_node_ids_by_geo(
+            &Depot::entity_name(),
+            "location",
+            &GeoArea::Radius {
+                center: GeoPoint::new(45.7640, 4.8357),
+                meters: 5_000.0,
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(found.len(), 2);
+    assert!(found.contains(&moved.id));
+}
+
+#[tokio::test]
+async fn query_within_tuple_locations() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+
+    let stores: Vec<_> = (0..10)
+        .map(|i| {
+            let lat = 40.0 + i as f64 * 0.01;
+            Store::new(Store {
+                id: NodeID::new(),
+                name: format!("store {}", i),
+                location: (lat, -74.0),
+                entrance: (lat, -74.0005),
+            })
+        })
+        .collect();
+    db.insert_nodes(&stores).await.unwrap();
+
+    let center = GeoPoint::new(40.0, -74.0);
+    let query = db
+        .query()
+        .within_radius("location", center, 2_500.0)
+        .build()
+        .unwrap();
+    let found: Vec<Store> = query.exec().await.unwrap();
+    assert_eq!(found.len(), 3);
+    assert!(found.iter().all(|store| store.location.0 <= 40.02));
+
+    let query = db
+        .query()
+        .within_radius("entrance", center, 2_500.0)
+        .build()
+        .unwrap();
+    let found: Vec<Store> = query.exec().await.unwrap();
+    assert_eq!(found.len(), 3);
+}
+
+#[tokio::test]
+async fn special_indexes_skip_equality_keys() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+
+    let article = Article::new(Article {
+        id: NodeID::new(),
+        title: "Graph databases".to_string(),
+        body: "Nodes and edges".to_string(),
+    });
+    let place = create_place(1.0, 2.0);
+    let depot = create_depot("Paris", 48.8566, 2.3522);
+    db.insert_node(&article).await.unwrap();
+    db.insert_node(&place).await.unwrap();
+    db.insert_node(&depot).await.unwrap();
+
+    let stored = [
+        (
+            Article::entity_name(),
+            "title",
+            Value::from(article.title.clone()),
+        ),
+        (
+            Place::entity_name(),
+            "position",
+            Value::from(place.position.clone()),
+        ),
+        (
+            Depot::entity_name(),
+            "location",
+            Value::from_serialize(&depot.location).unwrap(),
+        ),
+    ];
+    for (entity, field, value) in &stored {
+        let ids = db.get_node_ids_by_index(entity, field, value).await;
+        assert!(ids.unwrap().is_empty());
+    }
+
+    let query = db
+        .query()
+        .by_index("title", "Graph databases")
+        .build()
+        .unwrap();
+    let plan = query.explain::<Article>().await.unwrap();
+    assert_eq!(
+        accesses(&plan),
+        [(Access::EntityScan, 1), (Access::Filter, 1)]
+    );
+    assert_eq!(query.exec::<Article>().await.unwrap(), vec![article]);
+    let query = db
+        .query()
+        .by_index("position", place.position.clone())
+        .build()
+        .unwrap();
+    assert_eq!(query.exec::<Place>().await.unwrap(), vec![place]);
+}
+
+#[tokio::test]
+async fn query_short_path() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+
+    let cities: Vec<_> = ["a", "b", "c", "d", "e"]
+        .into_iter()
+        .map(|name| {
+            City::new(City {
+                id: NodeID::new(),
+                name: name.to_string(),
+            })
+        })
+        .collect();
+    db.insert_nodes(&cities).await.unwrap();
+    let [a, b, c, d, e] = [0, 1, 2, 3, 4].map(|i| &cities[i]);
+    let routes = [
+        ("road", a, b, 10),
+        ("road", b, d, 10),
+        ("road", a, c, 1),
+        ("road", c, e, 1),
+        ("road", e, d, 1),
+        ("ferry", a, d, 50),
+    ];
+    for (label, from, to, km) in routes {
+        let mut edge = Edge::new(label);
+        edge.link(from, to, Road::new(Road { km }));
+        db.insert_edge(edge.item.as_ref().unwrap()).await.unwrap();
+    }
+
+    let names =
+        |found: Vec<City>| -> Vec<String> { found.into_iter().map(|city| city.name).collect() };
+    let query = db.query().short_path(&a.id, &d.id).build().unwrap();
+    assert_eq!(names(query.exec().await.unwrap()), ["a", "d"]);
+    let path = query.path().await.unwrap().unwrap();
+    assert_eq!(path.nodes, vec![a.id, d.id]);
+    assert_eq!(path.edges[0].label, "ferry");
+    assert_eq!(path.cost, 1.0);
+
+    let roads = PathOptions::default().labels(&["road"]);
+    let query = db
+        .query()
+        .short_path_with(&a.id, &d.id, roads.clone())
+        .build()
+        .unwrap();
+    assert_eq!(names(query.exec().await.unwrap()), ["a", "b", "d"]);
+
+    let km = |data: &Data| Road::get(data).ok().map(|road| road.km as f64);
+    let query = db
+        .query()
+        .short_path_with(&a.id, &d.id, PathOptions::default().weight(km))
+        .build()
+        .unwrap();
+    let path = query.path().await.unwrap().unwrap();
+    assert_eq!(path.nodes, vec![a.id, c.id, e.id, d.id]);
+    assert_eq!(path.cost, 3.0);
+    let edges: Vec<_> = path.edges.iter().map(|edge| (edge.from, edge.to)).collect();
+    assert_eq!(edges, [(a.id, c.id), (c.id, e.id), (e.id, d.id)]);
+
+    let bounded = roads.clone().weight(km).max_depth(2);
+    let query = db
+        .query()
+        .short_path_with(&a.id, &d.id, bounded)
+        .build()
+        .unwrap();
+    let path = query.path().await.unwrap().unwrap();
+    assert_eq!(path.nodes, vec![a.id, b.id, d.id]);
+    assert_eq!(path.cost, 20.0);
+
+    let guided = roads.clone().weight(km).heuristic(|_| 0.5);
+    let query = db
+        .query()
+        .short_path_with(&a.id, &d.id, guided)
+        .build()
+        .unwrap();
+    assert_eq!(names(query.exec().await.unwrap()), ["a", "c", "e", "d"]);
+
+    let query = db.query().short_path(&d.id, &a.id).build().unwrap();
+    assert_eq!(query.path().await.unwrap(), None);
+    assert!(query.exec::<City>().await.unwrap().is_empty());
+    let query = db
+        .query()
+        .short_path_with(&a.id, &d.id, roads.max_depth(1))
+        .build()
+        .unwrap();
+    assert_eq!(query.path().await.unwrap(), None);
+
+    let query = db.query().by_id(&a.id).build().unwrap();
+    assert!(query.path().await.is_err());
+    assert!(db
+        .query()
+        .short_path(&a.id, &d.id)
+        .short_path(&d.id, &a.id)
+        .build()
+        .is_err());
+}
+
+#[tokio::test]
+async fn query_short_path_with_indexes() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+
+    let users: Vec<_> = [
+        ("Ann", 40),
+        ("Bob", 10),
+        ("Ann", 30),
+        ("Ann", 20),
+        ("Ann", 50),
+    ]
+    .into_iter()
+    .map(|(name, age)| create_user(name, age))
+    .collect();
+    db.insert_nodes(&users).await.unwrap();
+    for pair in users.windows(2) {
+        let mut edge = Edge::new("knows");
+        edge.link(&pair[0], &pair[1], Data::None);
+        db.insert_edge(edge.item.as_ref().unwrap()).await.unwrap();
+    }
+    let (first, last) = (&users[0], &users[4]);
+    let ages = |found: Vec<User>| -> Vec<u32> { found.into_iter().map(|user| user.age).collect() };
+
+    let query = db
+        .query()
+        .short_path(&first.id, &last.id)
+        .by_index_range("age", 15..)
+        .build()
+        .unwrap();
+    assert_eq!(ages(query.exec().await.unwrap()), [40, 30, 20, 50]);
+    let query = db
+        .query()
+        .by_index("name", "Ann")
+        .short_path(&first.id, &last.id)
+        .build()
+        .unwrap();
+    assert_eq!(ages(query.exec().await.unwrap()), [40, 30, 20, 50]);
+    let query = db
+        .query()
+        .by_index("name", "Ann")
+        .by_index_range("age", ..45)
+        .short_path(&first.id, &last.id)
+        .build()
+        .unwrap();
+    assert_eq!(ages(query.exec().await.unwrap()), [40, 30, 20]);
+}
+
+#[tokio::test]
+async fn query_plan_orders_by_selectivity() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+
+    let john = create_user("John", 20);
+    db.insert_node(&john).await.unwrap();
+    for age in 0..39 {
+        db.insert_node(&create_user("Jane", age)).await.unwrap();
+    }
+    let cars: Vec<_> = ["Mustang", "Civic", "Mustang"]
+        .into_iter()
+        .map(|model| {
+            Car::new(Car {
+                id: NodeID::new(),
+                name: "Ford".to_string(),
+                model: model.to_string(),
+            })
+        })
+        .collect();
+    db.insert_nodes(&cars).await.unwrap();
+    let mut owns = Edge::new("user_owns");
+    for car in &cars[..2] {
+        owns.link(&john, car, Data::None);
+        db.insert_edge(owns.item.as_ref().unwrap()).await.unwrap();
+    }
+
+    let query = db
+        .query()
+        .filter_by_prop("age", 20)
+        .by_index_range("age", 18..)
+        .by_index("name", "John")
+        .by_id(&john.id)
+        .build()
+        .unwrap();
+    let plan = query.explain::<User>().await.unwrap();
+    assert_eq!(
+        accesses(&plan),
+        [
+            (Access::Id, 1),
+            (Access::Residual, 1),
+            (Access::Residual, 1),
+            (Access::Filter, 1)
+        ]
+    );
+    assert_eq!(plan.steps[0].operation, Some(QueryOperation::ByID(john.id)));
+    assert_eq!(plan.estimate(), 1);
+    assert!(plan.to_string().starts_with("1. Id ByID("));
+    assert_eq!(query.exec::<User>().await.unwrap(), vec![john.clone()]);
+
+    let query = db
+        .query()
+        .by_index_range("age", 18..)
+        .by_index("name", "Jane")
+        .build()
+        .unwrap();
+    let plan = query.explain::<User>().await.unwrap();
+    assert_eq!(
+        accesses(&plan),
+        [(Access::Index, 4), (Access::RangeIndex, 4)]
+    );
+    assert_eq!(query.count::<User>().await.unwrap(), 21);
+
+    let query = db
+        .query()
+        .filter_by_prop("model", "Mustang")
+        .by_edge_from(&john.id)
+        .build()
+        .unwrap();
+    let plan = query.explain::<Car>().await.unwrap();
+    assert_eq!(
+        accesses(&plan),
+        [(Access::Adjacency, 2), (Access::Filter, 2)]
+    );
+    assert_eq!(query.exec::<Car>().await.unwrap(), vec![cars[0].clone()]);
+
+    let mut query = db.query().filter_by_prop("age", 2).build().unwrap();
+    let plan = query.sort_by_prop("age").explain::<User>().await.unwrap();
+    assert_eq!(
+        accesses(&plan),
+        [
+            (Access::EntityScan, 40),
+            (Access::Filter, 40),
+            (Access::IndexOrder("age".to_string()), 40)
+        ]
+    );
+    let plan = query.sort_by_prop("name").explain::<User>().await.unwrap();
+    assert_eq!(
+        plan.steps.last().unwrap().access,
+        Access::Sort("name".to_string())
+    );
+    assert_eq!(query.count::<User>().await.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn query_exec_stream() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+
+    let users: Vec<_> = (0..300)
+        .map(|age| create_user(if age % 2 == 0 { "Even" } else { "Odd" }, age))
+        .collect();
+    db.insert_nodes(&users).await.unwrap();
+    db.insert_node(&Car::new(Car {
+        id: NodeID::new(),
+        name: "Ford".to_string(),
+        model: "Mustang".to_string(),
+    }))
+    .await
+    .unwrap();
+
+    let query = db.query().build().unwrap();
+    let mut stream = query.exec_stream::<User>();
+    let mut count = 0;
+    while let Some(user) = stream.try_next().await.unwrap() {
+        assert!(user.age < 300);
+        count += 1;
+    }
+    assert_eq!(count, 300);
+
+    let mut query = db.query().by_index("name", "Odd").build().unwrap();
+    let ages: Vec<u32> = query
+        .sort_by_prop("age")
+        .skip(130)
+        .limit(5)
+        .exec_stream::<User>()
+        .map_ok(|user| user.age)
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(ages, [261, 263, 265, 267, 269]);
+
+    let mut query = db.query().by_index_range("age", 290..).build().unwrap();
+    let names: Vec<String> = query
+        .sort_by_prop("name")
+        .limit(3)
+        .exec_stream::<User>()
+        .map_ok(|user| user.name)
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(names, ["Even", "Even", "Even"]);
+
+    let query = db.query().by_edge_label("user_owns").build().unwrap();
+    let stream = query.exec_stream::<User>();
+    assert!(stream.try_collect::<Vec<_>>().await.unwrap().is_empty());
+    let cars = db.query().build().unwrap();
+    assert_eq!(cars.exec_stream::<Car>().count().await, 1);
+}
+
+#[tokio::test]
+async fn query_aggregate_groups() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+
+    let users = [("John", 40), ("Jane", 18), ("John", 20), ("Mary", 16)]
+        .map(|(name, age)| create_user(name, age));
+    db.insert_nodes(&users).await.unwrap();
+    let cars: Vec<_> = ["Mustang", "Civic", "Mustang"]
+        .into_iter()
+        .map(|model| {
+            Car::new(Car {
+                id: NodeID::new(),
+                name: "Ford".to_string(),
+                model: model.to_string(),
+            })
+        })
+        .collect();
+    db.insert_nodes(&cars).await.unwrap();
+    let mut owns = Edge::new("user_owns");
+    for (user, car) in [(0, 0), (0, 1), (3, 2)] {
+        owns.link(&users[user], &cars[car], Data::None);
+        db.insert_edge(owns.item.as_ref().unwrap()).await.unwrap();
+    }
+
+    let aggregates = [
+        Aggregate::Count,
+        Aggregate::sum("age"),
+        Aggregate::avg("age"),
+        Aggregate::min("age"),
+        Aggregate::max("name"),
+        Aggregate::distinct("name"),
+    ];
+    let query = db.query().build().unwrap();
+    let rows = query.aggregate::<User>(&aggregates).await.unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!((&rows[0].group, rows[0].count), (&Value::Null, 4));
+    assert_eq!(
+        rows[0].values,
+        [
+            Value::UInt(4),
+            Value::Int(94),
+            Value::Float(23.5),
+            Value::UInt(16),
+            "Mary".into(),
+            Value::List(vec!["Jane".into(), "John".into(), "Mary".into()])
+        ]
+    );
+
+    let mut query = db.query().by_index_range("age", 18..).build().unwrap();
+    let rows = query
+        .group_by("name")
+        .aggregate::<User>(&[Aggregate::avg("age")])
+        .await
+        .unwrap();
+    let groups: Vec<_> = rows
+        .iter()
+        .map(|row| (row.group.clone(), row.count, row.values[0].clone()))
+        .collect();
+    assert_eq!(
+        groups,
+        [
+            ("Jane".into(), 1, Value::Float(18.0)),
+            ("John".into(), 2, Value::Float(30.0))
+        ]
+    );
+    let rows = query.aggregate::<Car>(&[Aggregate::Count]).await.unwrap();
+    assert!(rows.is_empty());
+    let rows = query.aggregate::<User>(&[Aggregate::sum("name")]).await;
+    assert!(matches!(rows, Err(DBError::QueryError { .. })));
+
+    let mut query = db.query().build().unwrap();
+    let rows = query
+        .group_by("from")
+        .aggregate_edges::<User>("user_owns", &[Aggregate::distinct("to")])
+        .await
+        .unwrap();
+    let owned: Vec<_> = rows
+        .iter()
+        .map(|row| (row.group.clone(), row.count))
+        .collect();
+    let mut expected = vec![(users[0].id.into(), 2), (users[3].id.into(), 1)];
+    expected.sort();
+    assert_eq!(owned, expected);
+
+    let cities: Vec<_> = ["a", "b", "c"]
+        .into_iter()
+        .map(|name| {
+            City::new(City {
+                id: NodeID::new(),
+                name: name.to_string(),
+            })
+        })
+        .collect();
+    db.insert_nodes(&cities).await.unwrap();
+    for (from, to, km) in [(0, 1, 10), (0, 2, 4), (1, 2, 7)] {
+        let mut road = Edge::new("road");
+        road.link(&cities[from], &cities[to], Road::new(Road { km }));
+        db.insert_edge(road.item.as_ref().unwrap()).await.unwrap();
+    }
+    let rows = db
+        .query()
+        .build()
+        .unwrap()
+        .aggregate_edges::<City>("road", &[Aggregate::sum("km"), Aggregate::max("km")])
+        .await
+        .unwrap();
+    assert_eq!(rows[0].count, 3);
+    assert_eq!(rows[0].values, [Value::Int(21), Value::UInt(10)]);
+}
+
+#[tokio::test]
+async fn query_paginates_with_cursors() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+
+    let users: Vec<_> = [
+        ("John", 40),
+        ("Jane", 18),
+        ("Mary", 16),
+        ("Paul", 40),
+        ("Anna", 18),
+        ("John", 25),
+        ("Mark", 40),
+        ("Jane", 33),
+        ("Lisa", 18),
+        ("John", 52),
+    ]
+    .into_iter()
+    .map(|(name, age)| create_user(name, age))
+    .collect();
+    db.insert_nodes(&users).await.unwrap();
+
+    async fn pages<D: DB + Sync>(query: &mut QueryExecutor<'_, D>) -> Vec<Vec<User>> {
+        let mut pages = Vec::new();
+        loop {
+            let page = query.page::<User>().await.unwrap();
+            pages.push(page.nodes);
+            let Some(cursor) = page.next else {
+                return pages;
+            };
+            let cursor: Cursor = cursor.to_string().parse().unwrap();
+            query.after(&cursor);
+        }
+    }
+
+    let mut query = db.query().build().unwrap();
+    let by_age = query.sort_by_prop("age").exec::<User>().await.unwrap();
+    let found = pages(query.limit(3)).await;
+    assert_eq!(found.iter().map(Vec::len).collect::<Vec<_>>(), [3, 3, 3, 1]);
+    assert_eq!(found.concat(), by_age);
+
+    let mut query = db.query().build().unwrap();
+    let by_name = query.sort_by_prop("name").exec::<User>().await.unwrap();
+    assert_eq!(pages(query.limit(4)).await.concat(), by_name);
+
+    let mut query = db.query().build().unwrap();
+    let found = pages(query.limit(4)).await.concat();
+    assert_eq!(found.len(), 10);
+    assert!(users.iter().all(|user| found.contains(user)));
+
+    let mut query = db.query().by_index("name", "John").build().unwrap();
+    let found = pages(query.limit(1)).await;
+    let mut ids: Vec<_> = found.concat().iter().map(|user| user.id.0).collect();
+    assert_eq!(ids.len(), 3);
+    assert!(ids.is_sorted());
+    ids.dedup();
+    assert_eq!(ids.len(), 3);
+
+    let mut query = db.query().build().unwrap();
+    query.sort_by_prop("age").limit(4);
+    let first = query.page::<User>().await.unwrap();
+    let cursor = first.next.unwrap();
+    db.remove_node(first.nodes.last().unwrap()).await.unwrap();
+    let second = query.after(&cursor).page::<User>().await.unwrap();
+    assert_eq!(
+        second.nodes,
+        sorted_after(&by_age, first.nodes.last().unwrap(), 4)
+    );
+
+    let mut other = db.query().by_index("name", "Jane").build().unwrap();
+    let page = other.after(&cursor).page::<User>().await;
+    assert!(matches!(page, Err(DBError::QueryError { .. })));
+    assert!("zz".parse::<Cursor>().is_err());
+
+    let mut by_name = db.query().build().unwrap();
+    by_name.sort_by_prop("name");
+    let mut adult_by_age = db.query().by_index_range("age", 18..).build().unwrap();
+    adult_by_age.sort_by_prop("age");
+    for mut other in [by_name, adult_by_age] {
+        match other.limit(4).after(&cursor).page::<User>().await {
+            Err(DBError::QueryError { error }) => {
+                assert_eq!(error, "cursor was made by another query")
+            }
+            other => panic!("paged with a foreign cursor: {:?}", other),
+        }
+    }
+    let mut other = db.query().build().unwrap();
+    other.sort_by_prop("age").limit(4);
+    assert_eq!(other.after(&cursor).page::<User>().await.unwrap(), second);
+
+    let mut adults = db
+        .query()
+        .filter(|user: &User| user.age >= 18)
+        .build()
+        .unwrap();
+    adults.sort_by_prop("age").limit(4);
+    let page = adults.page::<User>().await;
+    assert!(matches!(page, Err(DBError::QueryError { .. })));
+}
+
+#[tokio::test]
+async fn query_paginates_over_optional_range_index() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+
+    let tasks: Vec<_> = [None, Some(3), None, Some(1), Some(2)]
+        .into_iter()
+        .map(|due| {
+            Task::new(Task {
+                id: NodeID::new(),
+                due,
+            })
+        })
+        .collect();
+    db.insert_nodes(&tasks).await.unwrap();
+
+    let mut query = db.query().build().unwrap();
+    query.sort_by_prop("due");
+    let sorted = query.exec::<Task>().await.unwrap();
+    let dues: Vec<_> = sorted.iter().map(|task| task.due).collect();
+    assert_eq!(dues, [None, None, Some(1), Some(2), Some(3)]);
+
+    // The first page ends on a null, and the next ones go on with numbers.
+    query.limit(2);
+    let mut found = Vec::new();
+    loop {
+        let page = query.page::<Task>().await.unwrap();
+        found.extend(page.nodes);
+        match page.next {
+            Some(cursor) => query.after(&cursor),
+            None => break,
+        };
+    }
+    assert_eq!(found, sorted);
+}
+
+fn sorted_after(sorted: &[User], last: &User, size: usize) -> Vec<User> {
+    let position = sorted.iter().position(|user| user == last).unwrap();
+    sorted[position + 1..].iter().take(size).cloned().collect()
+}
+
+#[tokio::test]
+async fn query_filter_combinators() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+
+    for (name, age) in [("John", 40), ("Jane", 18), ("Peter", 30), ("Mary", 16)] {
+        db.insert_node(&create_user(name, age)).await.unwrap();
+    }
+    let names = |users: Vec<User>| -> Vec<String> {
+        let mut names: Vec<_> = users.into_iter().map(|user| user.name).collect();
+        names.sort();
+        names
+    };
+
+    let mut query = db
+        .query()
+        .filter(|user: &User| user.age >= 18)
+        .build()
+        .unwrap();
+    assert_eq!(query.count::<User>().await.unwrap(), 3);
+    let users = query
+        .sort_by_prop("age")
+        .skip(1)
+        .limit(1)
+        .exec::<User>()
+        .await
+        .unwrap();
+    assert_eq!(names(users), ["Peter"]);
+
+    let adult = Predicate::new(|user: &User| user.age >= 18);
+    let starts_with_j = Predicate::new(|user: &User| user.name.starts_with('J'));
+    let query = db
+        .query()
+        .filter_with(&adult.and(&!starts_with_j.clone()))
+        .build()
+        .unwrap();
+    assert_eq!(names(query.exec().await.unwrap()), ["Peter"]);
+
+    let mut query = db
+        .query()
+        .by_index_range("age", ..35)
+        .filter_with(&starts_with_j.or(&Predicate::new(|user: &User| user.age < 18)))
+        .build()
+        .unwrap();
+    assert_eq!(names(query.exec().await.unwrap()), ["Jane", "Mary"]);
+    query.filter(|user: &User| user.name.len() == 4);
+    assert_eq!(names(query.exec().await.unwrap()), ["Jane", "Mary"]);
+    query.filter(|user: &User| user.age > 16);
+    assert_eq!(names(query.exec().await.unwrap()), ["Jane"]);
+
+    let mustang = Predicate::new(|car: &Car| car.model == "Mustang");
+    let query = db.query().filter_with(&!mustang).build().unwrap();
+    assert!(matches!(
+        query.exec::<User>().await,
+        Err(DBError::QueryError { .. })
+    ));
+}
+
+#[tokio::test]
+async fn query_update_and_delete() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+
+    let users: Vec<_> = [("John", 40), ("Jane", 18), ("Peter", 70), ("Mary", 16)]
+        .into_iter()
+        .map(|(name, age)| create_user(name, age))
+        .collect();
+    db.insert_nodes(&users).await.unwrap();
+    let car = Car::new(Car {
+        id: NodeID::new(),
+        name: "Ford".to_string(),
+        model: "Mustang".to_string(),
+    });
+    db.insert_node(&car).await.unwrap();
+    let mut owns = Edge::new("user_owns");
+    for user in &users[..3] {
+        owns.link(user, &car, Data::None);
+        db.insert_edge(owns.item.as_ref().unwrap()).await.unwrap();
+    }
+
+    let query = db.query().by_index_range("age", 18..).build().unwrap();
+    let updated = query
+        .update(|user: &mut User| user.name = format!("Adult {}", user.name))
+        .await
+        .unwrap();
+    assert_eq!(updated, 3);
+    let query = db.query().by_index("name", "Adult Jane").build().unwrap();
+    assert_eq!(query.exec::<User>().await.unwrap()[0].id, users[1].id);
+    let query = db.query().by_index("name", "Jane").build().unwrap();
+    assert_eq!(query.count::<User>().await.unwrap(), 0);
+
+    let query = db.query().by_id(&users[3].id).build().unwrap();
+    let changed = query
+        .update(|user: &mut User| user.id = NodeID::new())
+        .await;
+    assert!(matches!(changed, Err(DBError::QueryError { .. })));
+    assert_eq!(db.get_node::<User>(users[3].id).await.unwrap(), users[3]);
+
+    let query = db.query().by_index_range("age", 30..).build().unwrap();
+    assert_eq!(query.delete::<User>().await.unwrap(), 2);
+    assert_eq!(query.count::<User>().await.unwrap(), 0);
+    let owners: Vec<EdgeItem> = db.in_edges(car.id, None).try_collect().await.unwrap();
+    assert_eq!(owners.len(), 1);
+    assert_eq!(owners[0].from, users[1].id);
+    assert!(db.get_edge(users[0].id, car.id).await.is_err());
+    let query = db.query().by_edge_label("user_owns").build().unwrap();
+    assert_eq!(query.count::<User>().await.unwrap(), 1);
+    assert_eq!(
+        db.query().build().unwrap().count::<User>().await.unwrap(),
+        2
+    );
+}
+
+#[tokio::test]
+async fn query_update_fails_on_concurrent_write() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+
+    let users: Vec<_> = [("John", 40), ("Jane", 18)]
+        .into_iter()
+        .map(|(name, age)| create_user(name, age))
+        .collect();
+    db.insert_nodes(&users).await.unwrap();
+
+    // Another writer renames John while the update runs.
+    let renamed = User {
+        name: "Johnny".to_string(),
+        ..users[0].clone()
+    };
+    let query = db.query().by_index_range("age", 30..).build().unwrap();
+    let updated = query
+        .update(|user: &mut User| {
+            futures::executor::block_on(db.insert_node(&renamed)).unwrap();
+            user.age += 1;
+        })
+        .await;
+    assert!(matches!(updated, Err(DBError::TransactionError { .. })));
+    assert_eq!(db.get_node::<User>(users[0].id).await.unwrap(), renamed);
+}
+
+#[tokio::test]
+async fn query_select_projection() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+
+    let users: Vec<_> = [("John", 40), ("Jane", 18), ("Mary", 16)]
+        .into_iter()
+        .map(|(name, age)| create_user(name, age))
+        .collect();
+    db.insert_nodes(&users).await.unwrap();
+    let car = Car::new(Car {
+        id: NodeID::new(),
+        name: "Ford".to_string(),
+        model: "Mustang".to_string(),
+    });
+    db.insert_node(&car).await.unwrap();
+    let mut owns = Edge::new("user_owns");
+    owns.link(&users[0], &car, Data::None);
+    db.insert_edge(owns.item.as_ref().unwrap()).await.unwrap();
+
+    let row = |name: &str, other: (&str, Value)| {
+        Value::Map(
+            [
+                ("name".to_string(), name.into()),
+                (other.0.to_string(), other.1),
+            ]
+            .into(),
+        )
+    };
+
+    let mut query = db.query().by_index_range("age", 17..).build().unwrap();
+    let rows = query
+        .sort_by_prop("age")
+        .select(&["name", "age"])
+        .from("User")
+        .exec()
+        .await
+        .unwrap();
+    assert_eq!(
+        rows,
+        [
+            row("Jane", ("age", 18.into())),
+            row("John", ("age", 40.into()))
+        ]
+    );
+
+    let mut query = db.query().by_edge_label("user_owns").build().unwrap();
+    let rows = query
+        .sort_by_prop("name")
+        .select(&["name", "model"])
+        .from("User")
+        .from("Car")
+        .exec()
+        .await
+        .unwrap();
+    assert_eq!(
+        rows,
+        [
+            row("Ford", ("model", "Mustang".into())),
+            row("John", ("model", Value::Null))
+        ]
+    );
+    let rows = query
+        .limit(1)
+        .select(&["id"])
+        .from("Car")
+        .exec()
+        .await
+        .unwrap();
+    assert_eq!(rows[0].get("id"), Some(&car.id.into()));
+
+    assert!(query.select(&["name"]).exec().await.is_err());
+    assert!(query.select(&["name"]).from("Boat").exec().await.is_err());
+}
+
+#[tokio::test]
+async fn query_select_pages() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+
+    let users: Vec<_> = [("John", 40), ("Jane", 18), ("Mary", 18), ("Anna", 30)]
+        .into_iter()
+        .map(|(name, age)| create_user(name, age))
+        .collect();
+    db.insert_nodes(&users).await.unwrap();
+    let cars: Vec<_> = ["Ford", "Audi"]
+        .into_iter()
+        .map(|name| {
+            Car::new(Car {
+                id: NodeID::new(),
+                name: name.to_string(),
+                model: String::new(),
+            })
+        })
+        .collect();
+    db.insert_nodes(&cars).await.unwrap();
+    let names = |rows: &[Value]| -> Vec<Value> {
+        rows.iter()
+            .map(|row| row.get("name").cloned().unwrap())
+            .collect()
+    };
+
+    // Sorted on a range index, ties broken by id, from the entity steps.
+    let mut ties = [&users[1], &users[2]];
+    ties.sort_by_key(|user| user.id.0);
+    let mut query = db
+        .query()
+        .by_entity_name("User".to_string())
+        .build()
+        .unwrap();
+    let rows = query
+        .sort_by_prop("age")
+        .limit(3)
+        .select(&["name"])
+        .exec()
+        .await
+        .unwrap();
+    let expected: Vec<Value> = [ties[0].name.as_str(), ties[1].name.as_str(), "Anna"]
+        .into_iter()
+        .map(Into::into)
+        .collect();
+    assert_eq!(names(&rows), expected);
+
+    // Pages go through every entity, one after the other without sort.
+    let mut query = db.query().build().unwrap();
+    query.limit(4);
+    let mut seen = Vec::new();
+    loop {
+        let page = query
+            .select(&["name"])
+            .from("User")
+            .from("Car")
+            .page()
+            .await
+            .unwrap();
+        seen.extend(names(&page.nodes));
+        match page.next {
+            Some(cursor) => query.after(&cursor),
+            None => break,
+        };
+    }
+    assert_eq!(seen.len(), 6);
+    assert!(seen.contains(&"Audi".into()) && seen.contains(&"Mary".into()));
+
+    // Sorted pages are merged across the entities.
+    let mut query = db.query().build().unwrap();
+    query.sort_by_prop("name").limit(4);
+    let page = query
+        .select(&["name"])
+        .from("Car")
+        .from("User")
+        .page()
+        .await
+        .unwrap();
+    let cursor = page.next.unwrap();
+    let rest = query
+        .after(&cursor)
+        .select(&["name"])
+        .from("Car")
+        .from("User")
+        .page()
+        .await
+        .unwrap();
+    assert!(rest.next.is_none());
+    let expected: Vec<Value> = ["Anna", "Audi", "Ford", "Jane", "John", "Mary"]
+        .into_iter()
+        .map(Into::into)
+        .collect();
+    assert_eq!([names(&page.nodes), names(&rest.nodes)].concat(), expected);
+}
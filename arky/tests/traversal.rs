@@ -0,0 +1,297 @@
+use arky::edge::prelude::*;
+use arky::inst::prelude::*;
+use arky::node::prelude::*;
+use tempdir::TempDir;
+
+#[schema(Node)]
+struct User {
+    pub id: NodeID,
+    pub name: String,
+}
+
+#[schema(Node)]
+struct Car {
+    pub id: NodeID,
+    pub model: String,
+}
+
+fn create_storage() -> RocksDB {
+    let dir = TempDir::new("arky").unwrap();
+    let db_path = dir.path().join("test_db").to_str().unwrap().to_string();
+    RocksDB::new(RocksDBConfig {
+        path: db_path,
+        set_error_if_exists: false,
+        ..Default::default()
+    })
+}
+
+fn names(users: Vec<User>) -> Vec<String> {
+    let mut names: Vec<String> = users.into_iter().map(|user| user.name).collect();
+    names.sort();
+    names
+}
+
+fn models(cars: Vec<Car>) -> Vec<String> {
+    let mut models: Vec<String> = cars.into_iter().map(|car| car.model).collect();
+    models.sort();
+    models
+}
+
+async fn create_users<D: DB>(db: &D, names: &[&str]) -> Vec<User> {
+    let users: Vec<_> = names
+        .iter()
+        .map(|name| {
+            User::new(User {
+                id: NodeID::new(),
+                name: name.to_string(),
+            })
+        })
+        .collect();
+    db.insert_nodes(&users).await.unwrap();
+    users
+}
+
+async fn link<D: DB, F: Node, T: Node>(db: &D, label: &str, pairs: &[(&F, &T)]) {
+    let mut edge = Edge::new(label);
+    for (from, to) in pairs {
+        edge.link(*from, *to, Data::None);
+        db.insert_edge(edge.item.as_ref().unwrap()).await.unwrap();
+    }
+}
+
+/// alice owns both cars, bob and carol drive the Mustang, dave and alice
+/// drive the Civic.
+async fn create_garage<D: DB>(db: &D) -> (Vec<User>, Vec<Car>) {
+    let users = create_users(db, &["alice", "bob", "carol", "dave"]).await;
+    let cars: Vec<_> = ["Mustang", "Civic"]
+        .into_iter()
+        .map(|model| {
+            Car::new(Car {
+                id: NodeID::new(),
+                model: model.to_string(),
+            })
+        })
+        .collect();
+    db.insert_nodes(&cars).await.unwrap();
+    let [alice, bob, carol, dave] = [0, 1, 2, 3].map(|i| &users[i]);
+    let [mustang, civic] = [0, 1].map(|i| &cars[i]);
+
+    link(db, "user_owns", &[(alice, mustang), (alice, civic)]).await;
+    link(
+        db,
+        "drives",
+        &[
+            (bob, mustang),
+            (carol, mustang),
+            (dave, civic),
+            (alice, civic),
+        ],
+    )
+    .await;
+    (users, cars)
+}
+
+#[tokio::test]
+async fn traverse_out() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+    let (users, _) = create_garage(db).await;
+    let [alice, bob] = [0, 1].map(|i| &users[i]);
+
+    let owned = db.traverse(&alice.id).out("user_owns").exec::<Car>().await;
+    assert_eq!(models(owned.unwrap()), ["Civic", "Mustang"]);
+    let driven = db.traverse(&alice.id).out("drives").exec::<Car>().await;
+    assert_eq!(models(driven.unwrap()), ["Civic"]);
+    let missing = db.traverse(&bob.id).out("user_owns").ids().await.unwrap();
+    assert!(missing.is_empty());
+    let users = db.traverse(&alice.id).out("user_owns").exec::<User>().await;
+    assert!(users.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn traverse_in() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+    let (users, cars) = create_garage(db).await;
+    let [mustang, civic] = [0, 1].map(|i| &cars[i]);
+
+    let drivers = db.traverse(&mustang.id).in_("drives").exec::<User>().await;
+    assert_eq!(names(drivers.unwrap()), ["bob", "carol"]);
+    let owners = db.traverse(&civic.id).in_("user_owns").exec::<User>().await;
+    assert_eq!(names(owners.unwrap()), ["alice"]);
+    let count = db.traverse(&users[0].id).in_("drives").count().await;
+    assert_eq!(count.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn traverse_has() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+    let (users, _) = create_garage(db).await;
+    let alice = &users[0];
+
+    let mustangs = db
+        .traverse(&alice.id)
+        .out("user_owns")
+        .has::<Car>("model", "Mustang")
+        .exec::<Car>()
+        .await
+        .unwrap();
+    assert_eq!(models(mustangs), ["Mustang"]);
+
+    let drivers = db
+        .traverse(&alice.id)
+        .out("user_owns")
+        .has::<Car>("model", "Mustang")
+        .in_("drives")
+        .exec::<User>()
+        .await
+        .unwrap();
+    assert_eq!(names(drivers), ["bob", "carol"]);
+
+    let mut traversal = db.traverse(&alice.id);
+    traversal.out("user_owns").has::<Car>("model", "Beetle");
+    assert_eq!(traversal.count().await.unwrap(), 0);
+    // Nodes of another entity never match, even with the same property.
+    let mut traversal = db.traverse(&alice.id);
+    traversal.out("user_owns").has::<User>("model", "Mustang");
+    assert_eq!(traversal.count().await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn traverse_filter() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+    let (users, _) = create_garage(db).await;
+    let bob = &users[1];
+
+    let owners = db
+        .traverse(&bob.id)
+        .out("drives")
+        .in_("user_owns")
+        .filter::<User>(|user| user.name.starts_with('a'))
+        .exec::<User>()
+        .await
+        .unwrap();
+    assert_eq!(names(owners), ["alice"]);
+    let none = db
+        .traverse(&bob.id)
+        .out("drives")
+        .in_("drives")
+        .filter::<User>(|user| user.name.starts_with('z'))
+        .count()
+        .await;
+    assert_eq!(none.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn traverse_both() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+    let (users, _) = create_garage(db).await;
+    let dave = &users[3];
+
+    let mut traversal = db.traverse(&dave.id);
+    traversal.both("drives").both("drives").dedup();
+    let reached = names(traversal.exec::<User>().await.unwrap());
+    assert_eq!(reached, ["alice", "dave"]);
+}
+
+#[tokio::test]
+async fn traverse_dedup() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+    let (users, _) = create_garage(db).await;
+    let alice = &users[0];
+
+    let mut traversal = db.traverse(&alice.id);
+    traversal.out("user_owns").in_("drives").out("drives");
+    assert_eq!(traversal.count().await.unwrap(), 4);
+    assert_eq!(traversal.dedup().count().await.unwrap(), 2);
+    let cars = models(traversal.exec::<Car>().await.unwrap());
+    assert_eq!(cars, ["Civic", "Mustang"]);
+}
+
+#[tokio::test]
+async fn traverse_dedup_cycle() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+    let users = create_users(db, &["a", "b", "c"]).await;
+    let [a, b, c] = [0, 1, 2].map(|i| &users[i]);
+    link(db, "next", &[(a, b), (b, c), (c, a)]).await;
+
+    // Going around the cycle twice comes back to every node twice.
+    let mut traversal = db.traverse(&a.id);
+    for _ in 0..6 {
+        traversal.both("next");
+    }
+    let total = traversal.count().await.unwrap();
+    assert_eq!(total, 2usize.pow(6));
+    let reached = names(traversal.dedup().exec::<User>().await.unwrap());
+    assert_eq!(reached, ["a", "b", "c"]);
+
+    let mut traversal = db.traverse(&a.id);
+    traversal.out("next").out("next").out("next").dedup();
+    let ids = traversal.ids().await.unwrap();
+    assert_eq!(ids, [a.id]);
+}
+
+#[tokio::test]
+async fn traverse_dedup_self_loop() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+    let users = create_users(db, &["a", "b"]).await;
+    let [a, b] = [0, 1].map(|i| &users[i]);
+    link(db, "knows", &[(a, a), (a, b), (b, a)]).await;
+
+    let mut traversal = db.traverse(&a.id);
+    traversal.out("knows").out("knows");
+    let ids = traversal.ids().await.unwrap();
+    assert_eq!(ids.len(), 3);
+    assert_eq!(ids.iter().filter(|id| **id == a.id).count(), 2);
+    let reached = names(traversal.dedup().exec::<User>().await.unwrap());
+    assert_eq!(reached, ["a", "b"]);
+}
+
+#[tokio::test]
+async fn traverse_limit() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+    let (users, _) = create_garage(db).await;
+    let alice = &users[0];
+
+    let mut traversal = db.traverse(&alice.id);
+    traversal.out("user_owns").in_("drives").out("drives");
+    assert_eq!(traversal.limit(1).count().await.unwrap(), 1);
+    assert!(traversal.exec::<User>().await.unwrap().is_empty());
+
+    let mut traversal = db.traverse(&alice.id);
+    traversal.out("user_owns").in_("drives").limit(10);
+    assert_eq!(traversal.count().await.unwrap(), 4);
+    let mut traversal = db.traverse(&alice.id);
+    traversal.out("user_owns").in_("drives").limit(2);
+    assert_eq!(traversal.count().await.unwrap(), 2);
+    let mut traversal = db.traverse(&alice.id);
+    traversal.out("user_owns").limit(0);
+    assert_eq!(traversal.count().await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn traverse_from_lone_node() {
+    let storage = create_storage();
+    let db = ArkyDB::init(&storage);
+    create_garage(db).await;
+    let lone = create_users(db, &["eve"]).await.remove(0);
+
+    assert_eq!(db.traverse(&lone.id).ids().await.unwrap(), [lone.id]);
+    let mut traversal = db.traverse(&lone.id);
+    traversal.both("drives").dedup().limit(5);
+    assert!(traversal.ids().await.unwrap().is_empty());
+    assert!(traversal.exec::<User>().await.unwrap().is_empty());
+    let mut traversal = db.traverse(&lone.id);
+    traversal.out("drives").in_("drives");
+    assert_eq!(traversal.count().await.unwrap(), 0);
+    let mut traversal = db.traverse(&NodeID::new());
+    traversal.out("drives");
+    assert_eq!(traversal.count().await.unwrap(), 0);
+}
@@ -1,9 +1,14 @@
+use crate::value::Value;
 pub use downcast::TypeMismatch;
 use downcast::{downcast_sync, AnySync};
 use dyn_clone::{clone_trait_object, DynClone};
+use serde::de::{DeserializeOwned, Deserializer};
+use serde::ser::{Error as _, Serializer};
 use serde::{Deserialize, Serialize};
+use std::any::TypeId;
+use std::collections::HashMap;
 use std::fmt;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock, RwLock};
 
 pub trait AnyData: AnySync + fmt::Debug + DynClone + 'static {
     fn eq_as_any(&self, other: &(dyn std::any::Any + 'static)) -> bool;
@@ -24,10 +29,99 @@ where
     }
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+type Encoder = fn(&dyn AnyData) -> Option<Vec<u8>>;
+type Decoder = fn(&[u8]) -> Option<Arc<dyn AnyData>>;
+type Converter = fn(&dyn AnyData) -> Option<Value>;
+
+struct DataType {
+    type_id: TypeId,
+    encode: Encoder,
+    decode: Decoder,
+    to_value: Converter,
+}
+
+#[derive(Default)]
+struct Registry {
+    types: HashMap<String, DataType>,
+    names: HashMap<TypeId, String>,
+}
+
+fn registry() -> &'static RwLock<Registry> {
+    static REGISTRY: OnceLock<RwLock<Registry>> = OnceLock::new();
+    REGISTRY.get_or_init(Default::default)
+}
+
+/// Registers `T` under `name` so `Data` holding it can be written to and read
+/// back from storage. The `schema(EdgeData)` macro gives the struct a
+/// `register` function naming it by its module path and name, so this is
+/// only needed for hand-written payload types.
+///
+/// Payloads are decoded by the name stored with them, so a process reading
+/// edges must register their types first, or it reads them as undecoded
+/// bytes until it does. Panics when another type is registered under `name`.
+pub fn register<T>(name: &str)
+where
+    T: AnyData + Sync + Serialize + DeserializeOwned,
+{
+    fn encode<T: AnyData + Sync + Serialize>(value: &dyn AnyData) -> Option<Vec<u8>> {
+        let value = value.downcast_ref::<T>().ok()?;
+        bincode::serialize(value).ok()
+    }
+    fn decode<T: AnyData + Sync + DeserializeOwned>(bytes: &[u8]) -> Option<Arc<dyn AnyData>> {
+        let value: T = bincode::deserialize(bytes).ok()?;
+        Some(Arc::new(value))
+    }
+    fn to_value<T: AnyData + Sync + Serialize>(value: &dyn AnyData) -> Option<Value> {
+        let value = value.downcast_ref::<T>().ok()?;
+        Value::from_serialize(value).ok()
+    }
+
+    let type_id = TypeId::of::<T>();
+    let registered = registry().read().unwrap().names.get(&type_id).cloned();
+    if registered.as_deref() == Some(name) {
+        return;
+    }
+
+    let mut registry = registry().write().unwrap();
+    if registry
+        .types
+        .get(name)
+        .is_some_and(|data_type| data_type.type_id != type_id)
+    {
+        // Released first, so the registry stays usable by other threads.
+        drop(registry);
+        panic!("Another data type is already registered as {}", name);
+    }
+    registry.names.insert(type_id, name.to_string());
+    registry.types.insert(
+        name.to_string(),
+        DataType {
+            type_id,
+            encode: encode::<T>,
+            decode: decode::<T>,
+            to_value: to_value::<T>,
+        },
+    );
+}
+
+/// Payload read from storage whose type wasn't registered yet. It keeps the
+/// encoded bytes so it can still be written back or decoded later.
+#[derive(Debug, Clone, PartialEq)]
+struct RawData {
+    name: String,
+    bytes: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+enum DataRepr {
+    None,
+    Some(String, Vec<u8>),
+}
+
+#[derive(Clone, Default)]
 pub enum Data {
-    #[serde(skip_serializing, skip_deserializing)]
     Some(Arc<dyn AnyData>),
+    #[default]
     None,
 }
 impl Data {
@@ -43,6 +137,46 @@ impl Data {
             }),
         }
     }
+    /// Same as `get`, but also decodes payloads that were read from storage
+    /// before `T` was registered.
+    pub fn get_owned<T>(&self) -> Result<T, downcast::TypeMismatch>
+    where
+        T: AnyData + Sync + Clone + DeserializeOwned,
+    {
+        if let Ok(value) = self.get::<T>() {
+            return Ok(value.clone());
+        }
+
+        let mismatch = downcast::TypeMismatch {
+            expected: std::any::type_name::<T>(),
+            found: "RawData",
+        };
+        let raw = self.get::<RawData>().map_err(|_| mismatch)?;
+        let registry = registry().read().unwrap();
+        match registry.names.get(&TypeId::of::<T>()) {
+            Some(name) if name == &raw.name => {
+                bincode::deserialize(&raw.bytes).map_err(|_| mismatch)
+            }
+            _ => Err(mismatch),
+        }
+    }
+    /// Payload as a `Value`, `Value::Null` when there's none. `None` when
+    /// its type isn't registered.
+    pub fn to_value(&self) -> Option<Value> {
+        let value = match self {
+            Self::Some(value) => value,
+            Self::None => return Some(Value::Null),
+        };
+
+        let registry = registry().read().unwrap();
+        if let Ok(raw) = value.downcast_ref::<RawData>() {
+            let data_type = registry.types.get(&raw.name)?;
+            let decoded = (data_type.decode)(&raw.bytes)?;
+            return (data_type.to_value)(decoded.as_ref());
+        }
+        let name = registry.names.get(&value.as_any().type_id())?;
+        (registry.types[name].to_value)(value.as_ref())
+    }
     pub fn get_mut<T: AnyData + Sync>(&mut self) -> Result<&mut T, downcast::TypeMismatch> {
         match self {
             Self::Some(value) => Arc::get_mut(value).unwrap().downcast_mut::<T>(),
@@ -53,6 +187,46 @@ impl Data {
         }
     }
 }
+impl Serialize for Data {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let value = match self {
+            Self::Some(value) => value,
+            Self::None => return DataRepr::None.serialize(serializer),
+        };
+        if let Ok(raw) = value.downcast_ref::<RawData>() {
+            return DataRepr::Some(raw.name.clone(), raw.bytes.clone()).serialize(serializer);
+        }
+
+        let registry = registry().read().unwrap();
+        let type_id = value.as_any().type_id();
+        let (name, bytes) = registry
+            .names
+            .get(&type_id)
+            .and_then(|name| {
+                let bytes = (registry.types[name].encode)(value.as_ref())?;
+                Some((name.clone(), bytes))
+            })
+            .ok_or_else(|| S::Error::custom(format!("Unregistered data type: {:?}", value)))?;
+        DataRepr::Some(name, bytes).serialize(serializer)
+    }
+}
+impl<'de> Deserialize<'de> for Data {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (name, bytes) = match DataRepr::deserialize(deserializer)? {
+            DataRepr::Some(name, bytes) => (name, bytes),
+            DataRepr::None => return Ok(Self::None),
+        };
+
+        let registry = registry().read().unwrap();
+        let value = match registry.types.get(&name) {
+            Some(data_type) => (data_type.decode)(&bytes).ok_or_else(|| {
+                serde::de::Error::custom(format!("Failed to decode data type: {}", name))
+            })?,
+            None => Arc::new(RawData { name, bytes }),
+        };
+        Ok(Self::Some(value))
+    }
+}
 impl Eq for Data {}
 impl PartialEq for Data {
     fn eq(&self, other: &Self) -> bool {
@@ -74,8 +248,3 @@ impl fmt::Debug for Data {
         }
     }
 }
-impl Default for Data {
-    fn default() -> Self {
-        Self::None
-    }
-}
@@ -0,0 +1,146 @@
+use crate::value::Value;
+use serde::{Deserialize, Serialize};
+
+/// Mean earth radius, in meters.
+const EARTH_RADIUS: f64 = 6_371_008.8;
+
+/// Location in degrees. Indexed with `#[index(geo)]`, which also accepts
+/// a `(lat, lon)` tuple property.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct GeoPoint {
+    pub lat: f64,
+    pub lon: f64,
+}
+impl GeoPoint {
+    pub fn new(lat: f64, lon: f64) -> Self {
+        Self { lat, lon }
+    }
+    /// Reads a `GeoPoint` or a `(lat, lon)` tuple property.
+    pub fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Map(_) => Some(Self::new(
+                value.get("lat")?.as_f64()?,
+                value.get("lon")?.as_f64()?,
+            )),
+            Value::List(items) => match items.as_slice() {
+                [lat, lon] => Some(Self::new(lat.as_f64()?, lon.as_f64()?)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+    /// Great-circle distance in meters.
+    pub fn distance(&self, other: &GeoPoint) -> f64 {
+        let (lat1, lat2) = (self.lat.to_radians(), other.lat.to_radians());
+        let dlat = lat2 - lat1;
+        let dlon = (other.lon - self.lon).to_radians();
+        let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS * a.sqrt().min(1.0).asin()
+    }
+}
+
+/// Area matched by the geo query operations. A box whose west longitude is
+/// greater than its east one crosses the antimeridian.
+#[derive(Debug, Clone, Copy)]
+pub enum GeoArea {
+    Radius {
+        center: GeoPoint,
+        meters: f64,
+    },
+    BBox {
+        south_west: GeoPoint,
+        north_east: GeoPoint,
+    },
+}
+impl GeoArea {
+    pub fn contains(&self, point: &GeoPoint) -> bool {
+        match self {
+            Self::Radius { center, meters } => center.distance(point) <= *meters,
+            Self::BBox {
+                south_west,
+                north_east,
+            } => {
+                let lat = south_west.lat <= point.lat && point.lat <= north_east.lat;
+                let lon = if south_west.lon <= north_east.lon {
+                    south_west.lon <= point.lon && point.lon <= north_east.lon
+                } else {
+                    south_west.lon <= point.lon || point.lon <= north_east.lon
+                };
+                lat && lon
+            }
+        }
+    }
+    /// Boxes covering the area, none of them crossing the antimeridian, as
+    /// `(south_west, north_east)` corners.
+    pub fn bounds(&self) -> Vec<(GeoPoint, GeoPoint)> {
+        match *self {
+            Self::BBox {
+                south_west,
+                north_east,
+            } => split_antimeridian(south_west, north_east),
+            Self::Radius { center, meters } => {
+                let angle = meters / EARTH_RADIUS;
+                let south = (center.lat - angle.to_degrees()).max(-90.0);
+                let north = (center.lat + angle.to_degrees()).min(90.0);
+                let lon_angle = (angle.sin() / center.lat.to_radians().cos()).asin();
+                if south <= -90.0 || north >= 90.0 || lon_angle.is_nan() {
+                    return vec![(GeoPoint::new(south, -180.0), GeoPoint::new(north, 180.0))];
+                }
+
+                let west = wrap_lon(center.lon - lon_angle.to_degrees());
+                let east = wrap_lon(center.lon + lon_angle.to_degrees());
+                split_antimeridian(GeoPoint::new(south, west), GeoPoint::new(north, east))
+            }
+        }
+    }
+}
+impl PartialEq for GeoArea {
+    fn eq(&self, other: &Self) -> bool {
+        let bits = |point: &GeoPoint| (point.lat.to_bits(), point.lon.to_bits());
+        match (self, other) {
+            (
+                Self::Radius {
+                    center: a,
+                    meters: m,
+                },
+                Self::Radius {
+                    center: b,
+                    meters: n,
+                },
+            ) => bits(a) == bits(b) && m.to_bits() == n.to_bits(),
+            (
+                Self::BBox {
+                    south_west: a,
+                    north_east: b,
+                },
+                Self::BBox {
+                    south_west: c,
+                    north_east: d,
+                },
+            ) => bits(a) == bits(c) && bits(b) == bits(d),
+            _ => false,
+        }
+    }
+}
+impl Eq for GeoArea {}
+
+fn wrap_lon(lon: f64) -> f64 {
+    if lon < -180.0 {
+        lon + 360.0
+    } else if lon > 180.0 {
+        lon - 360.0
+    } else {
+        lon
+    }
+}
+
+fn split_antimeridian(south_west: GeoPoint, north_east: GeoPoint) -> Vec<(GeoPoint, GeoPoint)> {
+    if south_west.lon <= north_east.lon {
+        vec![(south_west, north_east)]
+    } else {
+        vec![
+            (south_west, GeoPoint::new(north_east.lat, 180.0)),
+            (GeoPoint::new(south_west.lat, -180.0), north_east),
+        ]
+    }
+}
@@ -39,9 +39,9 @@ macro_rules! id_struct {
                 Self(id)
             }
         }
-        impl Into<u64> for $name {
-            fn into(self) -> u64 {
-                self.0
+        impl From<$name> for u64 {
+            fn from(id: $name) -> Self {
+                id.0
             }
         }
         impl $name {
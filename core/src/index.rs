@@ -0,0 +1,103 @@
+use serde::{Deserialize, Serialize};
+
+/// Node property kept in a secondary index, as declared with `#[index]` on
+/// a `#[schema(Node)]` field. `#[index(unique)]` rejects two nodes of the
+/// same entity sharing a value, `#[index(range)]` keeps the entries ordered
+/// by value for range scans and sorting, `#[index(fulltext)]` keeps the
+/// words of a string property for ranked search, `#[index(vector(l2))]`
+/// keeps a `Vec<f32>` property in a graph for nearest neighbor search, and
+/// `#[index(geo)]` keeps the cell of a location for area queries.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Index {
+    pub field: String,
+    pub unique: bool,
+    pub range: bool,
+    pub fulltext: bool,
+    pub vector: Option<VectorMetric>,
+    pub geo: bool,
+}
+impl Index {
+    pub fn new(field: &str) -> Self {
+        Self {
+            field: field.to_string(),
+            unique: false,
+            range: false,
+            fulltext: false,
+            vector: None,
+            geo: false,
+        }
+    }
+    pub fn unique(mut self) -> Self {
+        self.unique = true;
+        self
+    }
+    pub fn range(mut self) -> Self {
+        self.range = true;
+        self
+    }
+    pub fn fulltext(mut self) -> Self {
+        self.fulltext = true;
+        self
+    }
+    pub fn vector(mut self, metric: VectorMetric) -> Self {
+        self.vector = Some(metric);
+        self
+    }
+    pub fn geo(mut self) -> Self {
+        self.geo = true;
+        self
+    }
+    /// Whether `by_index` on the field is served by the index. Full text,
+    /// vector and geo indexes only serve it when also unique or range.
+    pub fn is_equality(&self) -> bool {
+        let special = self.fulltext || self.vector.is_some() || self.geo;
+        !special || self.unique || self.range
+    }
+}
+
+/// Distance used by a vector index, `#[index(vector)]` defaulting to
+/// `Cosine`. Smaller is closer for all of them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VectorMetric {
+    #[default]
+    Cosine,
+    L2,
+    Dot,
+}
+impl VectorMetric {
+    /// Distance between two vectors of the same dimension: one minus the
+    /// cosine similarity, the squared euclidean distance, or the negated dot
+    /// product.
+    pub fn distance(&self, a: &[f32], b: &[f32]) -> f32 {
+        let dot = || a.iter().zip(b).map(|(x, y)| x * y).sum::<f32>();
+        match self {
+            Self::Cosine => {
+                let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+                let norms = norm(a) * norm(b);
+                if norms == 0.0 {
+                    1.0
+                } else {
+                    1.0 - dot() / norms
+                }
+            }
+            Self::L2 => a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum(),
+            Self::Dot => -dot(),
+        }
+    }
+}
+
+/// Index over several properties of a node, as declared with
+/// `#[index(country, city)]` on a `#[schema(Node)]` struct. Entries are
+/// ordered by each field in turn, so a lookup can match any leading subset
+/// of the fields.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompositeIndex {
+    pub fields: Vec<String>,
+}
+impl CompositeIndex {
+    pub fn new(fields: &[&str]) -> Self {
+        Self {
+            fields: fields.iter().map(|field| field.to_string()).collect(),
+        }
+    }
+}
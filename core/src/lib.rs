@@ -1,4 +1,7 @@
 pub mod data;
+pub mod geo;
 pub mod id;
+pub mod index;
 pub mod types;
 pub mod utils;
+pub mod value;
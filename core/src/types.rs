@@ -1,5 +1,8 @@
 pub use crate::data::Data;
+pub use crate::geo::{GeoArea, GeoPoint};
 pub use crate::id::{EdgeID, NodeID};
+pub use crate::index::{CompositeIndex, Index, VectorMetric};
+pub use crate::value::Value;
 pub use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::error::Error;
@@ -2,11 +2,11 @@ use proc_macro::TokenStream;
 use quote::quote;
 use syn::{
     parse::{ParseStream, Parser, Result},
-    parse_macro_input, Ident, ItemStruct,
+    parse_macro_input, Attribute, Ident, ItemStruct, Meta, MetaList, NestedMeta,
 };
 
 fn parse_idents(input: ParseStream) -> Result<Ident> {
-    Ok(input.parse()?)
+    input.parse()
 }
 
 fn str_to_path(input: &str) -> syn::Result<syn::Path> {
@@ -30,7 +30,160 @@ pub fn schema(args: TokenStream, input: TokenStream) -> TokenStream {
     }
 }
 
+struct IndexAttr {
+    field: String,
+    unique: bool,
+    range: bool,
+    fulltext: bool,
+    vector: Option<&'static str>,
+    geo: bool,
+}
+
+/// Parses the metric of a `vector(...)` index option.
+fn parse_vector_metric(list: &MetaList) -> syn::Result<&'static str> {
+    let metric = match list.nested.iter().collect::<Vec<_>>().as_slice() {
+        [NestedMeta::Meta(Meta::Path(path))] if path.is_ident("cosine") => Some("Cosine"),
+        [NestedMeta::Meta(Meta::Path(path))] if path.is_ident("l2") => Some("L2"),
+        [NestedMeta::Meta(Meta::Path(path))] if path.is_ident("dot") => Some("Dot"),
+        _ => None,
+    };
+    metric.ok_or_else(|| {
+        syn::Error::new_spanned(
+            list,
+            "Expected `vector(cosine)`, `vector(l2)` or `vector(dot)`",
+        )
+    })
+}
+
+/// Parses the options of an `#[index]`/`#[index(unique, range, fulltext)]`
+/// attribute.
+fn parse_index_attr(attr: &Attribute, index: &mut IndexAttr) -> syn::Result<()> {
+    let nested = match attr.parse_meta()? {
+        Meta::Path(_) => return Ok(()),
+        Meta::List(list) => list.nested,
+        meta => {
+            return Err(syn::Error::new_spanned(
+                meta,
+                "Expected `#[index]` or `#[index(unique, range, fulltext)]`",
+            ))
+        }
+    };
+
+    for option in nested {
+        match &option {
+            NestedMeta::Meta(Meta::Path(path)) if path.is_ident("unique") => index.unique = true,
+            NestedMeta::Meta(Meta::Path(path)) if path.is_ident("range") => index.range = true,
+            NestedMeta::Meta(Meta::Path(path)) if path.is_ident("fulltext") => {
+                index.fulltext = true
+            }
+            NestedMeta::Meta(Meta::Path(path)) if path.is_ident("geo") => index.geo = true,
+            NestedMeta::Meta(Meta::Path(path)) if path.is_ident("vector") => {
+                index.vector = Some("Cosine")
+            }
+            NestedMeta::Meta(Meta::List(list)) if list.path.is_ident("vector") => {
+                index.vector = Some(parse_vector_metric(list)?)
+            }
+            _ => return Err(syn::Error::new_spanned(
+                option,
+                "Unknown index option, expected `unique`, `range`, `fulltext`, `vector` or `geo`",
+            )),
+        }
+    }
+    Ok(())
+}
+
+/// Takes the `#[index]` attributes off the struct fields, which aren't real
+/// attributes, and returns the indexes they declare.
+fn take_indexes(item_struct: &mut ItemStruct) -> syn::Result<Vec<IndexAttr>> {
+    let mut indexes = Vec::new();
+    for field in item_struct.fields.iter_mut() {
+        let (attrs, others): (Vec<_>, Vec<_>) = field
+            .attrs
+            .drain(..)
+            .partition(|attr| attr.path.is_ident("index"));
+        field.attrs = others;
+        let Some(ident) = &field.ident else {
+            continue;
+        };
+        if attrs.is_empty() {
+            continue;
+        }
+
+        let mut index = IndexAttr {
+            field: ident.to_string(),
+            unique: false,
+            range: false,
+            fulltext: false,
+            vector: None,
+            geo: false,
+        };
+        for attr in &attrs {
+            parse_index_attr(attr, &mut index)?;
+        }
+        indexes.push(index);
+    }
+    Ok(indexes)
+}
+
+/// Takes the `#[index(a, b)]` attributes off the struct and returns the
+/// fields of the composite indexes they declare. Only the attributes placed
+/// after `#[schema(Node)]` reach the macro.
+fn take_composite_indexes(item_struct: &mut ItemStruct) -> syn::Result<Vec<Vec<String>>> {
+    let (attrs, others): (Vec<_>, Vec<_>) = item_struct
+        .attrs
+        .drain(..)
+        .partition(|attr| attr.path.is_ident("index"));
+    item_struct.attrs = others;
+
+    let mut indexes = Vec::new();
+    for attr in &attrs {
+        let nested = match attr.parse_meta()? {
+            Meta::List(list) if list.nested.len() >= 2 => list.nested,
+            meta => {
+                return Err(syn::Error::new_spanned(
+                    meta,
+                    "Expected `#[index(field, field, ...)]` with at least two fields",
+                ))
+            }
+        };
+
+        let mut fields = Vec::new();
+        for option in nested {
+            let ident = match &option {
+                NestedMeta::Meta(Meta::Path(path)) => path.get_ident(),
+                _ => None,
+            };
+            let known = ident.filter(|ident| {
+                item_struct
+                    .fields
+                    .iter()
+                    .any(|field| field.ident.as_ref() == Some(*ident))
+            });
+            match known {
+                Some(ident) => fields.push(ident.to_string()),
+                None => {
+                    return Err(syn::Error::new_spanned(
+                        option,
+                        "Expected a field of the struct",
+                    ))
+                }
+            }
+        }
+        indexes.push(fields);
+    }
+    Ok(indexes)
+}
+
 fn impl_schema_for_node(item_struct: &ItemStruct) -> TokenStream {
+    let mut item_struct = item_struct.clone();
+    let indexes = match take_indexes(&mut item_struct) {
+        Ok(indexes) => indexes,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let composite_indexes = match take_composite_indexes(&mut item_struct) {
+        Ok(indexes) => indexes,
+        Err(e) => return e.to_compile_error().into(),
+    };
     let entity_name = &item_struct.ident;
     let id_field_present = item_struct.fields.iter().any(|field| {
         field
@@ -42,7 +195,7 @@ fn impl_schema_for_node(item_struct: &ItemStruct) -> TokenStream {
 
     if !id_field_present {
         return syn::Error::new_spanned(
-            &item_struct,
+            item_struct,
             "The `id: NodeID` field is missing in the struct",
         )
         .to_compile_error()
@@ -52,8 +205,26 @@ fn impl_schema_for_node(item_struct: &ItemStruct) -> TokenStream {
     let types = str_to_path("arkycore::types").unwrap();
     let node_id = str_to_path("arkycore::types::NodeID").unwrap();
     let format_entity = str_to_path("arkycore::utils::format_entity").unwrap();
+    let index = str_to_path("arkycore::types::Index").unwrap();
+    let indexes = indexes.iter().map(|attr| {
+        let field = &attr.field;
+        let unique = attr.unique.then(|| quote! { .unique() });
+        let range = attr.range.then(|| quote! { .range() });
+        let fulltext = attr.fulltext.then(|| quote! { .fulltext() });
+        let vector = attr.vector.map(|metric| {
+            let metric =
+                str_to_path(&format!("arkycore::types::VectorMetric::{}", metric)).unwrap();
+            quote! { .vector(#metric) }
+        });
+        let geo = attr.geo.then(|| quote! { .geo() });
+        quote! { #index::new(#field) #unique #range #fulltext #vector #geo }
+    });
+    let composite_index = str_to_path("arkycore::types::CompositeIndex").unwrap();
+    let composite_indexes = composite_indexes.iter().map(|fields| {
+        quote! { #composite_index::new(&[#(#fields),*]) }
+    });
 
-    return quote! {
+    quote! {
         #[derive(Debug, Clone, PartialEq, #types::Serialize, #types::Deserialize)]
         #[allow(dead_code)]
         #item_struct
@@ -62,12 +233,21 @@ fn impl_schema_for_node(item_struct: &ItemStruct) -> TokenStream {
             fn key(&self) -> #node_id {
                 self.id
             }
-            fn entity(&self) -> String {
+            fn entity_name() -> String {
                 #format_entity(stringify!(#entity_name))
             }
+            fn entity(&self) -> String {
+                Self::entity_name()
+            }
+            fn indexes() -> Vec<#index> {
+                vec![#(#indexes),*]
+            }
+            fn composite_indexes() -> Vec<#composite_index> {
+                vec![#(#composite_indexes),*]
+            }
         }
     }
-    .into();
+    .into()
 }
 
 fn impl_schema_for_edge_data(item_struct: &ItemStruct) -> TokenStream {
@@ -75,19 +255,29 @@ fn impl_schema_for_edge_data(item_struct: &ItemStruct) -> TokenStream {
     let types = str_to_path("arkycore::types").unwrap();
     let edge_data = str_to_path("arky::edge::Data").unwrap();
     let edge_error = str_to_path("arky::edge::EdgeError").unwrap();
+    let register = str_to_path("arkycore::data::register").unwrap();
 
-    return quote! {
+    quote! {
         #[derive(Debug, Clone, PartialEq, #types::Serialize, #types::Deserialize)]
         #[allow(dead_code)]
         #item_struct
 
         impl #entity_name {
+            /// Registers the payload type under its module path and name, so
+            /// edges holding it can be read back. Building or getting a
+            /// payload registers it, but a process only reading edges has to
+            /// call this first.
+            pub fn register() {
+                #register::<#entity_name>(concat!(module_path!(), "::", stringify!(#entity_name)))
+            }
             fn new(data: #entity_name) -> #edge_data {
+                Self::register();
                 #edge_data::new::<#entity_name>(data)
             }
             fn get(data: &#edge_data) -> Result<#entity_name, #edge_error> {
-                match data.get::<#entity_name>() {
-                    Ok(data) => Ok(data.clone()),
+                Self::register();
+                match data.get_owned::<#entity_name>() {
+                    Ok(data) => Ok(data),
                     _ => Err(#edge_error::EdgeDataMismatch {
                         data_type: stringify!(#entity_name).to_string()
                     }),
@@ -95,5 +285,5 @@ fn impl_schema_for_edge_data(item_struct: &ItemStruct) -> TokenStream {
             }
         }
     }
-    .into();
+    .into()
 }
@@ -3,7 +3,11 @@ use arkymacros_schema::schema;
 
 trait Node {
     fn key(&self) -> NodeID;
+    fn entity_name() -> String;
     fn entity(&self) -> String;
+    fn indexes() -> Vec<Index>;
+    fn composite_indexes() -> Vec<CompositeIndex>;
+    #[allow(clippy::new_ret_no_self)]
     fn new<T: Node>(data: T) -> T {
         data
     }
@@ -12,10 +16,35 @@ trait Node {
 #[schema(Node)]
 struct Person {
     id: NodeID,
+    #[index(fulltext)]
     name: String,
+    #[index(range)]
     age: u8,
 }
 
+#[schema(Node)]
+struct Profile {
+    id: NodeID,
+    #[index(vector)]
+    embedding: Vec<f32>,
+    #[index(vector(l2))]
+    position: Vec<f32>,
+    #[index(geo)]
+    home: GeoPoint,
+}
+
+#[schema(Node)]
+#[index(email, balance)]
+#[index(number, email, balance)]
+struct Account {
+    id: NodeID,
+    #[index(unique)]
+    email: String,
+    #[index(unique, range)]
+    number: u64,
+    balance: u64,
+}
+
 #[test]
 fn test_global_id() {
     let first_id = NodeID::new();
@@ -50,3 +79,39 @@ fn test_schema_trait_impl() {
     assert_eq!(person.key(), id);
     assert_eq!(person.age, 30);
 }
+
+#[test]
+fn test_schema_indexes() {
+    assert_eq!(
+        Person::indexes(),
+        vec![Index::new("name").fulltext(), Index::new("age").range()]
+    );
+    assert_eq!(
+        Profile::indexes(),
+        vec![
+            Index::new("embedding").vector(VectorMetric::Cosine),
+            Index::new("position").vector(VectorMetric::L2),
+            Index::new("home").geo(),
+        ]
+    );
+    assert_eq!(
+        Account::indexes(),
+        vec![
+            Index::new("email").unique(),
+            Index::new("number").unique().range(),
+        ]
+    );
+}
+
+#[test]
+fn test_schema_composite_indexes() {
+    assert!(Person::composite_indexes().is_empty());
+    assert!(Profile::composite_indexes().is_empty());
+    assert_eq!(
+        Account::composite_indexes(),
+        vec![
+            CompositeIndex::new(&["email", "balance"]),
+            CompositeIndex::new(&["number", "email", "balance"]),
+        ]
+    );
+}